@@ -1,20 +1,36 @@
 //! Responsible for parsing strings into meaningful `Muropeptide` structures
+mod library;
+mod oligomers;
 mod parser;
+mod table;
+mod validation;
 
-use std::fmt::{self, Display, Formatter};
+use std::{
+    cmp::min,
+    fmt::{self, Display, Formatter},
+    iter::zip,
+};
 
+use ahash::HashSet;
 use itertools::Itertools;
-use miette::Diagnostic;
+use miette::{Diagnostic, SourceSpan};
 use nom_miette::{final_parser, LabeledError};
-use parser::{muropeptide, MuropeptideErrorKind};
+use parser::{muropeptide, ConstructionError, MuropeptideErrorKind};
 // FIXME: Blocks need separating and reordering!
 use polychem::{
-    errors::PolychemError, AverageMass, BondId, Charged, GroupState, Massive, ModificationInfo,
-    MonoisotopicMass, Polymer, Polymerizer, ResidueId,
+    errors::PolychemError, parsers::errors::PolychemErrorKind, AnyMod, AverageMass, BondInfo,
+    Charged, Formulaic, GroupState, LabelledMasses, Massive, ModificationInfo, MolecularFormula,
+    MonoisotopicMass, NamedMod, OffsetKind, OffsetMod, Polymer, Polymerizer, ResidueGroup,
+    ResidueId,
 };
-use smithereens::Dissociable;
+use smithereens::{Dissociable, FragmentLabel};
 use thiserror::Error;
 
+pub use library::{LibraryEntry, LibraryRules, Linkage, Oligomerization};
+pub use oligomers::{dimers, oligomers, trimers};
+pub use table::{export_library, import_library, ImportedLibrary, TableError, TableFormat};
+pub use validation::{ValidationError, ValidationRules};
+
 const AUTO_MODS: [&str; 1] = ["Red"];
 
 #[derive(Debug)]
@@ -22,6 +38,7 @@ pub struct Muropeptide<'a, 'p> {
     polymer: Polymer<'a, 'p>,
     monomers: Vec<Monomer>,
     connections: Vec<Connection>,
+    label: Option<FragmentLabel>,
 }
 
 #[derive(Debug, Clone)]
@@ -30,15 +47,21 @@ struct Monomer {
     peptide: Vec<AminoAcid>,
 }
 
-type Monosaccharide = ResidueId;
+// NOTE: Each `span` covers a residue and its modifications in the original structure — fragments keep the spans of
+// the muropeptide they were cut from
+#[derive(Copy, Clone, Debug)]
+struct Monosaccharide {
+    residue: ResidueId,
+    span: SourceSpan,
+}
 
 #[derive(Debug, Clone)]
 struct AminoAcid {
     residue: UnbranchedAminoAcid,
+    span: SourceSpan,
     lateral_chain: Option<LateralChain>,
 }
 
-// FIXME: This should store the `BondId` of each connection, so we know when it's removed!
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum Connection {
     GlycosidicBond,
@@ -46,15 +69,27 @@ enum Connection {
     Both(CrosslinkDescriptors),
 }
 
+// NOTE: A connection that (at least partly) survived fragmentation. Each crosslink is recorded by the stem residues
+// it joins — their `(stem, end)` pairs, where `end` is the last residue of any lateral chain — since the positions of
+// those residues can change once other residues are lost
+struct SurvivingConnection {
+    glycosidic: bool,
+    crosslinks: Vec<(CrosslinkDescriptor, StemResidue, StemResidue)>,
+}
+
+type StemResidue = (ResidueId, ResidueId);
+
 #[derive(Debug, Clone)]
 struct LateralChain {
     direction: PeptideDirection,
     peptide: Vec<UnbranchedAminoAcid>,
+    // NOTE: Covers the residues of the chain, but not the surrounding brackets or its direction
+    span: SourceSpan,
 }
 
 type CrosslinkDescriptors = Vec<CrosslinkDescriptor>;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 enum CrosslinkDescriptor {
     DonorAcceptor(Position, Position),
     AcceptorDonor(Position, Position),
@@ -86,6 +121,38 @@ pub enum Error {
 // FIXME: Maybe `Box` this error?
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    // NOTE: Lookup errors mean that a residue, modification, bond, or element is missing from a database (or that its
+    // name was mistyped), whereas every other error means that the structure itself can't be built
+    pub(crate) fn is_lookup_error(&self) -> bool {
+        let is_lookup = |error: &PolychemError| {
+            matches!(
+                error,
+                PolychemError::ResidueLookup { .. }
+                    | PolychemError::ModificationLookup { .. }
+                    | PolychemError::BondLookup { .. }
+            )
+        };
+        match self {
+            Self::ParseError(error) => {
+                error.kinds().into_iter().any(|kind| match kind {
+                    MuropeptideErrorKind::ConstructionError(ConstructionError::PolychemError(
+                        error,
+                    ))
+                    | MuropeptideErrorKind::CompositionError(PolychemErrorKind::PolychemError(
+                        error,
+                    )) => is_lookup(error),
+                    MuropeptideErrorKind::CompositionError(PolychemErrorKind::LookupError(_)) => {
+                        true
+                    }
+                    _ => false,
+                })
+            }
+            Self::PolychemError(error) => is_lookup(error),
+        }
+    }
+}
+
 impl<'a, 'p> Muropeptide<'a, 'p> {
     // FIXME: Messy stuff here! Needs a look! Especially the error type!
     pub fn new(polymerizer: &Polymerizer<'a, 'p>, structure: impl AsRef<str>) -> Result<Self> {
@@ -99,6 +166,45 @@ impl<'a, 'p> Muropeptide<'a, 'p> {
 
         Ok(muropeptide)
     }
+
+    // NOTE: Only fragments produced by `Dissociable::fragment()` are labelled
+    #[must_use]
+    pub const fn label(&self) -> Option<&FragmentLabel> {
+        self.label.as_ref()
+    }
+
+    // NOTE: Only muropeptides built by a `Polymerizer` with a `LabellingScheme` have distinct light and heavy masses
+    #[must_use]
+    pub fn labelled_masses(&self) -> LabelledMasses {
+        self.polymer.labelled_masses()
+    }
+
+    // NOTE: Two muropeptides are equivalent if their polymers share the same `CanonicalForm` — the same residues,
+    // modified in the same way, and joined by the same bonds. That makes this check blind to how either was written:
+    // monomers can be listed in either direction (`gm-AEJA=gm-AEJ (4-3)` is equivalent to `gm-AEJ=gm-AEJA (3=4)`), and
+    // modifications or crosslink descriptors can be listed in any order
+    #[must_use]
+    pub fn is_equivalent(&self, other: &Self) -> bool {
+        self.polymer.canonical_form() == other.polymer.canonical_form()
+    }
+}
+
+// NOTE: Equivalent structures can be written in several ways — with modifications or crosslink descriptors listed in
+// a different order, with `=~` instead of `~=`, with `Am, Am` instead of `2xAm`, or with their monomers listed
+// back-to-front — but they all normalise to the same canonical string. Of every order the monomers could be written in
+// (each rotation of a circular muropeptide, forwards and backwards), the one giving the smallest string is chosen
+pub fn normalize(polymerizer: &Polymerizer, structure: impl AsRef<str>) -> Result<String> {
+    let muropeptide = Muropeptide::new(polymerizer, structure)?;
+    let canonical_form = muropeptide.polymer.canonical_form();
+
+    // NOTE: Glycosidic bonds can only be written in one direction, so reordering the monomers of a muropeptide with
+    // glycosidic bonds can describe a different structure (or no valid structure at all). Only reorderings that parse
+    // back into the same structure are kept
+    let spellings = muropeptide.reorderings().into_iter().filter(|spelling| {
+        Muropeptide::new(polymerizer, spelling)
+            .is_ok_and(|reordered| reordered.polymer.canonical_form() == canonical_form)
+    });
+    Ok(spellings.fold(muropeptide.to_string(), min))
 }
 
 impl Massive for Muropeptide<'_, '_> {
@@ -117,6 +223,12 @@ impl Charged for Muropeptide<'_, '_> {
     }
 }
 
+impl<'a> Formulaic<'a> for Muropeptide<'a, '_> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        self.polymer.formula()
+    }
+}
+
 impl<'s, 'a: 's, 'p: 's> Dissociable<'s, 'a, 'p> for Muropeptide<'a, 'p> {
     fn polymer(&self) -> &Polymer<'a, 'p> {
         &self.polymer
@@ -127,10 +239,10 @@ impl<'s, 'a: 's, 'p: 's> Dissociable<'s, 'a, 'p> for Muropeptide<'a, 'p> {
         &self,
         fragmented_polymer: Polymer<'a, 'p>,
         lost_residues: Vec<ResidueId>,
-        _broken_bonds: Vec<BondId>,
+        label: FragmentLabel,
     ) -> Self {
         // FIXME: Obviously incomplete!
-        let monomers = self
+        let monomers: Vec<_> = self
             .monomers
             .iter()
             .map(|Monomer { glycan, peptide }| {
@@ -138,7 +250,7 @@ impl<'s, 'a: 's, 'p: 's> Dissociable<'s, 'a, 'p> for Muropeptide<'a, 'p> {
                 let glycan: Vec<_> = glycan
                     .iter()
                     .copied()
-                    .filter(|id| !lost_residues.contains(id))
+                    .filter(|monosaccharide| !lost_residues.contains(&monosaccharide.residue))
                     .collect();
                 let mut lateral_peptides = Vec::new();
                 let peptide: Vec<_> = peptide
@@ -159,21 +271,23 @@ impl<'s, 'a: 's, 'p: 's> Dissociable<'s, 'a, 'p> for Muropeptide<'a, 'p> {
                                     Some(LateralChain {
                                         direction: chain.direction,
                                         peptide,
+                                        span: chain.span,
                                     })
                                 }
                             });
                             Some(AminoAcid {
                                 residue,
+                                span: aa.span,
                                 lateral_chain,
                             })
-                        } else if let Some(LateralChain { peptide, .. }) = &aa.lateral_chain {
+                        } else if let Some(LateralChain { peptide, span, .. }) = &aa.lateral_chain {
                             let peptide: Vec<_> = peptide
                                 .iter()
                                 .copied()
                                 .filter(|id| !lost_residues.contains(id))
                                 .collect();
                             if !peptide.is_empty() {
-                                lateral_peptides.push(peptide);
+                                lateral_peptides.push((peptide, *span));
                             }
                             None
                         } else {
@@ -184,10 +298,14 @@ impl<'s, 'a: 's, 'p: 's> Dissociable<'s, 'a, 'p> for Muropeptide<'a, 'p> {
                 if peptide.is_empty() && glycan.is_empty() && !lateral_peptides.is_empty() {
                     // FIXME: Probably not true eventually...
                     assert_eq!(lateral_peptides.len(), 1);
-                    let peptide = lateral_peptides[0]
+                    // NOTE: Residues promoted from a lateral chain don't have spans of their own, so they take the
+                    // span of the whole chain instead
+                    let (lateral_peptide, span) = &lateral_peptides[0];
+                    let peptide = lateral_peptide
                         .iter()
                         .map(|&residue| AminoAcid {
                             residue,
+                            span: *span,
                             lateral_chain: None,
                         })
                         .collect();
@@ -197,57 +315,207 @@ impl<'s, 'a: 's, 'p: 's> Dissociable<'s, 'a, 'p> for Muropeptide<'a, 'p> {
                 }
             })
             .collect();
-        let connections = self.connections.clone();
+        let surviving = self.surviving_connections(&fragmented_polymer);
+        let (monomers, connections) = reconnect(&monomers, &self.connections, &surviving);
         Self {
             polymer: fragmented_polymer,
             monomers,
             connections,
+            label: Some(label),
         }
     }
 }
 
-// FIXME: Oh god.
+// NOTE: Muropeptides are written in a canonical form that `Muropeptide::new()` can always parse back into the same
+// structure — modifications and crosslink descriptors are sorted, repeated modifications are merged, and any
+// `AUTO_MODS` are left out, since parsing will apply them again anyways
 impl Display for Muropeptide<'_, '_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let connections = self.connections.clone();
-        let mut cross_links = Vec::new();
+        let mut crosslinks = Vec::new();
 
-        for (i, left_monomer) in self.monomers.iter().enumerate() {
-            if left_monomer.glycan.is_empty() && left_monomer.peptide.is_empty() {
-                continue;
-            }
-            display_monomer(f, &self.polymer, left_monomer)?;
-            if let Some(right_monomer) = self.monomers.get(i + 1) {
-                if right_monomer.glycan.is_empty() && right_monomer.peptide.is_empty() {
-                    continue;
+        for (i, monomer) in self.monomers.iter().enumerate() {
+            display_monomer(f, &self.polymer, monomer)?;
+
+            // NOTE: Circular muropeptides have one more connection than they have gaps between monomers — that final
+            // connection links the last monomer back around to the first
+            match self.connections.get(i) {
+                Some(Connection::GlycosidicBond) => write!(f, "~")?,
+                Some(Connection::Crosslink(descriptors)) => {
+                    write!(f, "=")?;
+                    crosslinks.push(descriptors);
                 }
-                if let Some(connection) = connections.get(i) {
-                    match connection {
-                        Connection::GlycosidicBond => write!(f, "~")?,
-                        Connection::Crosslink(descriptors) => {
-                            write!(f, "=")?;
-                            assert_eq!(descriptors.len(), 1);
-                            cross_links.push(descriptors[0].to_string());
-                        }
-                        Connection::Both(descriptors) => {
-                            write!(f, "~=")?;
-                            assert_eq!(descriptors.len(), 1);
-                            cross_links.push(descriptors[0].to_string());
-                        }
-                    }
+                Some(Connection::Both(descriptors)) => {
+                    write!(f, "~=")?;
+                    crosslinks.push(descriptors);
                 }
+                None => (),
             }
         }
 
-        let cross_links = cross_links.join(", ");
-        if !cross_links.is_empty() {
-            write!(f, " ({cross_links})")?;
+        let modifications =
+            display_modifications(self.polymer.modification_refs().filter_map(|info| {
+                if let ModificationInfo::Unlocalized(modification) = info {
+                    Some((
+                        ModificationKind::from(modification.kind()),
+                        u32::from(modification.multiplier()),
+                    ))
+                } else {
+                    None
+                }
+            }));
+        if !modifications.is_empty() {
+            write!(f, " ({modifications})")?;
+        }
+
+        let crosslinks = crosslinks
+            .into_iter()
+            .map(|descriptors| descriptors.iter().sorted_unstable().join(" & "))
+            .join(", ");
+        if !crosslinks.is_empty() {
+            write!(f, " ({crosslinks})")?;
         }
 
         Ok(())
     }
 }
 
+impl Muropeptide<'_, '_> {
+    // NOTE: Checks which bonds of each connection are still present in a `fragment` of this muropeptide, finding the
+    // residues of each connection just like the parser does when it first builds them
+    fn surviving_connections(&self, fragment: &Polymer) -> Vec<Option<SurvivingConnection>> {
+        let bonds: HashSet<_> = fragment
+            .bond_refs()
+            .map(
+                |BondInfo(ResidueGroup(donor, _), _, ResidueGroup(acceptor, _))| {
+                    (*donor, *acceptor)
+                },
+            )
+            .collect();
+        let bonded = |donor: ResidueId, acceptor: ResidueId| bonds.contains(&(donor, acceptor));
+        let stem_residue = |peptide: &[AminoAcid], position: Position| {
+            let AminoAcid {
+                residue,
+                lateral_chain,
+                ..
+            } = peptide.get(usize::from(position.saturating_sub(1)))?;
+            let end = lateral_chain
+                .as_ref()
+                .and_then(|chain| chain.peptide.last())
+                .unwrap_or(residue);
+            Some((*residue, *end))
+        };
+
+        let mut last_glycan = None;
+        let mut last_peptide = None;
+        let monomer_pairs = self.monomers.iter().circular_tuple_windows();
+        zip(monomer_pairs, &self.connections)
+            .map(|((left, right), connection)| {
+                if !left.glycan.is_empty() {
+                    last_glycan = Some(&left.glycan);
+                }
+                if !left.peptide.is_empty() {
+                    last_peptide = Some(&left.peptide);
+                }
+
+                let descriptors: &[_] = match connection {
+                    Connection::GlycosidicBond => &[],
+                    Connection::Crosslink(descriptors) | Connection::Both(descriptors) => {
+                        descriptors
+                    }
+                };
+                let glycosidic = !matches!(connection, Connection::Crosslink(_))
+                    && last_glycan
+                        .and_then(|glycan| glycan.last())
+                        .zip(right.glycan.first())
+                        .is_some_and(|(donor, acceptor)| bonded(donor.residue, acceptor.residue));
+                let crosslinks: Vec<_> = descriptors
+                    .iter()
+                    .filter_map(|&descriptor| {
+                        let (l, r) = match descriptor {
+                            CrosslinkDescriptor::DonorAcceptor(l, r)
+                            | CrosslinkDescriptor::AcceptorDonor(l, r) => (l, r),
+                        };
+                        let left_residue = stem_residue(last_peptide?, l)?;
+                        let right_residue = stem_residue(&right.peptide, r)?;
+                        let ((donor, _), (_, acceptor)) = match descriptor {
+                            CrosslinkDescriptor::DonorAcceptor(..) => (left_residue, right_residue),
+                            CrosslinkDescriptor::AcceptorDonor(..) => (right_residue, left_residue),
+                        };
+                        bonded(donor, acceptor).then_some((descriptor, left_residue, right_residue))
+                    })
+                    .collect();
+
+                (glycosidic || !crosslinks.is_empty()).then_some(SurvivingConnection {
+                    glycosidic,
+                    crosslinks,
+                })
+            })
+            .collect()
+    }
+
+    // NOTE: Writes this muropeptide with its monomers in every other order that keeps bonded monomers next to each
+    // other, without checking that any of those orders can be parsed back into the same structure
+    fn reorderings(&self) -> Vec<String> {
+        let circular = self.connections.len() == self.monomers.len();
+        let rotations = if circular { self.monomers.len() } else { 1 };
+        let forwards = (self.monomers.clone(), self.connections.clone());
+        let backwards = (
+            self.monomers.iter().rev().cloned().collect(),
+            self.reversed_connections(),
+        );
+
+        (0..rotations)
+            .cartesian_product([forwards, backwards])
+            // NOTE: The first ordering is the one this muropeptide was already written in
+            .skip(1)
+            .map(|(rotation, (mut monomers, mut connections))| {
+                monomers.rotate_left(rotation);
+                connections.rotate_left(rotation);
+                Self {
+                    polymer: self.polymer.clone(),
+                    monomers,
+                    connections,
+                    label: None,
+                }
+                .to_string()
+            })
+            .collect()
+    }
+
+    // NOTE: When the monomers are written back-to-front, so are the connections between them, and the donor and
+    // acceptor of every crosslink descriptor swap sides. The final connection of a circular muropeptide (linking the
+    // last monomer back to the first) stays last
+    fn reversed_connections(&self) -> Vec<Connection> {
+        let reverse = |descriptors: &CrosslinkDescriptors| {
+            descriptors
+                .iter()
+                .map(|descriptor| match *descriptor {
+                    CrosslinkDescriptor::DonorAcceptor(l, r) => {
+                        CrosslinkDescriptor::AcceptorDonor(r, l)
+                    }
+                    CrosslinkDescriptor::AcceptorDonor(l, r) => {
+                        CrosslinkDescriptor::DonorAcceptor(r, l)
+                    }
+                })
+                .collect()
+        };
+        let mut connections: Vec<_> = self
+            .connections
+            .iter()
+            .rev()
+            .map(|connection| match connection {
+                Connection::GlycosidicBond => Connection::GlycosidicBond,
+                Connection::Crosslink(descriptors) => Connection::Crosslink(reverse(descriptors)),
+                Connection::Both(descriptors) => Connection::Both(reverse(descriptors)),
+            })
+            .collect();
+        if connections.len() == self.monomers.len() {
+            connections.rotate_left(1);
+        }
+        connections
+    }
+}
+
 impl Display for CrosslinkDescriptor {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -257,70 +525,166 @@ impl Display for CrosslinkDescriptor {
     }
 }
 
+impl Display for PeptideDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unspecified => Ok(()),
+            Self::CToN => write!(f, "<"),
+            Self::NToC => write!(f, ">"),
+        }
+    }
+}
+
+impl Monomer {
+    fn is_empty(&self) -> bool {
+        self.glycan.is_empty() && self.peptide.is_empty()
+    }
+}
+
+// NOTE: Fragments only keep the monomers that still have residues left, and a ring of monomers that's been broken is
+// opened at the broken connection. Monomers are otherwise kept in the same order, so the connection written before each
+// monomer is the one that joined it to the monomers before it — with crosslink descriptors renumbered to match the
+// residues left in each stem
+fn reconnect(
+    monomers: &[Monomer],
+    connections: &[Connection],
+    surviving: &[Option<SurvivingConnection>],
+) -> (Vec<Monomer>, Vec<Connection>) {
+    let count = monomers.len();
+    let circular = connections.len() == count;
+    let incoming = |i: usize| {
+        if i == 0 && !circular {
+            None
+        } else {
+            surviving.get((i + count - 1) % count)?.as_ref()
+        }
+    };
+
+    let mut order: Vec<_> = (0..count).filter(|&i| !monomers[i].is_empty()).collect();
+    let broken_at = circular
+        .then(|| order.iter().position(|&i| incoming(i).is_none()))
+        .flatten();
+    order.rotate_left(broken_at.unwrap_or(0));
+    let ring_intact = circular && broken_at.is_none() && !order.is_empty();
+
+    let mut last_peptide = None;
+    let mut new_connections = Vec::with_capacity(order.len());
+    for (j, &i) in order.iter().enumerate() {
+        let monomer = &monomers[i];
+        if j > 0 {
+            // FIXME: Fragments are connected, so every monomer after the first should still be joined to the ones
+            // before it! If that ever isn't true, the original connection is written, so monomers aren't run together
+            let connection = incoming(i)
+                .and_then(|connection| renumber(connection, last_peptide, &monomer.peptide))
+                .unwrap_or_else(|| connections[(i + count - 1) % count].clone());
+            new_connections.push(connection);
+        }
+        if !monomer.peptide.is_empty() {
+            last_peptide = Some(monomer.peptide.as_slice());
+        }
+    }
+    if ring_intact {
+        let first = order[0];
+        let connection = incoming(first)
+            .and_then(|connection| renumber(connection, last_peptide, &monomers[first].peptide))
+            .unwrap_or_else(|| connections[(first + count - 1) % count].clone());
+        new_connections.push(connection);
+    }
+
+    let monomers = order.into_iter().map(|i| monomers[i].clone()).collect();
+    (monomers, new_connections)
+}
+
+fn renumber(
+    connection: &SurvivingConnection,
+    left_peptide: Option<&[AminoAcid]>,
+    right_peptide: &[AminoAcid],
+) -> Option<Connection> {
+    // NOTE: If the stem residue of a lateral chain was lost, that chain has been promoted to the stem, so its end is
+    // looked for instead
+    let position = |peptide: &[AminoAcid], (stem, end): StemResidue| {
+        let index = peptide
+            .iter()
+            .position(|aa| aa.residue == stem)
+            .or_else(|| peptide.iter().position(|aa| aa.residue == end))?;
+        Position::try_from(index + 1).ok()
+    };
+    let descriptors: CrosslinkDescriptors = connection
+        .crosslinks
+        .iter()
+        .filter_map(|&(descriptor, left, right)| {
+            let l = position(left_peptide?, left)?;
+            let r = position(right_peptide, right)?;
+            Some(match descriptor {
+                CrosslinkDescriptor::DonorAcceptor(..) => CrosslinkDescriptor::DonorAcceptor(l, r),
+                CrosslinkDescriptor::AcceptorDonor(..) => CrosslinkDescriptor::AcceptorDonor(l, r),
+            })
+        })
+        .collect();
+
+    match (connection.glycosidic, descriptors.is_empty()) {
+        (true, true) => Some(Connection::GlycosidicBond),
+        (true, false) => Some(Connection::Both(descriptors)),
+        (false, false) => Some(Connection::Crosslink(descriptors)),
+        (false, true) => None,
+    }
+}
+
 // FIXME: Change all of these individual functions into a trait, then implement it for all of the sub-components. The
 // trait could be called something like `DisplayMoiety` and could be a bit like the `ValidateInto` trait?
 fn display_monomer(f: &mut Formatter, polymer: &Polymer, monomer: &Monomer) -> fmt::Result {
     let Monomer { glycan, peptide } = monomer;
-    for &monosaccharide in glycan {
-        display_residue(f, polymer, monosaccharide)?;
+    for monosaccharide in glycan {
+        display_residue(f, polymer, monosaccharide.residue)?;
     }
     if !glycan.is_empty() && !peptide.is_empty() {
         write!(f, "-")?;
     }
     for amino_acid in peptide {
         display_residue(f, polymer, amino_acid.residue)?;
-        if let Some(chain) = &amino_acid.lateral_chain {
-            write!(f, "[")?;
-            for &residue in &chain.peptide {
+        if let Some(LateralChain {
+            direction, peptide, ..
+        }) = &amino_acid.lateral_chain
+        {
+            write!(f, "[{direction}")?;
+            for &residue in peptide {
                 display_residue(f, polymer, residue)?;
             }
             write!(f, "]")?;
         }
     }
-    // TODO: Bring this back once I have more ion types, but this just noise for now (when it's all protons)
-    // let unlocalised_modifications = polymer
-    //     .modification_refs()
-    //     .filter_map(|info| {
-    //         if let ModificationInfo::Unlocalized(modification) = info {
-    //             Some(modification.to_string())
-    //         } else {
-    //             None
-    //         }
-    //     })
-    //     .join(", ");
-    // if !unlocalised_modifications.is_empty() {
-    //     write!(f, " ({unlocalised_modifications})")?;
-    // }
     Ok(())
 }
 
-// FIXME: Sickening.
 fn display_residue(f: &mut Formatter, polymer: &Polymer, residue: ResidueId) -> fmt::Result {
     // FIXME: What to do about the unwrap()? Is that fine here?
     let residue = polymer.residue(residue).unwrap();
     let abbr = residue.abbr();
-    let named_mods = residue.functional_groups().filter_map(|(_, gs)| {
-        if let &GroupState::Modified(id) = gs {
-            let ModificationInfo::Named(named_mod, _) = polymer.modification(id).unwrap() else {
-                unreachable!();
-            };
-            Some(named_mod.abbr().to_owned())
-        } else {
-            None
-        }
-    });
+    let named_mods = residue
+        .functional_groups()
+        .filter_map(|(_, gs)| {
+            if let &GroupState::Modified(id) = gs {
+                let ModificationInfo::Named(named_mod, _) = polymer.modification(id).unwrap()
+                else {
+                    unreachable!();
+                };
+                Some(named_mod)
+            } else {
+                None
+            }
+        })
+        .filter(|named_mod| !AUTO_MODS.contains(&named_mod.abbr()))
+        .map(|named_mod| (ModificationKind::from(named_mod), 1));
     let offset_mods = residue.offset_modifications().map(|id| {
         let ModificationInfo::Offset(modification, _) = polymer.modification(id).unwrap() else {
             unreachable!();
         };
-        modification.to_string()
+        (
+            ModificationKind::from(modification.kind()),
+            u32::from(modification.multiplier()),
+        )
     });
-    let modifications = named_mods
-        .chain(offset_mods)
-        // FIXME: Awful hacks to format things in a way Steph likes...
-        .map(|m| m.replace("Red", "r").replace("-H2O", ""))
-        .filter(|m| !m.is_empty())
-        .join(", ");
+    let modifications = display_modifications(named_mods.chain(offset_mods));
 
     if modifications.is_empty() {
         write!(f, "{abbr}")
@@ -329,6 +693,374 @@ fn display_residue(f: &mut Formatter, polymer: &Polymer, residue: ResidueId) ->
     }
 }
 
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+enum ModificationKind<'p> {
+    Named(&'p str),
+    Offset(OffsetKind, String),
+}
+
+impl<'p> From<&NamedMod<'_, 'p>> for ModificationKind<'p> {
+    fn from(value: &NamedMod<'_, 'p>) -> Self {
+        Self::Named(value.abbr())
+    }
+}
+
+// NOTE: Offset compositions are written in Hill notation, so `+OH2` and `+H2O` are written the same way
+impl From<&OffsetMod<'_>> for ModificationKind<'_> {
+    fn from(value: &OffsetMod<'_>) -> Self {
+        Self::Offset(value.kind(), value.composition().formula().to_string())
+    }
+}
+
+impl<'p> From<&AnyMod<'_, 'p>> for ModificationKind<'p> {
+    fn from(value: &AnyMod<'_, 'p>) -> Self {
+        match value {
+            AnyMod::Named(named_mod) => named_mod.into(),
+            AnyMod::Offset(offset_mod) => offset_mod.into(),
+        }
+    }
+}
+
+// NOTE: Repeated modifications are merged into a single modification with a multiplier, so both `Am, Am` and `2xAm`
+// are written as `2xAm`. Named modifications are written before offsets, and each are sorted alphabetically
+fn display_modifications<'p>(
+    modifications: impl Iterator<Item = (ModificationKind<'p>, u32)>,
+) -> String {
+    modifications
+        .sorted_unstable()
+        .coalesce(|(kind, count), (next_kind, next_count)| {
+            if kind == next_kind {
+                Ok((kind, count + next_count))
+            } else {
+                Err(((kind, count), (next_kind, next_count)))
+            }
+        })
+        .map(|(kind, count)| match (kind, count) {
+            (ModificationKind::Named(abbr), 1) => abbr.to_owned(),
+            (ModificationKind::Named(abbr), count) => format!("{count}x{abbr}"),
+            (ModificationKind::Offset(kind, formula), 1) => format!("{kind}{formula}"),
+            (ModificationKind::Offset(kind, formula), count) => format!("{kind}{count}x{formula}"),
+        })
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, PolymerDatabase, Stereochemistry};
+    use proptest::{prelude::*, sample::select};
+    use smithereens::FragmentationRules;
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+    static RULES: Lazy<FragmentationRules> = Lazy::new(|| {
+        FragmentationRules::new(
+            &ATOMIC_DB,
+            &POLYMER_DB,
+            "hcd_rules.kdl",
+            include_str!("../data/hcd_rules.kdl"),
+        )
+        .unwrap()
+    });
+
+    fn display(structure: &str) -> String {
+        Muropeptide::new(&POLYMERIZER, structure)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn canonical_display() {
+        // NOTE: Structures that are already canonical are displayed unchanged
+        for structure in [
+            "gm",
+            "AEJA",
+            "gm-AEJA",
+            "gm(Anh)-AEJA",
+            "gm(Ac)-AE(Am)J(Am)A",
+            "gm-AE(Am, -H2O)JA",
+            "gm-AE(Am)K[G]",
+            "gm-AEJ[<GG]A",
+            "gm-AEJ[>GG]A",
+            "gm-AEJA~gm-AEJA",
+            "gm-AEJA~gm-AEJA~",
+            "gm-AEJA~=gm-AEJA (4-3)",
+            "gm-AEJA=gm-AEJ (4-3 & 3=3)",
+            "gm-AEJ~=gm-AEJ (-H2O) (3-3)",
+            "gm-AEJA (2xAm)",
+            "gm-lAdEmJdA",
+            "lAdE(Am)mJ(Am)dA",
+        ] {
+            assert_eq!(display(structure), structure);
+        }
+
+        // NOTE: Anything else is written canonically
+        for (structure, canonical) in [
+            ("gm(Red)-AEJA", "gm-AEJA"),
+            ("gm-AE(-H2O,Am)JA", "gm-AE(Am, -H2O)JA"),
+            ("gm-AEJA (-H2O,+2xH)", "gm-AEJA (+2xH, -H2O)"),
+            ("gm-AEJA=~gm-AEJA (4-3)", "gm-AEJA~=gm-AEJA (4-3)"),
+            ("gm-AEJA=gm-AEJ (4-3&3=3)", "gm-AEJA=gm-AEJ (4-3 & 3=3)"),
+            (
+                "gm-AEJ~=gm-AEJ (3-3)   (-H2O)",
+                "gm-AEJ~=gm-AEJ (-H2O) (3-3)",
+            ),
+        ] {
+            assert_eq!(display(structure), canonical);
+        }
+    }
+
+    #[test]
+    fn normalize_structures() {
+        // NOTE: Every structure in each group normalises to the first, canonical, structure
+        for structures in [
+            &["gm-AEJ=gm-AEJA (3-3 & 3=4)", "gm-AEJA=gm-AEJ (3=3&4-3)"][..],
+            &["gm-AEJ=gm-AEJA (3=4)", "gm-AEJA=gm-AEJ (4-3)"],
+            &["gm-AEJA~=gm-AEJA (4-3)", "gm-AEJA=~gm-AEJA (4-3)"],
+            &["gm-AE(Am, -H2O)JA", "gm-AE(-H2O,Am)JA", "gm-AE(Am,-OH2)JA"],
+            &["gm-AE(-2xH2O)JA", "gm-AE(-H2O, -H2O)JA"],
+            &["gm-AEJA (2xAm)", "gm-AEJA (Am, Am)", "gm-AEJA (Am,Am)"],
+            &["gm-AEJA (+2xH, -H2O)", "gm-AEJA (+H, -OH2, +H)"],
+        ] {
+            for structure in structures {
+                assert_eq!(normalize(&POLYMERIZER, structure).unwrap(), structures[0]);
+            }
+        }
+
+        assert!(normalize(&POLYMERIZER, "gm-AE(Xyz)JA").is_err());
+    }
+
+    #[test]
+    fn structural_equivalence() {
+        let muropeptide = |structure| Muropeptide::new(&POLYMERIZER, structure).unwrap();
+        let equivalent =
+            |structure, other| muropeptide(structure).is_equivalent(&muropeptide(other));
+
+        for (structure, other) in [
+            ("gm-AEJA", "gm-AEJA"),
+            ("gm(Red)-AEJA", "gm-AEJA"),
+            ("gm-AEJA (Am, Am)", "gm-AEJA (2xAm)"),
+            ("gm-AE(-H2O, -H2O)JA", "gm-AE(-2xH2O)JA"),
+            ("gm-AEJ[<GG]A~gm-AEJA", "gm-AEJ[<GG]A~gm-AEJA"),
+            // NOTE: The same muropeptides, written back-to-front
+            ("gm-AEJA=gm-AEJ (4-3)", "gm-AEJ=gm-AEJA (3=4)"),
+            ("gm-AEJA=gm-AEJ (4-3 & 3=3)", "gm-AEJ=gm-AEJA (3-3 & 3=4)"),
+        ] {
+            assert!(equivalent(structure, other), "{structure} != {other}");
+            assert!(equivalent(other, structure), "{other} != {structure}");
+        }
+
+        // NOTE: Each of these pairs share the same formula, but have different structures
+        for (structure, other) in [
+            ("gm-AEJA", "gm-AEAJ"),
+            ("gm-AE(Am)JA", "gm-AEJ(Am)A"),
+            ("gm-AEJA (-H2O)", "gm-AE(-H2O)JA"),
+            ("gm-AEJ[<GG]A", "gm-AEJ[>GG]A"),
+            ("gm-AEJA~gm-AEJ", "gm-AEJ~gm-AEJA"),
+            ("gm-AEJA=gm-AEJ (4-3)", "gm-AEJA=gm-AEJ (3=3)"),
+        ] {
+            assert_eq!(
+                muropeptide(structure).formula().to_string(),
+                muropeptide(other).formula().to_string()
+            );
+            assert!(!equivalent(structure, other), "{structure} == {other}");
+            assert!(!equivalent(other, structure), "{other} == {structure}");
+        }
+    }
+
+    #[test]
+    fn stereoisomers() {
+        let muropeptide = |structure| Muropeptide::new(&POLYMERIZER, structure).unwrap();
+        let unspecified = muropeptide("gm-AEJA");
+        let specified = muropeptide("gm-lAdEmJdA");
+
+        let stereochemistry = |muropeptide: &Muropeptide| {
+            let peptide = &muropeptide.monomers[0].peptide;
+            peptide
+                .iter()
+                .map(|amino_acid| {
+                    let residue = muropeptide.polymer.residue(amino_acid.residue).unwrap();
+                    residue.stereochemistry()
+                })
+                .collect_vec()
+        };
+        assert_eq!(stereochemistry(&unspecified), [None; 4]);
+        assert_eq!(
+            stereochemistry(&specified),
+            [
+                Some(Stereochemistry::L),
+                Some(Stereochemistry::D),
+                Some(Stereochemistry::Meso),
+                Some(Stereochemistry::D)
+            ]
+        );
+
+        // NOTE: Stereoisomers share the same mass and formula, but aren't structurally equivalent
+        assert_eq!(
+            unspecified.monoisotopic_mass(),
+            specified.monoisotopic_mass()
+        );
+        assert_eq!(unspecified.average_mass(), specified.average_mass());
+        assert_eq!(
+            unspecified.formula().to_string(),
+            specified.formula().to_string()
+        );
+        assert!(!unspecified.is_equivalent(&specified));
+        assert!(!muropeptide("gm-lAEJA").is_equivalent(&muropeptide("gm-dAEJA")));
+    }
+
+    fn monosaccharide() -> impl Strategy<Value = &'static str> {
+        select(vec![
+            "g", "m", "g(Ac)", "m(Ac)", "m(Anh)", "g(DeAc)", "m(Glyc)", "g(-H2O)",
+        ])
+    }
+
+    fn amino_acid() -> impl Strategy<Value = String> {
+        let unbranched = select(vec![
+            "A", "E", "E(Am)", "J", "J(Am)", "K", "Q", "G", "D(+H2O)", "lA", "dA", "dE(Am)", "mJ",
+        ]);
+        let branched = (
+            select(vec!["E", "J", "K"]),
+            select(vec!["", "<", ">"]),
+            select(vec!["G", "GG", "A(Am)", "S"]),
+        );
+        prop_oneof![
+            3 => unbranched.prop_map(String::from),
+            1 => branched.prop_map(|(residue, direction, chain)| {
+                format!("{residue}[{direction}{chain}]")
+            }),
+        ]
+    }
+
+    fn monomer() -> impl Strategy<Value = String> {
+        let glycan =
+            prop::collection::vec(monosaccharide(), 1..=2).prop_map(|glycan| glycan.concat());
+        let stem = prop::collection::vec(amino_acid(), 1..=5).prop_map(|stem| stem.concat());
+        prop_oneof![
+            (glycan.clone(), stem.clone()).prop_map(|(glycan, stem)| format!("{glycan}-{stem}")),
+            glycan,
+            stem,
+        ]
+    }
+
+    fn crosslink() -> impl Strategy<Value = String> {
+        let descriptor = select(vec!["4-3", "3-3", "3=3", "3=4", "2-4"]);
+        let separator = select(vec!["&", " & ", "  &"]);
+        (prop::collection::vec(descriptor, 1..=2), separator)
+            .prop_map(|(descriptors, separator)| descriptors.join(separator))
+    }
+
+    fn connection() -> impl Strategy<Value = &'static str> {
+        select(vec!["~", "=", "~=", "=~"])
+    }
+
+    fn modifications() -> impl Strategy<Value = &'static str> {
+        select(vec!["Am", "2xAm", "-H2O", "+2xH, -H2O", "-H2O,Ac"])
+    }
+
+    prop_compose! {
+        fn muropeptide()(
+            monomers in prop::collection::vec((monomer(), connection()), 1..=3),
+            circular in prop::bool::weighted(0.1),
+            crosslinks in prop::collection::vec(crosslink(), 3),
+            modifications in prop::option::of(modifications()),
+            modifications_first in any::<bool>(),
+        ) -> String {
+            let mut structure = String::new();
+            let mut connections = 0;
+            for (i, (monomer, connection)) in monomers.iter().enumerate() {
+                structure.push_str(monomer);
+                if i + 1 < monomers.len() || circular {
+                    structure.push_str(connection);
+                    connections += usize::from(connection.contains('='));
+                }
+            }
+
+            let crosslinks = crosslinks[..connections].join(", ");
+            let crosslinks = (connections > 0).then(|| format!("({crosslinks})"));
+            let modifications = modifications.map(|modifications| format!("({modifications})"));
+            let annotations = if modifications_first {
+                [modifications, crosslinks]
+            } else {
+                [crosslinks, modifications]
+            };
+            for annotation in annotations.into_iter().flatten() {
+                structure.push(' ');
+                structure.push_str(&annotation);
+            }
+
+            structure
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trips(structure in muropeptide()) {
+            // NOTE: Plenty of generated structures are chemically impossible (like crosslinks between residues that
+            // don't exist), and there is nothing to display for those
+            let Ok(muropeptide) = Muropeptide::new(&POLYMERIZER, &structure) else {
+                return Ok(());
+            };
+
+            let canonical = muropeptide.to_string();
+            let reparsed = Muropeptide::new(&POLYMERIZER, &canonical);
+            prop_assert!(
+                reparsed.is_ok(),
+                "{structure:?} was displayed as {canonical:?}, which failed to parse"
+            );
+            let reparsed = reparsed.unwrap();
+
+            prop_assert_eq!(reparsed.to_string(), canonical);
+            prop_assert_eq!(
+                reparsed.formula().to_string(),
+                muropeptide.formula().to_string()
+            );
+            prop_assert_eq!(reparsed.monoisotopic_mass(), muropeptide.monoisotopic_mass());
+            prop_assert_eq!(reparsed.charge(), muropeptide.charge());
+            prop_assert!(reparsed.is_equivalent(&muropeptide));
+        }
+    }
+
+    proptest! {
+        // NOTE: Every structure can have thousands of fragments, so fewer structures are checked here
+        #![proptest_config(ProptestConfig::with_cases(32))]
+        #[test]
+        fn fragment_display_round_trips(structure in muropeptide()) {
+            let Ok(muropeptide) = Muropeptide::new(&POLYMERIZER, &structure) else {
+                return Ok(());
+            };
+
+            // NOTE: Fragments in other charge states only differ by their charge carriers, and freed ions (like
+            // oxonium ions) have no residues left to display, so neither are checked
+            let fragments: HashSet<_> = muropeptide
+                .fragment(&RULES)
+                .filter(|fragment| {
+                    i64::from(fragment.charge()) == 1 && !fragment.monomers.is_empty()
+                })
+                .map(|fragment| fragment.to_string())
+                .collect();
+            for fragment in fragments {
+                let reparsed = Muropeptide::new(&POLYMERIZER, &fragment);
+                prop_assert!(
+                    reparsed.is_ok(),
+                    "a fragment of {structure:?} was displayed as {fragment:?}, which failed to parse"
+                );
+                prop_assert_eq!(reparsed.unwrap().to_string(), fragment);
+            }
+        }
+    }
+}
+
 // OPEN QUESTIONS =============================================================
 // 1) Which direction do lateral chains run off from mDAP? (from the amine!)
 // 2) What should I do when I have several B-ion (O+) termini?
@@ -0,0 +1,416 @@
+//! Generating libraries of muropeptide structures from a set of combinatorial rules
+use std::iter::{once, repeat_n};
+
+use ahash::{HashSet, HashSetExt};
+use itertools::Itertools;
+use polychem::Polymerizer;
+
+use crate::{Muropeptide, Result};
+
+// NOTE: Every list here describes a set of alternatives, and the library contains every combination of them. Residues
+// and glycans are written just as they would be in a structure, so they can carry their own modifications (`E(Am)` or
+// `gm(Anh)`)
+#[derive(Clone, Debug, Default)]
+pub struct LibraryRules {
+    // NOTE: An empty glycan (`""`) generates structures made up of only a stem peptide
+    pub glycans: Vec<String>,
+    // NOTE: A stem length of zero generates structures made up of only a glycan chain
+    pub stem_lengths: Vec<usize>,
+    // NOTE: The residues allowed at each position of the stem, so `stem_residues[0]` lists the first residues
+    pub stem_residues: Vec<Vec<String>>,
+    // NOTE: Lateral chains, each paired with the (1-indexed) stem position they branch from. Every stem is generated
+    // without any lateral chains too
+    pub lateral_chains: Vec<(usize, String)>,
+    // NOTE: Modifications applied to the whole structure (like `-H2O`). Every structure is generated without these
+    // modifications too
+    pub modifications: Vec<String>,
+    pub oligomerization: Option<Oligomerization>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Oligomerization {
+    // NOTE: The largest number of monomers that will be joined together — dimers are generated for 2, dimers and
+    // trimers for 3, and so on
+    pub max_monomers: usize,
+    pub linkages: Vec<Linkage>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Linkage {
+    Glycosidic,
+    // NOTE: The crosslink descriptor is written just as it would be in a structure (`4-3` or `3=3`)
+    Crosslink(String),
+    Both(String),
+}
+
+#[derive(Debug)]
+pub struct LibraryEntry<'a, 'p> {
+    pub structure: String,
+    pub muropeptide: Muropeptide<'a, 'p>,
+}
+
+impl LibraryRules {
+    // NOTE: Returns every structure described by these rules, without checking that any of them are chemically valid
+    // (`A[G]`, for example, lists a lateral chain that an alanine has nowhere to attach). Duplicates are removed
+    #[must_use]
+    pub fn structures(&self) -> Vec<String> {
+        let monomers = self.monomers();
+        let multimers = self.oligomers(&monomers);
+        let modifications = once(None).chain(self.modifications.iter().map(Some));
+
+        monomers
+            .into_iter()
+            .map(|monomer| (monomer, Vec::new()))
+            .chain(multimers)
+            .cartesian_product(modifications.collect_vec())
+            .map(|((structure, crosslinks), modification)| {
+                format_structure(&structure, modification.map(String::as_str), &crosslinks)
+            })
+            .unique()
+            .collect()
+    }
+
+    // NOTE: Structures that are chemically impossible (like those with bonds or modifications their residues have no
+    // free groups for) are left out of the library, as are structures equivalent to an earlier entry (like
+    // `gm(Red)-AE` and `gm-AE`, or `gm-AEJ=gm-AEJ (3-3)` and `gm-AEJ=gm-AEJ (3=3)`). Residues, modifications, or bonds
+    // missing from the polymer database are a mistake in the rules (or the database) instead, so they return an error
+    pub fn generate<'a, 'p>(
+        &self,
+        polymerizer: &Polymerizer<'a, 'p>,
+    ) -> Result<Vec<LibraryEntry<'a, 'p>>> {
+        build_entries(polymerizer, self.structures())
+    }
+
+    fn monomers(&self) -> Vec<String> {
+        let stems = self.stems();
+        self.glycans
+            .iter()
+            .cartesian_product(&stems)
+            .filter_map(
+                |(glycan, stem)| match (glycan.is_empty(), stem.is_empty()) {
+                    (true, true) => None,
+                    (false, false) => Some(format!("{glycan}-{stem}")),
+                    _ => Some(format!("{glycan}{stem}")),
+                },
+            )
+            .collect()
+    }
+
+    fn stems(&self) -> Vec<String> {
+        // NOTE: A stem length of zero produces a single, empty stem, since the `multi_cartesian_product()` of zero
+        // iterators yields a single, empty `Vec`
+        self.stem_lengths
+            .iter()
+            .flat_map(|&length| {
+                (0..length)
+                    .map(|index| self.amino_acids(index))
+                    .multi_cartesian_product()
+                    .map(|amino_acids| amino_acids.concat())
+            })
+            .collect()
+    }
+
+    fn amino_acids(&self, index: usize) -> Vec<String> {
+        let position = index + 1;
+        let residues = self.stem_residues.get(index).map_or(&[][..], Vec::as_slice);
+        let lateral_chains: Vec<_> = once(String::new())
+            .chain(
+                self.lateral_chains
+                    .iter()
+                    .filter(|&&(chain_position, _)| chain_position == position)
+                    .map(|(_, chain)| format!("[{chain}]")),
+            )
+            .collect();
+
+        residues
+            .iter()
+            .cartesian_product(&lateral_chains)
+            .map(|(residue, lateral_chain)| format!("{residue}{lateral_chain}"))
+            .collect()
+    }
+
+    // NOTE: Returns each oligomer along with the crosslink descriptors it needs listed after it
+    fn oligomers<'s>(&'s self, monomers: &[String]) -> Vec<(String, Vec<&'s str>)> {
+        let Some(Oligomerization {
+            max_monomers,
+            linkages,
+        }) = &self.oligomerization
+        else {
+            return Vec::new();
+        };
+
+        (2..=*max_monomers)
+            .flat_map(|length| {
+                let monomers = repeat_n(monomers, length).multi_cartesian_product();
+                let linkages = repeat_n(linkages, length - 1)
+                    .multi_cartesian_product()
+                    .collect_vec();
+                monomers.cartesian_product(linkages)
+            })
+            .map(|(monomers, linkages)| join_monomers(&monomers, linkages))
+            .collect()
+    }
+}
+
+// NOTE: Joins each pair of neighbouring `monomers` with a linkage, returning the joined structure along with the
+// crosslink descriptors that need to be listed after it
+pub(crate) fn join_monomers<'l>(
+    monomers: &[impl AsRef<str>],
+    linkages: impl IntoIterator<Item = &'l Linkage>,
+) -> (String, Vec<&'l str>) {
+    let mut monomers = monomers.iter().map(AsRef::as_ref);
+    let mut structure = monomers.next().unwrap_or_default().to_owned();
+    let mut crosslinks = Vec::new();
+    for (monomer, linkage) in monomers.zip(linkages) {
+        structure.push_str(linkage.connection());
+        structure.push_str(monomer);
+        crosslinks.extend(linkage.descriptor());
+    }
+    (structure, crosslinks)
+}
+
+// NOTE: Builds every structure, skipping those that are chemically impossible, and keeping only the first spelling of
+// each `CanonicalForm`. Lookup errors are returned instead of skipped
+pub(crate) fn build_entries<'a, 'p>(
+    polymerizer: &Polymerizer<'a, 'p>,
+    structures: impl IntoIterator<Item = String>,
+) -> Result<Vec<LibraryEntry<'a, 'p>>> {
+    let mut canonical_forms = HashSet::new();
+    structures
+        .into_iter()
+        .filter_map(
+            |structure| match Muropeptide::new(polymerizer, &structure) {
+                Ok(muropeptide) => canonical_forms
+                    .insert(muropeptide.polymer.canonical_form())
+                    .then_some(Ok(LibraryEntry {
+                        structure,
+                        muropeptide,
+                    })),
+                Err(error) if error.is_lookup_error() => Some(Err(error)),
+                Err(_) => None,
+            },
+        )
+        .collect()
+}
+
+pub(crate) fn format_structure(
+    structure: &str,
+    modification: Option<&str>,
+    crosslinks: &[&str],
+) -> String {
+    let modification = modification.map(|m| format!(" ({m})")).unwrap_or_default();
+    let crosslinks = if crosslinks.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", crosslinks.join(", "))
+    };
+    format!("{structure}{modification}{crosslinks}")
+}
+
+impl Linkage {
+    const fn connection(&self) -> &'static str {
+        match self {
+            Self::Glycosidic => "~",
+            Self::Crosslink(_) => "=",
+            Self::Both(_) => "~=",
+        }
+    }
+
+    fn descriptor(&self) -> Option<&str> {
+        match self {
+            Self::Glycosidic => None,
+            Self::Crosslink(descriptor) | Self::Both(descriptor) => Some(descriptor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, Massive, PolymerDatabase};
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+
+    fn strings<const N: usize>(strings: [&str; N]) -> Vec<String> {
+        strings.map(String::from).to_vec()
+    }
+
+    fn monomer_rules() -> LibraryRules {
+        LibraryRules {
+            glycans: strings(["gm", ""]),
+            stem_lengths: vec![0, 2, 3],
+            stem_residues: vec![strings(["A"]), strings(["E", "E(Am)"]), strings(["J", "K"])],
+            lateral_chains: vec![(3, "G".to_owned())],
+            ..LibraryRules::default()
+        }
+    }
+
+    #[test]
+    fn monomer_structures() {
+        let structures = monomer_rules().structures();
+        assert_eq!(
+            structures,
+            [
+                "gm",
+                "gm-AE",
+                "gm-AE(Am)",
+                "gm-AEJ",
+                "gm-AEJ[G]",
+                "gm-AEK",
+                "gm-AEK[G]",
+                "gm-AE(Am)J",
+                "gm-AE(Am)J[G]",
+                "gm-AE(Am)K",
+                "gm-AE(Am)K[G]",
+                "AE",
+                "AE(Am)",
+                "AEJ",
+                "AEJ[G]",
+                "AEK",
+                "AEK[G]",
+                "AE(Am)J",
+                "AE(Am)J[G]",
+                "AE(Am)K",
+                "AE(Am)K[G]",
+            ]
+        );
+    }
+
+    #[test]
+    fn modified_structures() {
+        let rules = LibraryRules {
+            glycans: strings(["gm"]),
+            stem_lengths: vec![1],
+            stem_residues: vec![strings(["A"])],
+            modifications: strings(["-H2O", "Anh"]),
+            ..LibraryRules::default()
+        };
+        assert_eq!(rules.structures(), ["gm-A", "gm-A (-H2O)", "gm-A (Anh)"]);
+    }
+
+    #[test]
+    fn oligomer_structures() {
+        let rules = LibraryRules {
+            glycans: strings(["gm"]),
+            stem_lengths: vec![3],
+            stem_residues: vec![strings(["A"]), strings(["E"]), strings(["J"])],
+            modifications: strings(["-H2O"]),
+            oligomerization: Some(Oligomerization {
+                max_monomers: 3,
+                linkages: vec![Linkage::Glycosidic, Linkage::Both("3-3".to_owned())],
+            }),
+            ..LibraryRules::default()
+        };
+        let structures = rules.structures();
+        // NOTE: One monomer, two dimers, and four trimers — each with and without the modification
+        assert_eq!(structures.len(), 14);
+        assert_eq!(
+            structures[..8],
+            [
+                "gm-AEJ",
+                "gm-AEJ (-H2O)",
+                "gm-AEJ~gm-AEJ",
+                "gm-AEJ~gm-AEJ (-H2O)",
+                "gm-AEJ~=gm-AEJ (3-3)",
+                "gm-AEJ~=gm-AEJ (-H2O) (3-3)",
+                "gm-AEJ~gm-AEJ~gm-AEJ",
+                "gm-AEJ~gm-AEJ~gm-AEJ (-H2O)",
+            ]
+        );
+        assert!(structures.contains(&"gm-AEJ~=gm-AEJ~=gm-AEJ (3-3, 3-3)".to_owned()));
+    }
+
+    #[test]
+    fn empty_rules() {
+        assert!(LibraryRules::default().structures().is_empty());
+        let rules = LibraryRules {
+            glycans: strings(["gm"]),
+            // NOTE: There are no residues listed for the second position, so no stems of length 2 can be made
+            stem_lengths: vec![2],
+            stem_residues: vec![strings(["A"])],
+            ..LibraryRules::default()
+        };
+        assert!(rules.structures().is_empty());
+    }
+
+    #[test]
+    fn generate_library() {
+        let mut rules = monomer_rules();
+        // NOTE: Alanine has no sidechain for a lateral chain to attach to, and amidated glutamate has no free carboxyl
+        // group left to amidate, so none of these new structures can be built
+        rules.lateral_chains.push((1, "G".to_owned()));
+        rules.stem_residues[1].push("E(Am, Am)".to_owned());
+
+        let library = rules.generate(&POLYMERIZER).unwrap();
+        let structures: Vec<_> = library
+            .iter()
+            .map(|entry| entry.structure.as_str())
+            .collect();
+        assert!(rules.structures().len() > 21);
+        assert_eq!(structures, monomer_rules().structures());
+
+        let entry = library
+            .iter()
+            .find(|entry| entry.structure == "gm-AE(Am)K[G]")
+            .unwrap();
+        let expected = Muropeptide::new(&POLYMERIZER, "gm-AE(Am)K[G]").unwrap();
+        assert_eq!(
+            entry.muropeptide.monoisotopic_mass(),
+            expected.monoisotopic_mass()
+        );
+    }
+
+    #[test]
+    fn generate_library_lookup_errors() {
+        // NOTE: `iA` isn't a residue, and `Xyz` isn't a modification — both are mistakes in the rules, so they're
+        // reported instead of being left out of the library
+        for (glycan, residue) in [("gm", "iA"), ("gm(Xyz)", "A")] {
+            let mut rules = monomer_rules();
+            rules.glycans.push(glycan.to_owned());
+            rules.stem_residues[0].push(residue.to_owned());
+            let error = rules.generate(&POLYMERIZER).unwrap_err();
+            assert!(error.is_lookup_error());
+        }
+    }
+
+    #[test]
+    fn deduplicate_library() {
+        let rules = LibraryRules {
+            glycans: strings(["gm", "gm(Red)"]),
+            stem_lengths: vec![2],
+            stem_residues: vec![strings(["A"]), strings(["E", "E(Am)"])],
+            modifications: strings(["Am, -H2O", "-H2O, Am", "2xAm", "Am, Am"]),
+            ..LibraryRules::default()
+        };
+        assert_eq!(rules.structures().len(), 20);
+
+        let structures: Vec<_> = rules
+            .generate(&POLYMERIZER)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.structure)
+            .collect();
+        assert_eq!(
+            structures,
+            [
+                "gm-AE",
+                "gm-AE (Am, -H2O)",
+                "gm-AE (2xAm)",
+                "gm-AE(Am)",
+                "gm-AE(Am) (Am, -H2O)",
+                "gm-AE(Am) (2xAm)",
+            ]
+        );
+    }
+}
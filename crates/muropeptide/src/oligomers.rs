@@ -0,0 +1,290 @@
+//! Building oligomers by joining monomers with every glycosidic bond and crosslink their residues allow
+use std::{cell::RefCell, iter::repeat_n};
+
+use itertools::Itertools;
+use nom_miette::final_parser;
+use polychem::{Polymer, Polymerizer, ResidueId};
+
+use crate::{
+    library::{build_entries, format_structure, join_monomers},
+    parser::monomer,
+    CrosslinkDescriptor, LateralChain, LibraryEntry, Linkage, Monomer, Position, Result,
+};
+
+const AMINO: &str = "Amino";
+const CARBOXYL: &str = "Carboxyl";
+const SIDECHAIN: &str = "Sidechain";
+const C_TERMINAL: &str = "C-Terminal";
+
+pub fn dimers<'a, 'p>(
+    polymerizer: &Polymerizer<'a, 'p>,
+    monomers: &[impl AsRef<str>],
+) -> Result<Vec<LibraryEntry<'a, 'p>>> {
+    oligomers(polymerizer, monomers, 2)
+}
+
+pub fn trimers<'a, 'p>(
+    polymerizer: &Polymerizer<'a, 'p>,
+    monomers: &[impl AsRef<str>],
+) -> Result<Vec<LibraryEntry<'a, 'p>>> {
+    oligomers(polymerizer, monomers, 3)
+}
+
+// NOTE: Every ordered selection of `size` monomers (the same monomer can be picked more than once) is joined by every
+// combination of linkages that their residues allow. Monomers with glycans can be joined by glycosidic bonds, and
+// crosslinks join the free C-terminus of one stem to a free sidechain amino group (or lateral chain) of another.
+// Combinations that can't be built (like those that use the same functional group twice) are left out, but residues,
+// modifications, or bonds missing from the polymer database return an error
+pub fn oligomers<'a, 'p>(
+    polymerizer: &Polymerizer<'a, 'p>,
+    monomers: &[impl AsRef<str>],
+    size: usize,
+) -> Result<Vec<LibraryEntry<'a, 'p>>> {
+    let sites: Vec<_> = monomers
+        .iter()
+        .map(|structure| LinkageSites::new(polymerizer, structure.as_ref()))
+        .collect::<Result<_>>()?;
+
+    let structures = repeat_n(&sites, size)
+        .multi_cartesian_product()
+        .flat_map(|monomers| {
+            let structures: Vec<_> = monomers.iter().map(|sites| sites.structure).collect();
+            monomers
+                .iter()
+                .tuple_windows()
+                .map(|(left, right)| left.linkages_to(right))
+                .multi_cartesian_product()
+                .map(move |linkages| {
+                    let (structure, crosslinks) = join_monomers(&structures, &linkages);
+                    format_structure(&structure, None, &crosslinks)
+                })
+        })
+        .unique();
+
+    // NOTE: The same oligomer can be written in more than one direction (like `gm-AEJA=gm-AEJA (4-3)` and
+    // `gm-AEJA=gm-AEJA (3=4)`), so only the first spelling of each `CanonicalForm` is kept
+    build_entries(polymerizer, structures)
+}
+
+// NOTE: Records which stem positions of a monomer could take part in a crosslink
+struct LinkageSites<'m> {
+    structure: &'m str,
+    has_glycan: bool,
+    donors: Vec<Position>,
+    acceptors: Vec<Position>,
+}
+
+impl<'m> LinkageSites<'m> {
+    fn new(polymerizer: &Polymerizer, structure: &'m str) -> Result<Self> {
+        let polymer = RefCell::new(polymerizer.new_polymer());
+        let Monomer { glycan, peptide } = final_parser(monomer(&polymer))(structure)?;
+        let polymer = polymer.into_inner();
+
+        let positions = (1..=Position::MAX).zip(&peptide);
+        let donors = positions
+            .clone()
+            .filter(|(_, amino_acid)| {
+                has_free_group(&polymer, amino_acid.residue, CARBOXYL, Some(C_TERMINAL))
+            })
+            .map(|(position, _)| position)
+            .collect();
+        // NOTE: Just like in the parser, a residue with a lateral chain is crosslinked through the end of that chain
+        let acceptors = positions
+            .filter(|(_, amino_acid)| match &amino_acid.lateral_chain {
+                // SAFETY: All `LateralChain`s carry non-empty vectors, so this call to `.last()` should never fail!
+                Some(LateralChain { peptide, .. }) => {
+                    has_free_group(&polymer, *peptide.last().unwrap(), AMINO, None)
+                }
+                None => has_free_group(&polymer, amino_acid.residue, AMINO, Some(SIDECHAIN)),
+            })
+            .map(|(position, _)| position)
+            .collect();
+
+        Ok(Self {
+            structure,
+            has_glycan: !glycan.is_empty(),
+            donors,
+            acceptors,
+        })
+    }
+
+    fn linkages_to(&self, right: &Self) -> Vec<Linkage> {
+        let donor_acceptor = self
+            .donors
+            .iter()
+            .cartesian_product(&right.acceptors)
+            .map(|(&l, &r)| CrosslinkDescriptor::DonorAcceptor(l, r));
+        let acceptor_donor = self
+            .acceptors
+            .iter()
+            .cartesian_product(&right.donors)
+            .map(|(&l, &r)| CrosslinkDescriptor::AcceptorDonor(l, r));
+        let glycosidic = self.has_glycan && right.has_glycan;
+
+        let mut linkages = Vec::new();
+        if glycosidic {
+            linkages.push(Linkage::Glycosidic);
+        }
+        for descriptor in donor_acceptor.chain(acceptor_donor) {
+            let descriptor = descriptor.to_string();
+            if glycosidic {
+                linkages.push(Linkage::Both(descriptor.clone()));
+            }
+            linkages.push(Linkage::Crosslink(descriptor));
+        }
+        linkages
+    }
+}
+
+fn has_free_group(
+    polymer: &Polymer,
+    residue: ResidueId,
+    name: &str,
+    location: Option<&str>,
+) -> bool {
+    polymer.residue(residue).is_some_and(|residue| {
+        residue.functional_groups().any(|(group, state)| {
+            state.is_free()
+                && group.name() == name
+                && location.is_none_or(|location| group.location() == location)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, Massive, PolymerDatabase};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use crate::Muropeptide;
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+
+    const H2O: Decimal = dec!(18.01056468403);
+    const H2: Decimal = dec!(2.01565006446);
+
+    fn structures<'e>(oligomers: &'e [LibraryEntry]) -> Vec<&'e str> {
+        oligomers
+            .iter()
+            .map(|entry| entry.structure.as_str())
+            .collect()
+    }
+
+    fn mass(muropeptide: &Muropeptide) -> Decimal {
+        muropeptide.monoisotopic_mass().into()
+    }
+
+    #[test]
+    fn linkage_sites() {
+        fn sites(structure: &str) -> LinkageSites {
+            LinkageSites::new(&POLYMERIZER, structure).unwrap()
+        }
+
+        let tetrapeptide = sites("gm-AEJA");
+        assert!(tetrapeptide.has_glycan);
+        assert_eq!(tetrapeptide.donors, [4]);
+        assert_eq!(tetrapeptide.acceptors, [3]);
+
+        let branched = sites("AE(Am)K[GGGGG]A");
+        assert!(!branched.has_glycan);
+        assert_eq!(branched.donors, [4]);
+        assert_eq!(branched.acceptors, [3]);
+
+        let dipeptide = sites("gm-AE");
+        assert_eq!(dipeptide.donors, [2]);
+        assert!(dipeptide.acceptors.is_empty());
+
+        let glycan = sites("gm");
+        assert!(glycan.donors.is_empty() && glycan.acceptors.is_empty());
+
+        assert!(LinkageSites::new(&POLYMERIZER, "gm-AEJA=gm-AEJA").is_err());
+        assert!(LinkageSites::new(&POLYMERIZER, "gm-AEiJA").is_err());
+    }
+
+    #[test]
+    fn tetrapeptide_dimers() {
+        let monomer = Muropeptide::new(&POLYMERIZER, "gm-AEJA").unwrap();
+        let dimers = dimers(&POLYMERIZER, &["gm-AEJA"]).unwrap();
+        // NOTE: `gm-AEJA=gm-AEJA (3=4)` is the same dimer as `gm-AEJA=gm-AEJA (4-3)`, just written back-to-front, but
+        // both `~=` dimers are kept: the glycosidic bond fixes which monomer comes first, so in one of them the
+        // crosslink is donated by the first monomer, and in the other, by the second
+        assert_eq!(
+            structures(&dimers),
+            [
+                "gm-AEJA~gm-AEJA",
+                "gm-AEJA~=gm-AEJA (4-3)",
+                "gm-AEJA=gm-AEJA (4-3)",
+                "gm-AEJA~=gm-AEJA (3=4)",
+            ]
+        );
+
+        // NOTE: Glycosidic bonds consume the reducing end of the first MurNAc, so only the second is reduced
+        let monomers = mass(&monomer) + mass(&monomer);
+        let expected = [
+            monomers - H2O - H2,
+            monomers - H2O - H2O - H2,
+            monomers - H2O,
+            monomers - H2O - H2O - H2,
+        ];
+        for (dimer, expected) in dimers.iter().zip(expected) {
+            assert_eq!(mass(&dimer.muropeptide), expected, "{}", dimer.structure);
+        }
+    }
+
+    #[test]
+    fn mixed_dimers() {
+        let dimers = dimers(&POLYMERIZER, &["gm-AEJ", "gm-AE"]).unwrap();
+        let structures = structures(&dimers);
+        assert_eq!(structures.len(), 10);
+        for structure in [
+            "gm-AEJ=gm-AEJ (3-3)",
+            "gm-AEJ~=gm-AEJ (3=3)",
+            "gm-AEJ=gm-AE (3=2)",
+            "gm-AE~gm-AE",
+        ] {
+            assert!(structures.contains(&structure), "{structure}");
+        }
+        // NOTE: Only the first way of writing each dimer is kept
+        assert!(!structures.contains(&"gm-AEJ=gm-AEJ (3=3)"));
+        assert!(!structures.contains(&"gm-AE=gm-AEJ (2-3)"));
+        // NOTE: Glutamate has no free sidechain amino group, so `gm-AE` can only ever be the crosslink donor
+        assert!(!structures.contains(&"gm-AEJ=gm-AE (3-2)"));
+        assert!(!structures.contains(&"gm-AE=gm-AE (2-2)"));
+    }
+
+    #[test]
+    fn tetrapeptide_trimers() {
+        let trimers = trimers(&POLYMERIZER, &["gm-AEJA"]).unwrap();
+        let structures = structures(&trimers);
+        // NOTE: Of the 25 possible pairs of linkages, 8 would need to crosslink through the middle monomer's single
+        // acceptor (or single donor) twice, and `(3=4, 3=4)` is just `(4-3, 4-3)` written back-to-front
+        assert_eq!(structures.len(), 16);
+        assert!(structures.contains(&"gm-AEJA~gm-AEJA~gm-AEJA"));
+        assert!(structures.contains(&"gm-AEJA=gm-AEJA=gm-AEJA (4-3, 4-3)"));
+        assert!(structures.contains(&"gm-AEJA~=gm-AEJA=gm-AEJA (3=4, 3=4)"));
+        assert!(!structures.contains(&"gm-AEJA=gm-AEJA=gm-AEJA (4-3, 3=4)"));
+        assert!(!structures.contains(&"gm-AEJA=gm-AEJA=gm-AEJA (3=4, 4-3)"));
+        assert!(!structures.contains(&"gm-AEJA=gm-AEJA=gm-AEJA (3=4, 3=4)"));
+    }
+
+    #[test]
+    fn invalid_monomers() {
+        assert!(dimers(&POLYMERIZER, &["gm-AEJA", "gm-AEJA~gm-AEJA"]).is_err());
+        let error = dimers(&POLYMERIZER, &["gm-AEJA", "gm-AEJiA"]).unwrap_err();
+        assert!(error.is_lookup_error());
+        assert!(dimers(&POLYMERIZER, &[] as &[&str]).unwrap().is_empty());
+    }
+}
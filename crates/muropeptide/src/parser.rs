@@ -1,12 +1,12 @@
 use std::{cell::RefCell, iter::zip};
 
 use itertools::{EitherOrBoth, Itertools};
-use miette::Diagnostic;
+use miette::{Diagnostic, SourceSpan};
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alpha1, alphanumeric1, char, one_of, space0, space1},
-    combinator::{cut, map, opt, recognize},
+    combinator::{cut, map, not, opt, recognize, verify},
     error::ErrorKind,
     multi::{many0, many1, separated_list1},
     sequence::{delimited, pair, preceded, terminated, tuple},
@@ -35,7 +35,7 @@ const GLYCOSIDIC_BOND: &str = "Gly";
 const STEM_BOND: &str = "Stem";
 const NTOC_BOND: &str = "NToC";
 const CTON_BOND: &str = "CToN";
-const CROSSLINK_BOND: &str = "Link";
+pub(crate) const CROSSLINK_BOND: &str = "Link";
 
 // FIXME: A horrible hack that's needed to specify the lifetimes captured by `impl FnMut(...) -> ...` correctly. Once
 // Rust 2024 is stabilized, however, this hack can be removed. Keep an eye on:
@@ -60,7 +60,7 @@ pub fn muropeptide<'z, 'a, 'p, 's>(
     move |i| {
         let polymer = RefCell::new(polymerizer.new_polymer());
         // FIXME: Perhaps there is a better way to shorten that `polymer` borrow...
-        let (rest, (monomers, connections)) = {
+        let (rest, (mut monomers, connections)) = {
             let multimer = map(
                 tuple((
                     monomer(&polymer),
@@ -133,11 +133,15 @@ pub fn muropeptide<'z, 'a, 'p, 's>(
                         }
 
                         let gly_bond = || -> Result<(), ConstructionError> {
-                            let donor = *last_glycan
+                            let donor = last_glycan
                                 .and_then(|lg| lg.last())
-                                .ok_or(ConstructionError::NoGlycan)?;
-                            let acceptor =
-                                *right.glycan.first().ok_or(ConstructionError::NoGlycan)?;
+                                .ok_or(ConstructionError::NoGlycan)?
+                                .residue;
+                            let acceptor = right
+                                .glycan
+                                .first()
+                                .ok_or(ConstructionError::NoGlycan)?
+                                .residue;
                             polymer
                                 .borrow_mut()
                                 .bond_residues(GLYCOSIDIC_BOND, donor, acceptor)?;
@@ -164,6 +168,7 @@ pub fn muropeptide<'z, 'a, 'p, 's>(
                                         let AminoAcid {
                                             residue,
                                             lateral_chain,
+                                            ..
                                         } = lookup_amino_acid(peptide, idx)?;
                                         Ok(*if let Some(LateralChain { peptide, .. }) =
                                             lateral_chain
@@ -218,6 +223,10 @@ pub fn muropeptide<'z, 'a, 'p, 's>(
             parser(i)?
         };
 
+        for monomer in &mut monomers {
+            rebase_spans(monomer, i.len());
+        }
+
         let polymer = polymer.into_inner();
         Ok((
             rest,
@@ -225,6 +234,7 @@ pub fn muropeptide<'z, 'a, 'p, 's>(
                 polymer,
                 monomers,
                 connections,
+                label: None,
             },
         ))
     }
@@ -242,7 +252,7 @@ pub fn monomer<'c, 'a, 'p, 's>(
             if let Some(peptide) = peptide {
                 // SAFETY: Both the `glycan` and `peptide` parsers ensure at least one residue is present, so `.last()` and
                 // `.first()` will never return `None`!
-                let donor = *glycan.last().unwrap();
+                let donor = glycan.last().unwrap().residue;
                 let acceptor = peptide.first().unwrap().residue;
 
                 polymer
@@ -275,9 +285,10 @@ fn glycan<'c, 'a, 'p, 's>(
 ) -> impl FnMut(&'s str) -> ParseResult<Vec<Monosaccharide>> + Captures<(&'c (), &'a (), &'p ())> {
     let parser = many1(monosaccharide(polymer));
     map_res(parser, |residues| {
+        let residue_ids = residues.iter().map(|monosaccharide| monosaccharide.residue);
         polymer
             .borrow_mut()
-            .bond_chain(GLYCOSIDIC_BOND, &residues)?;
+            .bond_chain(GLYCOSIDIC_BOND, residue_ids)?;
         Ok(residues)
     })
 }
@@ -300,8 +311,14 @@ fn peptide<'c, 'a, 'p, 's>(
 fn monosaccharide<'c, 'a, 'p, 's>(
     polymer: &'c RefCell<Polymer<'a, 'p>>,
 ) -> impl FnMut(&'s str) -> ParseResult<Monosaccharide> + Captures<(&'c (), &'a (), &'p ())> {
-    let parser = pair(recognize(lowercase), opt(modifications(polymer)));
-    map_res(parser, |(abbr, modifications)| {
+    // NOTE: A lowercase letter followed by an uppercase one might be an amino acid (like `dA` or `mJ`) instead, so
+    // checking the database for one here lets peptides without a glycan start with those amino acids
+    let amino_acid = verify(recognize(pair(lowercase, uppercase)), |abbr: &str| {
+        polymer.borrow().polymer_db().residues.contains_key(abbr)
+    });
+    let abbr = preceded(not(amino_acid), recognize(lowercase));
+    let parser = spanned(pair(abbr, opt(modifications(polymer))));
+    map_res(parser, |((abbr, modifications), span)| {
         let residue = polymer.borrow_mut().new_residue(abbr)?;
         for modification in modifications.into_iter().flatten() {
             polymer
@@ -309,7 +326,7 @@ fn monosaccharide<'c, 'a, 'p, 's>(
                 .localize_modification(modification, residue)?;
         }
 
-        Ok(residue)
+        Ok(Monosaccharide { residue, span })
     })
 }
 
@@ -318,9 +335,15 @@ fn monosaccharide<'c, 'a, 'p, 's>(
 fn amino_acid<'c, 'a, 'p, 's>(
     polymer: &'c RefCell<Polymer<'a, 'p>>,
 ) -> impl FnMut(&'s str) -> ParseResult<AminoAcid> + Captures<(&'c (), &'a (), &'p ())> {
-    let parser = pair(unbranched_amino_acid(polymer), opt(lateral_chain(polymer)));
-    map_res(parser, |(residue, lateral_chain)| {
-        if let Some(LateralChain { direction, peptide }) = &lateral_chain {
+    let parser = pair(
+        spanned(unbranched_amino_acid(polymer)),
+        opt(lateral_chain(polymer)),
+    );
+    map_res(parser, |((residue, span), lateral_chain)| {
+        if let Some(LateralChain {
+            direction, peptide, ..
+        }) = &lateral_chain
+        {
             let c_to_n = || -> polychem::Result<_> {
                 polymer
                     .borrow_mut()
@@ -350,6 +373,7 @@ fn amino_acid<'c, 'a, 'p, 's>(
         }
         Ok(AminoAcid {
             residue,
+            span,
             lateral_chain,
         })
     })
@@ -394,11 +418,12 @@ fn unbranched_amino_acid<'c, 'a, 'p, 's>(
 fn lateral_chain<'c, 'a, 'p, 's>(
     polymer: &'c RefCell<Polymer<'a, 'p>>,
 ) -> impl FnMut(&'s str) -> ParseResult<LateralChain> + Captures<(&'c (), &'a (), &'p ())> {
-    let peptide = many1(unbranched_amino_acid(polymer));
+    let peptide = spanned(many1(unbranched_amino_acid(polymer)));
     let parser = delimited(char('['), pair(peptide_direction, peptide), char(']'));
-    map(parser, |(direction, peptide)| LateralChain {
+    map(parser, |(direction, (peptide, span))| LateralChain {
         direction,
         peptide,
+        span,
     })
 }
 
@@ -415,6 +440,33 @@ fn peptide_direction(i: &str) -> ParseResult<PeptideDirection> {
 }
 // =
 
+// NOTE: Parsers only ever see the input that's left to parse, so the spans recorded here are first measured back from
+// the end of the input (which never moves), then converted to offsets from its start by `rebase_spans()`
+fn spanned<'s, O>(
+    mut parser: impl FnMut(&'s str) -> ParseResult<O>,
+) -> impl FnMut(&'s str) -> ParseResult<(O, SourceSpan)> {
+    move |i| {
+        let (rest, output) = parser(i)?;
+        let span = (i.len(), i.len() - rest.len()).into();
+        Ok((rest, (output, span)))
+    }
+}
+
+fn rebase_spans(monomer: &mut Monomer, input_length: usize) {
+    let rebase = |span: &mut SourceSpan| *span = (input_length - span.offset(), span.len()).into();
+    for monosaccharide in &mut monomer.glycan {
+        rebase(&mut monosaccharide.span);
+    }
+    for amino_acid in &mut monomer.peptide {
+        rebase(&mut amino_acid.span);
+        if let Some(lateral_chain) = &mut amino_acid.lateral_chain {
+            rebase(&mut lateral_chain.span);
+        }
+    }
+}
+
+// =
+
 /// Identifier = letter , { letter | digit | "_" } ;
 fn identifier(i: &str) -> ParseResult<&str> {
     // PERF: Could maybe avoid allocations by using `many0_count` instead, but needs benchmarking
@@ -1166,7 +1218,7 @@ mod tests {
             ($input:literal, $output:literal, $name:literal) => {
                 let (rest, id) = monosaccharide($input).unwrap();
                 assert_eq!(
-                    (rest, polymer.borrow().residue(id).unwrap().name()),
+                    (rest, polymer.borrow().residue(id.residue).unwrap().name()),
                     ($output, $name)
                 );
             };
@@ -1331,7 +1383,7 @@ mod tests {
                 let polymer = polymer.borrow();
                 let parsed_ids: Vec<_> = parsed_ids
                     .into_iter()
-                    .map(|id| polymer.residue(id).unwrap().name())
+                    .map(|id| polymer.residue(id.residue).unwrap().name())
                     .collect();
                 let residues = Vec::from($residues);
                 assert_eq!(parsed_ids, residues);
@@ -1394,6 +1446,8 @@ mod tests {
             496.46375660678381490
         );
         assert_chain_residues_and_masses!("xAJgmK", "AJgmK", ["Unknown Monosaccharide"], 0.0, 0.0);
+        // Amino Acids With Lowercase Prefixes
+        assert!(err_glycan("yEJA").is_err());
     }
 
     // FIXME: Add modification testing!
@@ -1414,7 +1468,7 @@ mod tests {
                 let polymer = polymer.borrow();
                 let glycan: Vec<_> = glycan
                     .into_iter()
-                    .map(|id| polymer.residue(id).unwrap().name())
+                    .map(|id| polymer.residue(id.residue).unwrap().name())
                     .collect();
                 let peptide: Vec<_> = peptide
                     .into_iter()
@@ -1524,6 +1578,11 @@ mod tests {
         assert!(err_monomer("AEJiA").is_err());
         assert!(err_monomer("AQyK").is_err());
         assert!(err_monomer("g-A").is_err());
+        // Peptides Starting With Prefixed Amino Acids
+        let (rest, Monomer { glycan, peptide }) = err_monomer("yEJA").unwrap();
+        assert_eq!((rest, glycan.len(), peptide.len()), ("", 0, 3));
+        let (rest, Monomer { glycan, peptide }) = err_monomer("eK[GGGGG]A").unwrap();
+        assert_eq!((rest, glycan.len(), peptide.len()), ("", 0, 2));
         // Multiple Monomers
         assert_monomer_residues_and_masses!(
             "gm,AEJ",
@@ -0,0 +1,394 @@
+//! Reading and writing libraries of muropeptides as PGFinder-style CSV or TSV tables
+use std::{io::Write, sync::Arc};
+
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use miette::{Diagnostic, LabeledSpan, NamedSource, Severity, SourceSpan};
+use polychem::{Charged, Formulaic, Massive, Polymerizer};
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::{Error, LibraryEntry, Muropeptide};
+
+const STRUCTURE: &str = "Structure";
+const MONOISOTOPIC_MASS: &str = "Monoisotopic Mass";
+const AVERAGE_MASS: &str = "Average Mass";
+const FORMULA: &str = "Formula";
+const CHARGE: &str = "Charge";
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TableFormat {
+    Csv,
+    Tsv,
+}
+
+#[derive(Debug)]
+pub struct ImportedLibrary<'a, 'p> {
+    pub entries: Vec<LibraryEntry<'a, 'p>>,
+    // NOTE: Rows are still imported when their stored mass disagrees with the one computed from their structure, but
+    // a warning pointing at each of those rows is collected here
+    pub warnings: Vec<TableError>,
+}
+
+// NOTE: Tables are expected to have a header containing a `Structure` column and (optionally) a `Monoisotopic Mass`
+// column — any other columns are ignored. Tables without a `Structure` header are read as a bare list of structures,
+// one per row, like `data/E. coli Monomers.csv`
+pub fn import_library<'a, 'p>(
+    polymerizer: &Polymerizer<'a, 'p>,
+    format: TableFormat,
+    file_name: impl AsRef<str>,
+    table: impl AsRef<str>,
+) -> Result<ImportedLibrary<'a, 'p>, Box<TableError>> {
+    let table = table.as_ref();
+    let source = NamedSource::new(file_name, Arc::from(table));
+    let mut reader = ReaderBuilder::new()
+        .delimiter(format.delimiter())
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(table.as_bytes());
+    let mut records = reader.records().peekable();
+
+    let (mut structure_column, mut mass_column) = (0, None);
+    if let Some(Ok(header)) = records.peek() {
+        if let Some(column) = find_column(header, STRUCTURE) {
+            structure_column = column;
+            mass_column = find_column(header, MONOISOTOPIC_MASS);
+            records.next();
+        }
+    }
+
+    let mut library = ImportedLibrary {
+        entries: Vec::new(),
+        warnings: Vec::new(),
+    };
+    for record in records {
+        let record = record.map_err(|e| TableErrorKind::Csv(e).finalize(&source))?;
+        let span = row_span(table, &record);
+
+        let structure = record.get(structure_column).unwrap_or_default().trim();
+        let muropeptide = Muropeptide::new(polymerizer, structure).map_err(|e| {
+            TableErrorKind::InvalidStructure(span, structure.to_owned(), e).finalize(&source)
+        })?;
+
+        let stored_mass = mass_column
+            .and_then(|column| record.get(column))
+            .map(str::trim)
+            .filter(|mass| !mass.is_empty());
+        if let Some(stored_mass) = stored_mass {
+            let stored: Decimal = stored_mass.parse().map_err(|_| {
+                TableErrorKind::InvalidMass(span, stored_mass.to_owned()).finalize(&source)
+            })?;
+            let computed: Decimal = muropeptide.monoisotopic_mass().into();
+            // NOTE: Stored masses are almost always rounded, so they are allowed to be off by one in their last place
+            if (computed - stored).abs() > Decimal::new(1, stored.scale()) {
+                let kind =
+                    TableErrorKind::MassMismatch(span, structure.to_owned(), stored, computed);
+                library.warnings.push(kind.finalize(&source));
+            }
+        }
+
+        library.entries.push(LibraryEntry {
+            structure: structure.to_owned(),
+            muropeptide,
+        });
+    }
+
+    Ok(library)
+}
+
+pub fn export_library<'e, 'a: 'e, 'p: 'e>(
+    writer: impl Write,
+    format: TableFormat,
+    entries: impl IntoIterator<Item = &'e LibraryEntry<'a, 'p>>,
+) -> csv::Result<()> {
+    let mut writer = WriterBuilder::new()
+        .delimiter(format.delimiter())
+        .from_writer(writer);
+
+    writer.write_record([STRUCTURE, MONOISOTOPIC_MASS, AVERAGE_MASS, FORMULA, CHARGE])?;
+    for LibraryEntry {
+        structure,
+        muropeptide,
+    } in entries
+    {
+        writer.write_record([
+            structure.clone(),
+            format!("{:.6}", muropeptide.monoisotopic_mass()),
+            format!("{:.6}", muropeptide.average_mass()),
+            muropeptide.formula().to_string(),
+            muropeptide.charge().to_string(),
+        ])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+impl TableFormat {
+    const fn delimiter(self) -> u8 {
+        match self {
+            Self::Csv => b',',
+            Self::Tsv => b'\t',
+        }
+    }
+}
+
+fn find_column(header: &StringRecord, name: &str) -> Option<usize> {
+    header
+        .iter()
+        .position(|column| column.trim().eq_ignore_ascii_case(name))
+}
+
+// NOTE: Spans the whole row (without its line ending), so diagnostics can point at it
+fn row_span(table: &str, record: &StringRecord) -> SourceSpan {
+    let start = record
+        .position()
+        .and_then(|position| usize::try_from(position.byte()).ok())
+        .unwrap_or_default();
+    let length = table[start..].lines().next().map_or(0, str::len);
+    (start, length).into()
+}
+
+// Table Error Types and Trait Implementations =========================================================================
+
+#[derive(Debug, Error)]
+#[error("library table contains an invalid row")]
+pub struct TableError {
+    table: NamedSource<Arc<str>>,
+    #[source]
+    kind: TableErrorKind,
+}
+
+// NOTE: This is manually implemented because the labels and severity need to be extracted from `self.kind`
+impl Diagnostic for TableError {
+    fn severity(&self) -> Option<Severity> {
+        self.kind.severity()
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.table)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(self.kind.labels().into_iter().map(|(s, l)| {
+            LabeledSpan::new_with_span(Some(l.to_owned()), *s)
+        })))
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        Some(&self.kind)
+    }
+}
+
+#[derive(Debug, Diagnostic, Error)]
+enum TableErrorKind {
+    #[error("failed to read a row of the table")]
+    Csv(#[source] csv::Error),
+
+    #[error("failed to build the structure {1:?}")]
+    InvalidStructure(
+        SourceSpan,
+        String,
+        #[source]
+        #[diagnostic_source]
+        Error,
+    ),
+
+    #[error("the stored monoisotopic mass {1:?} is not a valid number")]
+    #[diagnostic(help("masses should be written as plain decimal numbers, like 941.4077"))]
+    InvalidMass(SourceSpan, String),
+
+    #[error("the stored monoisotopic mass of {1:?} ({2}) doesn't match its computed mass ({3})")]
+    #[diagnostic(
+        severity(Warning),
+        help("double-check the structure and stored mass — the mass may have been computed for another structure")
+    )]
+    MassMismatch(SourceSpan, String, Decimal, Decimal),
+}
+
+impl TableErrorKind {
+    fn labels(&self) -> Vec<(&SourceSpan, &'static str)> {
+        match self {
+            Self::Csv(_) => Vec::new(),
+            Self::InvalidStructure(s, _, _) => vec![(s, "invalid structure")],
+            Self::InvalidMass(s, _) => vec![(s, "invalid mass")],
+            Self::MassMismatch(s, _, _, _) => vec![(s, "mismatched mass")],
+        }
+    }
+
+    fn finalize(self, table: &NamedSource<Arc<str>>) -> TableError {
+        TableError {
+            table: table.clone(),
+            kind: self,
+        }
+    }
+}
+
+// Module Tests ========================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, PolymerDatabase};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+
+    fn import(
+        format: TableFormat,
+        table: &str,
+    ) -> Result<ImportedLibrary<'static, 'static>, Box<TableError>> {
+        import_library(&POLYMERIZER, format, "table", table)
+    }
+
+    fn structures<'e>(library: &'e ImportedLibrary) -> Vec<&'e str> {
+        library
+            .entries
+            .iter()
+            .map(|entry| entry.structure.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn import_structure_list() {
+        let library = import(
+            TableFormat::Csv,
+            include_str!("../../../data/E. coli Monomers.csv"),
+        )
+        .unwrap();
+        assert!(library.warnings.is_empty());
+        assert_eq!(structures(&library)[..3], ["gm-AE", "gm-AEJ", "gm-AEJA"]);
+        let expected = Muropeptide::new(&POLYMERIZER, "gm-AEJA").unwrap();
+        assert_eq!(
+            library.entries[2].muropeptide.monoisotopic_mass(),
+            expected.monoisotopic_mass()
+        );
+    }
+
+    #[test]
+    fn import_mass_table() {
+        let table = "Inferred structure,Structure,Monoisotopic Mass\r\n\
+                     Penta,gm-AE,698.2858\r\n\
+                     Tetra,gm-AEJA,941.407702\r\n\
+                     Tetra,gm-AEJAA,\r\n";
+        let library = import(TableFormat::Csv, table).unwrap();
+        assert_eq!(structures(&library), ["gm-AE", "gm-AEJA", "gm-AEJAA"]);
+        assert!(library.warnings.is_empty());
+
+        let tsv = table.replace(',', "\t");
+        let library = import(TableFormat::Tsv, &tsv).unwrap();
+        assert_eq!(structures(&library), ["gm-AE", "gm-AEJA", "gm-AEJAA"]);
+    }
+
+    #[test]
+    fn flag_mismatched_masses() {
+        let table = "structure\tmonoisotopic mass\n\
+                     gm-AE\t698.2858\n\
+                     gm-AEJ\t698.2858\n\
+                     gm-AEJA\t941.4076\n";
+        let library = import(TableFormat::Tsv, table).unwrap();
+        assert_eq!(library.entries.len(), 3);
+        assert_eq!(library.warnings.len(), 2);
+
+        let warning = &library.warnings[0];
+        assert_eq!(warning.severity(), Some(Severity::Warning));
+        let TableErrorKind::MassMismatch(span, structure, stored, computed) = &warning.kind else {
+            panic!("expected a mass mismatch, got {warning:?}");
+        };
+        assert_eq!(
+            &table[span.offset()..span.offset() + span.len()],
+            "gm-AEJ\t698.2858"
+        );
+        assert_eq!(structure, "gm-AEJ");
+        assert_eq!(*stored, dec!(698.2858));
+        assert_eq!(*computed, computed_mass(&library, 1));
+
+        // NOTE: Rounding can't explain a difference of more than 0.0001 from 941.407702
+        let TableErrorKind::MassMismatch(span, ..) = &library.warnings[1].kind else {
+            panic!("expected a mass mismatch");
+        };
+        assert_eq!(
+            &table[span.offset()..span.offset() + span.len()],
+            "gm-AEJA\t941.4076"
+        );
+    }
+
+    fn computed_mass(library: &ImportedLibrary, index: usize) -> Decimal {
+        library.entries[index]
+            .muropeptide
+            .monoisotopic_mass()
+            .into()
+    }
+
+    #[test]
+    fn invalid_rows() {
+        let error = import(TableFormat::Csv, "Structure\ngm-AEJA\ngm-AEiJA\n").unwrap_err();
+        assert_eq!(error.severity(), None);
+        let TableErrorKind::InvalidStructure(span, structure, _) = &error.kind else {
+            panic!("expected an invalid structure, got {error:?}");
+        };
+        assert_eq!(structure, "gm-AEiJA");
+        assert_eq!((span.offset(), span.len()), (18, 8));
+
+        let error = import(
+            TableFormat::Csv,
+            "Structure,Monoisotopic Mass\ngm-AEJA,unknown\n",
+        )
+        .unwrap_err();
+        assert!(
+            matches!(error.kind, TableErrorKind::InvalidMass(_, ref mass) if mass == "unknown")
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let entries: Vec<_> = ["gm-AEJA", "gm-AEJA=gm-AEJA=gm-AEJA (4-3, 4-3)"]
+            .into_iter()
+            .map(|structure| LibraryEntry {
+                structure: structure.to_owned(),
+                muropeptide: Muropeptide::new(&POLYMERIZER, structure).unwrap(),
+            })
+            .collect();
+
+        let mut csv = Vec::new();
+        export_library(&mut csv, TableFormat::Csv, &entries).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("Structure,Monoisotopic Mass,Average Mass,Formula,Charge")
+        );
+        let monomer = lines.next().unwrap();
+        assert!(monomer.starts_with("gm-AEJA,941.407702,"));
+        assert!(monomer.ends_with(",C37H63N7O21,0"));
+        // NOTE: Structures containing the delimiter are quoted
+        let trimer = lines.next().unwrap();
+        assert!(trimer.starts_with("\"gm-AEJA=gm-AEJA=gm-AEJA (4-3, 4-3)\","));
+        assert!(trimer.ends_with(",C111H185N21O61,0"));
+
+        let library = import(TableFormat::Csv, &csv).unwrap();
+        assert!(library.warnings.is_empty());
+        assert_eq!(
+            structures(&library),
+            ["gm-AEJA", "gm-AEJA=gm-AEJA=gm-AEJA (4-3, 4-3)"]
+        );
+
+        let mut tsv = Vec::new();
+        export_library(&mut tsv, TableFormat::Tsv, &library.entries).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.starts_with(
+            "Structure\tMonoisotopic Mass\tAverage Mass\tFormula\tCharge\ngm-AEJA\t941.407702\t"
+        ));
+        assert!(tsv.contains("\ngm-AEJA=gm-AEJA=gm-AEJA (4-3, 4-3)\t"));
+    }
+}
@@ -0,0 +1,575 @@
+//! Checking muropeptide structures against a configurable set of biochemical rules
+use std::sync::Arc;
+
+use ahash::HashMap;
+use itertools::Itertools;
+use miette::{Diagnostic, LabeledSpan, Severity, SourceSpan};
+use polychem::{GroupState, ModificationInfo, Polymer, Polymerizer, Stereochemistry};
+use thiserror::Error;
+
+use crate::{
+    parser::CROSSLINK_BOND, AminoAcid, LateralChain, Monomer, Monosaccharide, Muropeptide, Result,
+};
+
+// NOTE: Stem peptides attached to anything other than MurNAc (like `g-AE`) never make it this far — the `Stem` bond in
+// the polymer database only has a donor on MurNAc, so `Muropeptide::new()` already rejects those structures
+#[derive(Clone, Debug)]
+pub struct ValidationRules {
+    // NOTE: The residues allowed at each position of the stem, so `stem_residues[0]` lists the first residues. Residues
+    // are listed by abbreviation alone (so `E` also allows `E(Am)`, and stereoisomers like `dE`), and an empty list (or
+    // a position past the end of this one) allows any residue
+    pub stem_residues: Vec<Vec<String>>,
+    // NOTE: The stereochemistries allowed at each position of the stem, following the same layout as `stem_residues`.
+    // Residues that don't specify their stereochemistry in the polymer database (like `A`, instead of `dA` or `lA`)
+    // aren't checked
+    pub stem_stereochemistry: Vec<Vec<Stereochemistry>>,
+    pub max_lateral_chain_length: Option<usize>,
+    // NOTE: Every free reducing end must carry one of these modifications — an empty list disables this check
+    pub reducing_end_modifications: Vec<String>,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        Self {
+            stem_residues: Vec::new(),
+            stem_stereochemistry: Vec::new(),
+            max_lateral_chain_length: None,
+            reducing_end_modifications: vec!["Red".to_owned(), "Anh".to_owned()],
+        }
+    }
+}
+
+impl ValidationRules {
+    // NOTE: Structures that fail to build are returned as an `Err`, but everything else is built and then checked
+    // against these rules. Rules set here are reported as errors, and crosslinks that look chemically implausible are
+    // reported as warnings — a structure that returns no errors (or warnings) is valid
+    pub fn validate(
+        &self,
+        polymerizer: &Polymerizer,
+        structure: impl AsRef<str>,
+    ) -> Result<Vec<ValidationError>> {
+        let structure = structure.as_ref();
+        let muropeptide = Muropeptide::new(polymerizer, structure)?;
+
+        let mut errors = Vec::new();
+        for Monomer { peptide, .. } in &muropeptide.monomers {
+            errors.extend(self.check_stem(&muropeptide.polymer, peptide));
+            errors.extend(self.check_stem_stereochemistry(&muropeptide.polymer, peptide));
+            errors.extend(self.check_lateral_chains(peptide));
+        }
+        errors.extend(self.check_reducing_ends(&muropeptide));
+        errors.extend(check_crosslinks(&muropeptide));
+
+        let structure = Arc::from(structure);
+        Ok(errors
+            .into_iter()
+            .map(|kind| kind.finalize(&structure))
+            .collect())
+    }
+
+    fn check_stem(&self, polymer: &Polymer, peptide: &[AminoAcid]) -> Vec<ValidationErrorKind> {
+        peptide
+            .iter()
+            .zip(&self.stem_residues)
+            .enumerate()
+            .filter_map(|(idx, (&AminoAcid { residue, span, .. }, allowed))| {
+                // SAFETY: Every `ResidueId` stored in a `Monomer` comes from its `Muropeptide`'s polymer
+                let residue = polymer.residue(residue).unwrap();
+                let abbr = residue.abbr();
+                (!allowed.is_empty() && !allowed.iter().any(|a| residue.matches_abbr(a))).then(
+                    || {
+                        let position = idx + 1;
+                        let allowed = allowed.join(", ");
+                        ValidationErrorKind::UnexpectedStemResidue(
+                            span,
+                            abbr.to_owned(),
+                            position,
+                            allowed,
+                        )
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn check_stem_stereochemistry(
+        &self,
+        polymer: &Polymer,
+        peptide: &[AminoAcid],
+    ) -> Vec<ValidationErrorKind> {
+        peptide
+            .iter()
+            .zip(&self.stem_stereochemistry)
+            .enumerate()
+            .filter_map(|(idx, (&AminoAcid { residue, span, .. }, allowed))| {
+                // SAFETY: Every `ResidueId` stored in a `Monomer` comes from its `Muropeptide`'s polymer
+                let residue_ref = polymer.residue(residue).unwrap();
+                let stereochemistry = residue_ref.stereochemistry()?;
+                (!allowed.is_empty() && !allowed.contains(&stereochemistry)).then(|| {
+                    let position = idx + 1;
+                    let allowed = allowed.iter().join(", ");
+                    ValidationErrorKind::UnexpectedStereochemistry(
+                        span,
+                        residue_ref.abbr().to_owned(),
+                        position,
+                        stereochemistry,
+                        allowed,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn check_lateral_chains(&self, peptide: &[AminoAcid]) -> Vec<ValidationErrorKind> {
+        let Some(max_length) = self.max_lateral_chain_length else {
+            return Vec::new();
+        };
+
+        peptide
+            .iter()
+            .filter_map(|amino_acid| amino_acid.lateral_chain.as_ref())
+            .filter(|LateralChain { peptide, .. }| peptide.len() > max_length)
+            .map(|chain| {
+                ValidationErrorKind::LateralChainTooLong(
+                    chain.span,
+                    chain.peptide.len(),
+                    max_length,
+                )
+            })
+            .collect()
+    }
+
+    fn check_reducing_ends(&self, muropeptide: &Muropeptide) -> Vec<ValidationErrorKind> {
+        if self.reducing_end_modifications.is_empty() {
+            return Vec::new();
+        }
+
+        let polymer = &muropeptide.polymer;
+        let is_allowed = |state: &GroupState| match state {
+            GroupState::Free => false,
+            &GroupState::Modified(id) => matches!(
+                polymer.modification(id),
+                Some(ModificationInfo::Named(named_mod, _))
+                    if self.reducing_end_modifications.iter().any(|m| m == named_mod.abbr())
+            ),
+            // NOTE: Bonded reducing ends (like those in a glycan chain) aren't free, so they don't need modifying
+            GroupState::Donor(_) | GroupState::Acceptor(_) => true,
+        };
+
+        // NOTE: Only monosaccharides have reducing ends
+        muropeptide
+            .monomers
+            .iter()
+            .flat_map(|monomer| &monomer.glycan)
+            .filter_map(|&Monosaccharide { residue, span }| {
+                // SAFETY: Every `ResidueId` stored in a `Monomer` comes from its `Muropeptide`'s polymer
+                let residue = polymer.residue(residue).unwrap();
+                residue
+                    .functional_groups()
+                    .any(|(group, state)| {
+                        group.name() == REDUCING_END_GROUP
+                            && group.location() == REDUCING_END_LOCATION
+                            && !is_allowed(state)
+                    })
+                    .then(|| {
+                        let allowed = self.reducing_end_modifications.join(" or ");
+                        ValidationErrorKind::UnmodifiedReducingEnd(
+                            span,
+                            residue.abbr().to_owned(),
+                            allowed,
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+const REDUCING_END_GROUP: &str = "Hydroxyl";
+const REDUCING_END_LOCATION: &str = "Reducing End";
+const N_TERMINAL_LOCATION: &str = "N-Terminal";
+
+// NOTE: Bond-target matching already ensures that every crosslink is accepted by a free amino group, but the
+// N-terminus of a stem peptide is only free when the stem has lost its glycan — crosslinks are normally accepted by a
+// sidechain amino group, or by the end of a lateral chain
+fn check_crosslinks(muropeptide: &Muropeptide) -> Vec<ValidationErrorKind> {
+    let stem_spans: HashMap<_, _> = muropeptide
+        .monomers
+        .iter()
+        .flat_map(|monomer| &monomer.peptide)
+        .map(|amino_acid| (amino_acid.residue, amino_acid.span))
+        .collect();
+
+    muropeptide
+        .polymer
+        .bond_refs()
+        .filter(|bond| bond.1.abbr() == CROSSLINK_BOND)
+        .map(|bond| bond.2)
+        .filter(|acceptor| {
+            acceptor.1.location() == N_TERMINAL_LOCATION && stem_spans.contains_key(&acceptor.0)
+        })
+        .sorted_unstable()
+        .map(|acceptor| {
+            // SAFETY: Every bonded `ResidueId` comes from this polymer
+            let abbr = muropeptide.polymer.residue(acceptor.0).unwrap().abbr();
+            let span = stem_spans[&acceptor.0];
+            ValidationErrorKind::TerminalCrosslink(span, abbr.to_owned())
+        })
+        .collect()
+}
+
+// Validation Error Types and Trait Implementations ====================================================================
+
+#[derive(Debug, Error)]
+#[error("muropeptide structure breaks a validation rule")]
+pub struct ValidationError {
+    structure: Arc<str>,
+    #[source]
+    kind: ValidationErrorKind,
+}
+
+// NOTE: This is manually implemented because the labels and severity need to be extracted from `self.kind`
+impl Diagnostic for ValidationError {
+    fn severity(&self) -> Option<Severity> {
+        self.kind.severity()
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.structure)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let (span, label) = self.kind.label();
+        Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+            Some(label.to_owned()),
+            *span,
+        ))))
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        Some(&self.kind)
+    }
+}
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+enum ValidationErrorKind {
+    #[error("the residue {1:?} isn't allowed at position {2} of a stem peptide")]
+    #[diagnostic(help("the residues allowed at this position are: {3}"))]
+    UnexpectedStemResidue(SourceSpan, String, usize, String),
+
+    #[error("the {3} residue {1:?} isn't allowed at position {2} of a stem peptide")]
+    #[diagnostic(help("the stereochemistries allowed at this position are: {4}"))]
+    UnexpectedStereochemistry(SourceSpan, String, usize, Stereochemistry, String),
+
+    #[error("the lateral chain is {1} residues long, which is longer than the limit of {2}")]
+    #[diagnostic(help(
+        "double-check the lateral chain, or raise the maximum lateral chain length"
+    ))]
+    LateralChainTooLong(SourceSpan, usize, usize),
+
+    #[error("the reducing end of {1:?} isn't modified with {2}")]
+    #[diagnostic(help(
+        "add one of the required modifications to the reducing end of this glycan chain"
+    ))]
+    UnmodifiedReducingEnd(SourceSpan, String, String),
+
+    #[error("a crosslink is accepted by the N-terminus of the stem residue {1:?}")]
+    #[diagnostic(
+        severity(Warning),
+        help("crosslinks are normally accepted by a sidechain or lateral chain amino group")
+    )]
+    TerminalCrosslink(SourceSpan, String),
+}
+
+impl ValidationErrorKind {
+    const fn label(&self) -> (&SourceSpan, &'static str) {
+        match self {
+            Self::UnexpectedStemResidue(s, _, _, _) => (s, "unexpected stem residue"),
+            Self::UnexpectedStereochemistry(s, _, _, _, _) => (s, "unexpected stereochemistry"),
+            Self::LateralChainTooLong(s, _, _) => (s, "lateral chain too long"),
+            Self::UnmodifiedReducingEnd(s, _, _) => (s, "unmodified reducing end"),
+            Self::TerminalCrosslink(s, _) => (s, "crosslink acceptor"),
+        }
+    }
+
+    fn finalize(self, structure: &Arc<str>) -> ValidationError {
+        ValidationError {
+            structure: Arc::clone(structure),
+            kind: self,
+        }
+    }
+}
+
+// Module Tests ========================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, PolymerDatabase};
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+
+    fn findings(
+        rules: &ValidationRules,
+        structure: &str,
+    ) -> Vec<(String, Option<Severity>, usize, usize)> {
+        rules
+            .validate(&POLYMERIZER, structure)
+            .unwrap()
+            .into_iter()
+            .map(|error| {
+                let span = *error.kind.label().0;
+                (
+                    error.kind.to_string(),
+                    error.severity(),
+                    span.offset(),
+                    span.len(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stem_attachment() {
+        let rules = ValidationRules::default();
+        assert!(rules.validate(&POLYMERIZER, "gm-AEJA").is_ok());
+        assert!(rules.validate(&POLYMERIZER, "g-AE").is_err());
+        assert!(rules.validate(&POLYMERIZER, "mg-AE").is_err());
+    }
+
+    #[test]
+    fn stem_residues() {
+        let rules = ValidationRules {
+            stem_residues: vec![
+                vec!["A".to_owned(), "S".to_owned(), "G".to_owned()],
+                vec!["E".to_owned()],
+                Vec::new(),
+                vec!["A".to_owned()],
+            ],
+            ..ValidationRules::default()
+        };
+
+        // NOTE: Stereoisomers (like `lA` and `dE`) are allowed wherever the residue they're a stereoisomer of is
+        for structure in [
+            "gm",
+            "gm-AEJA",
+            "gm-SE(Am)KAAG",
+            "gm-GEJ=gm-AEJA (3=4)",
+            "gm-lAdE(Am)mJdA",
+        ] {
+            assert_eq!(findings(&rules, structure), Vec::new());
+        }
+
+        assert_eq!(
+            findings(&rules, "gm-AQJ(Am)G"),
+            vec![
+                (
+                    r#"the residue "Q" isn't allowed at position 2 of a stem peptide"#.to_owned(),
+                    None,
+                    4,
+                    1
+                ),
+                (
+                    r#"the residue "G" isn't allowed at position 4 of a stem peptide"#.to_owned(),
+                    None,
+                    10,
+                    1
+                ),
+            ]
+        );
+        assert_eq!(
+            findings(&rules, "gm-AEJA~gm(Anh)-AD(Am)J"),
+            vec![(
+                r#"the residue "D" isn't allowed at position 2 of a stem peptide"#.to_owned(),
+                None,
+                17,
+                5
+            )]
+        );
+    }
+
+    #[test]
+    fn stem_stereochemistry() {
+        let rules = ValidationRules {
+            stem_stereochemistry: vec![
+                vec![Stereochemistry::L],
+                vec![Stereochemistry::D],
+                vec![Stereochemistry::L, Stereochemistry::Meso],
+                vec![Stereochemistry::D],
+            ],
+            ..ValidationRules::default()
+        };
+
+        // NOTE: Residues without a stereochemistry (like `A` or `E`) are never flagged
+        for structure in [
+            "gm-AEJA",
+            "gm-lAdEmJdA",
+            "gm-lAdElKdA",
+            "lAdEmJdAdA",
+            "gm-AdEJA",
+            "gm-lAdEmJdA=gm-lAdEmJ (4-3)",
+        ] {
+            assert_eq!(findings(&rules, structure), Vec::new());
+        }
+
+        assert_eq!(
+            findings(&rules, "gm-dAdEmJlA"),
+            vec![
+                (
+                    r#"the D residue "dA" isn't allowed at position 1 of a stem peptide"#
+                        .to_owned(),
+                    None,
+                    3,
+                    2
+                ),
+                (
+                    r#"the L residue "lA" isn't allowed at position 4 of a stem peptide"#
+                        .to_owned(),
+                    None,
+                    9,
+                    2
+                ),
+            ]
+        );
+        assert_eq!(
+            findings(&rules, "gm-lAdEdE(Am)"),
+            vec![(
+                r#"the D residue "dE" isn't allowed at position 3 of a stem peptide"#.to_owned(),
+                None,
+                7,
+                6
+            )]
+        );
+    }
+
+    #[test]
+    fn lateral_chain_length() {
+        let rules = ValidationRules {
+            max_lateral_chain_length: Some(2),
+            ..ValidationRules::default()
+        };
+
+        for structure in ["gm-AEJA", "gm-AEK[G]A", "gm-AEK[GG]A", "gm-AEJ[<GG]A"] {
+            assert_eq!(findings(&rules, structure), Vec::new());
+        }
+
+        assert_eq!(
+            findings(&rules, "gm-AEK[GGG]A=gm-AEJ[<GGGGG]AA (4-3)"),
+            vec![
+                (
+                    "the lateral chain is 3 residues long, which is longer than the limit of 2"
+                        .to_owned(),
+                    None,
+                    7,
+                    3
+                ),
+                (
+                    "the lateral chain is 5 residues long, which is longer than the limit of 2"
+                        .to_owned(),
+                    None,
+                    21,
+                    5
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reducing_ends() {
+        let rules = ValidationRules {
+            reducing_end_modifications: vec!["Anh".to_owned()],
+            ..ValidationRules::default()
+        };
+
+        for structure in ["AEJA", "gm(Anh)-AEJA", "gm-AEJA~gm(Anh)-AEJA"] {
+            assert_eq!(findings(&rules, structure), Vec::new());
+        }
+
+        assert_eq!(
+            findings(&rules, "gm-AEJA=gm(Anh)-AEJ (4-3)"),
+            vec![(
+                r#"the reducing end of "m" isn't modified with Anh"#.to_owned(),
+                None,
+                1,
+                1
+            )]
+        );
+
+        let no_reducing_ends = ValidationRules {
+            reducing_end_modifications: Vec::new(),
+            ..ValidationRules::default()
+        };
+        assert_eq!(findings(&no_reducing_ends, "gm-AEJA"), Vec::new());
+        assert_eq!(findings(&ValidationRules::default(), "gm-AEJA"), Vec::new());
+    }
+
+    #[test]
+    fn terminal_crosslinks() {
+        let rules = ValidationRules::default();
+
+        for structure in [
+            "gm-AEJA=gm-AEJA (4-3)",
+            "gm-AEJ=gm-AEJ (3-3)",
+            "AEJA=AEJA (4-3)",
+            "gm-AEK[GGGGG]A=gm-AEK[GGGGG]A (4-3)",
+        ] {
+            assert_eq!(findings(&rules, structure), Vec::new());
+        }
+
+        assert_eq!(
+            findings(&rules, "gm-AEJA=AEJA (4-1)"),
+            vec![(
+                r#"a crosslink is accepted by the N-terminus of the stem residue "A""#.to_owned(),
+                Some(Severity::Warning),
+                8,
+                1
+            )]
+        );
+    }
+
+    #[test]
+    fn residue_spans() {
+        let structure = "gm(Anh)-AE(Am, -H2O)J[<GG]A=gm-AEJ (-H2O) (4-3)";
+        let muropeptide = Muropeptide::new(&POLYMERIZER, structure).unwrap();
+        let text = |span: SourceSpan| &structure[span.offset()..span.offset() + span.len()];
+        let mut spans = Vec::new();
+        for Monomer { glycan, peptide } in &muropeptide.monomers {
+            for monosaccharide in glycan {
+                spans.push(text(monosaccharide.span));
+            }
+            for amino_acid in peptide {
+                spans.push(text(amino_acid.span));
+                if let Some(lateral_chain) = &amino_acid.lateral_chain {
+                    spans.push(text(lateral_chain.span));
+                }
+            }
+        }
+        assert_eq!(
+            spans,
+            [
+                "g",
+                "m(Anh)",
+                "A",
+                "E(Am, -H2O)",
+                "J",
+                "GG",
+                "A",
+                "g",
+                "m",
+                "A",
+                "E",
+                "J"
+            ]
+        );
+    }
+}
@@ -66,6 +66,19 @@ impl<E: Diagnostic> Diagnostic for LabeledError<E> {
 }
 
 impl<E> LabeledError<E> {
+    // NOTE: Returns the kind of every error in this tree, following each error's source and every branch that failed
+    #[must_use]
+    pub fn kinds(&self) -> Vec<&E> {
+        match &self.error {
+            ErrorTree::Node { kind, source } => {
+                let mut kinds = vec![kind];
+                kinds.extend(source.iter().flat_map(|source| source.kinds()));
+                kinds
+            }
+            ErrorTree::Branch(alternatives) => alternatives.iter().flat_map(Self::kinds).collect(),
+        }
+    }
+
     fn bubble_labels(&mut self) {
         // FIXME: This eventually needs testing — showing that labels with different spans *don't* get merged
         fn merge_labels(labels: impl Iterator<Item = LabeledSpan>) -> Vec<LabeledSpan> {
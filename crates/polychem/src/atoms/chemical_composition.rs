@@ -1,6 +1,7 @@
 use std::{
     fmt::{self, Display, Formatter},
     hash::{Hash, Hasher},
+    ops::{Add, Mul, Sub},
 };
 
 // External Crate Imports
@@ -8,8 +9,9 @@ use nom_miette::final_parser;
 
 // Local Crate Imports
 use crate::{
-    parsers::chemical_composition::chemical_composition, AtomicDatabase, AverageMass, Charge,
-    Charged, ChemicalComposition, Element, Mass, Massive, MonoisotopicMass, Result,
+    errors::PolychemError, parsers::chemical_composition::chemical_composition, AtomicDatabase,
+    AverageMass, Charge, Charged, ChemicalComposition, Count, Element, Formulaic, Mass, Massive,
+    MolecularFormula, MonoisotopicMass, OffsetKind, Result,
 };
 
 // Public API ==========================================================================================================
@@ -19,6 +21,136 @@ impl<'a> ChemicalComposition<'a> {
         let mut parser = final_parser(chemical_composition(db));
         parser(formula.as_ref()).map_err(|e| Box::new(e.into()))
     }
+
+    // NOTE: Only counts atoms exactly matching `element`, so `count("C")` doesn't include any `[13C]` and vice versa
+    #[must_use]
+    pub fn count(&self, element: impl AsRef<str>) -> u32 {
+        let element = element.as_ref();
+        // PERF: Formatting every element is a bit wasteful, but saves looking `element` up in an `AtomicDatabase`
+        self.chemical_formula
+            .iter()
+            .filter(|(other, _)| other.to_string() == element)
+            .map(|&(_, count)| u32::from(count))
+            .sum()
+    }
+
+    // NOTE: Merges repeated elements and isotopes (like the extra `O` and `H`s of `C3H4O2OHH`) into a single entry,
+    // keeping each in the position it first appeared
+    pub fn normalize(&mut self) {
+        let mut merged: Vec<(Element<'a>, Count)> = Vec::with_capacity(self.chemical_formula.len());
+        for (element, count) in self.chemical_formula.drain(..) {
+            if let Some((_, total)) = merged.iter_mut().find(|(other, _)| *other == element) {
+                *total = *total + count;
+            } else {
+                merged.push((element, count));
+            }
+        }
+        self.chemical_formula = merged;
+    }
+}
+
+// Crate API ===========================================================================================================
+
+impl<'a> ChemicalComposition<'a> {
+    // NOTE: Returns the only atom in this composition, as long as there is exactly one of them and no particle offset
+    pub(crate) fn into_single_atom(mut self) -> Option<Element<'a>> {
+        match self.chemical_formula.pop() {
+            Some((element, count))
+                if self.chemical_formula.is_empty()
+                    && self.particle_offset.is_none()
+                    && u32::from(count) == 1 =>
+            {
+                Some(element)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Arithmetic Trait Implementations ====================================================================================
+
+// NOTE: The sum or difference of two compositions can have negative counts, or offset more than one kind of particle,
+// neither of which can be represented by a `ChemicalComposition` — that's why these return a `Result`. Successful
+// results are always normalized
+impl Add for ChemicalComposition<'_> {
+    type Output = Result<Self>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::try_from(self.formula() + rhs.formula())
+    }
+}
+
+impl Sub for ChemicalComposition<'_> {
+    type Output = Result<Self>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::try_from(self.formula() - rhs.formula())
+    }
+}
+
+impl Mul<Count> for ChemicalComposition<'_> {
+    type Output = Self;
+
+    fn mul(mut self, rhs: Count) -> Self::Output {
+        for (_, count) in &mut self.chemical_formula {
+            *count = *count * rhs;
+        }
+
+        if let Some((_, count, _)) = &mut self.particle_offset {
+            *count = *count * rhs;
+        }
+
+        self
+    }
+}
+
+impl<'a> Mul<ChemicalComposition<'a>> for Count {
+    type Output = ChemicalComposition<'a>;
+
+    fn mul(self, rhs: ChemicalComposition<'a>) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl<'a> TryFrom<MolecularFormula<'a>> for ChemicalComposition<'a> {
+    type Error = Box<PolychemError>;
+
+    fn try_from(formula: MolecularFormula<'a>) -> Result<Self> {
+        let to_count = |item: &dyn Display, count: i64| {
+            u32::try_from(count)
+                .ok()
+                .and_then(Count::new)
+                .ok_or_else(|| PolychemError::composition_count(&formula, &item, count))
+        };
+
+        let chemical_formula: Vec<_> = formula
+            .elements
+            .iter()
+            .map(|&(ref element, n)| Ok((element.clone(), to_count(element, n)?)))
+            .collect::<Result<_, PolychemError>>()?;
+
+        let particle_offset = match formula.particles.as_slice() {
+            [] => None,
+            // NOTE: Standalone particle offsets (like `2p`) are always added, so they can't be negative
+            &[(ref particle, n)] if n < 0 && chemical_formula.is_empty() => {
+                return Err(PolychemError::composition_count(&formula, particle, n).into());
+            }
+            &[(ref particle, n)] => {
+                let offset_kind = if n < 0 {
+                    OffsetKind::Remove
+                } else {
+                    OffsetKind::Add
+                };
+                Some((offset_kind, to_count(particle, n.abs())?, particle.clone()))
+            }
+            _ => return Err(PolychemError::multiple_particle_offsets(&formula).into()),
+        };
+
+        Ok(Self {
+            chemical_formula,
+            particle_offset,
+        })
+    }
 }
 
 // Massive, Charged, and Mz Trait Implementations ======================================================================
@@ -377,4 +509,122 @@ mod tests {
             Charge(5)
         );
     }
+
+    #[test]
+    fn composition_counts() {
+        let composition = ChemicalComposition::new(&DB, "C3H4O2OHH").unwrap();
+        assert_eq!(composition.count("C"), 3);
+        assert_eq!(composition.count("H"), 6);
+        assert_eq!(composition.count("O"), 3);
+        assert_eq!(composition.count("N"), 0);
+        assert_eq!(composition.count("Xx"), 0);
+
+        let isotopes = ChemicalComposition::new(&DB, "C[13C]11H10O[15N]2+p").unwrap();
+        assert_eq!(isotopes.count("C"), 1);
+        assert_eq!(isotopes.count("[13C]"), 11);
+        assert_eq!(isotopes.count("[15N]"), 2);
+        assert_eq!(isotopes.count("N"), 0);
+        assert_eq!(isotopes.count("p"), 0);
+    }
+
+    #[test]
+    fn composition_normalize() {
+        let formulae = [
+            ("C3H4O2OHH", "C3H6O3"),
+            ("C2[13C]2H6C", "C3[13C]2H6"),
+            ("NH3+p", "NH3+p"),
+            ("OHHO-2e", "O2H2-2e"),
+            ("3e", "3e"),
+        ];
+        for (formula, normalized) in formulae {
+            let mut composition = ChemicalComposition::new(&DB, formula).unwrap();
+            let mass = composition.monoisotopic_mass();
+            composition.normalize();
+            assert_eq!(composition.to_string(), normalized);
+            assert_eq!(composition.monoisotopic_mass(), mass);
+        }
+    }
+
+    #[test]
+    fn composition_arithmetic() {
+        let composition = |formula| ChemicalComposition::new(&DB, formula).unwrap();
+        let water = composition("H2O");
+        let alanine = composition("C3H7NO2");
+
+        let sum = (alanine.clone() + water.clone()).unwrap();
+        assert_eq!(sum.to_string(), "C3H9NO3");
+        assert_eq!(
+            sum.monoisotopic_mass(),
+            alanine.monoisotopic_mass() + water.monoisotopic_mass()
+        );
+        let difference = (alanine.clone() - water.clone()).unwrap();
+        assert_eq!(difference.to_string(), "C3H5NO");
+        assert_eq!(
+            difference.monoisotopic_mass(),
+            alanine.monoisotopic_mass() - water.monoisotopic_mass()
+        );
+
+        assert_eq!(
+            (water.clone() - water.clone()).unwrap(),
+            ChemicalComposition::default()
+        );
+        assert_eq!(
+            (composition("C3H4O2") + composition("OHH")).unwrap(),
+            composition("C3H6O3")
+        );
+        assert_eq!(
+            (composition("NH3+p") + composition("OH-p")).unwrap(),
+            composition("NH4O")
+        );
+        assert_eq!(
+            (water.clone() + composition("2p")).unwrap(),
+            composition("H2O+2p")
+        );
+        assert_eq!(
+            (composition("H2O+2p") - composition("p")).unwrap(),
+            composition("H2O+p")
+        );
+        assert_eq!(
+            (water.clone() - composition("p")).unwrap(),
+            composition("H2O-p")
+        );
+        let loss = (composition("H2O+p") - water.clone()).unwrap();
+        assert_eq!(loss, composition("p"));
+        assert_eq!(loss.charge(), Charge(1));
+
+        let three = Count::new(3).unwrap();
+        assert_eq!(three * alanine.clone(), composition("C9H21N3O6"));
+        assert_eq!(composition("H2O+p") * three, composition("H6O3+3p"));
+        assert_eq!(three * composition("2e"), composition("6e"));
+    }
+
+    #[test]
+    fn composition_arithmetic_errors() {
+        let composition = |formula| ChemicalComposition::new(&DB, formula).unwrap();
+        let water = composition("H2O");
+
+        let error = (water.clone() - composition("C3H7NO2")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the formula \"-C3H5NO\" can't be represented as a chemical composition, since it contains -5 H"
+        );
+        let error = (water.clone() - composition("H2O+p")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the formula \"-p\" can't be represented as a chemical composition, since it contains -1 p"
+        );
+        let error = (composition("NH3+p") + composition("e")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the formula \"H3N+e+p\" offsets more than one kind of particle, but a chemical composition can only \
+            offset one"
+        );
+
+        let formula = composition("C3H7NO2").formula() - water.formula();
+        assert_eq!(
+            ChemicalComposition::try_from(formula).unwrap(),
+            composition("C3H5NO")
+        );
+        assert!(ChemicalComposition::try_from(-water.formula()).is_err());
+    }
 }
@@ -1,7 +1,7 @@
 use std::{
     fmt::{self, Display, Formatter},
     num::NonZero,
-    ops::Mul,
+    ops::{Add, Mul},
 };
 
 use rust_decimal::Decimal;
@@ -48,6 +48,23 @@ impl From<Count> for usize {
     }
 }
 
+// NOTE: Counts saturate instead of overflowing — nothing sensible has over four-billion copies of a single atom
+impl Add for Count {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.0.saturating_add(rhs.0.get()))
+    }
+}
+
+impl Mul for Count {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self(self.0.saturating_mul(rhs.0))
+    }
+}
+
 macro_rules! mass_mul_impls {
     // NOTE: `$mass_type` is a `tt` since it actually has to play the role of both a type (`ty`) and expression (`expr`)
     // in this impl, and `tt` appears to be the only way to pull off that sort of "metavariable polymorphism"
@@ -0,0 +1,453 @@
+use itertools::Itertools;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use crate::{
+    errors::PolychemError, AtomicDatabase, ChemicalComposition, Element, ElementBounds,
+    FormulaCandidate, FormulaSearch, MassTolerance, Massive, MolecularFormula, MonoisotopicMass,
+    Result,
+};
+
+// NOTE: Generous enough for most muropeptides and their unknown modifications, whilst keeping searches quick
+const DEFAULT_BOUNDS: [(&str, u32, u32); 8] = [
+    ("C", 0, 150),
+    ("H", 0, 300),
+    ("N", 0, 40),
+    ("O", 0, 80),
+    ("P", 0, 4),
+    ("S", 0, 4),
+    ("Na", 0, 2),
+    ("K", 0, 2),
+];
+
+// Public API ==========================================================================================================
+
+impl<'a> FormulaSearch<'a> {
+    // NOTE: Searches start out bounded to CHNOPS, plus Na and K, only return candidates with a non-negative RDBE, and
+    // apply the nitrogen rule
+    pub fn new(atomic_db: &'a AtomicDatabase) -> Result<Self> {
+        let mut search = Self {
+            atomic_db,
+            bounds: Vec::new(),
+            rdbe: Decimal::ZERO..=Decimal::MAX,
+            nitrogen_rule: true,
+        };
+        for (element, min, max) in DEFAULT_BOUNDS {
+            search.set_bounds(element, min, max)?;
+        }
+
+        Ok(search)
+    }
+
+    #[must_use]
+    pub const fn atomic_db(&self) -> &'a AtomicDatabase {
+        self.atomic_db
+    }
+
+    // NOTE: Replaces the bounds of any element already being searched — setting `max` to 0 removes an element from
+    // the search entirely
+    pub fn set_bounds(
+        &mut self,
+        element: impl AsRef<str>,
+        min: u32,
+        max: u32,
+    ) -> Result<&mut Self> {
+        let element = self.parse_element(element.as_ref())?;
+        if min > max {
+            return Err(PolychemError::invalid_element_bounds(&element, min, max).into());
+        }
+
+        self.bounds.retain(|bounds| bounds.element != element);
+        if max > 0 {
+            self.bounds.push(ElementBounds { element, min, max });
+        }
+
+        Ok(self)
+    }
+
+    pub fn set_rdbe(&mut self, min: Decimal, max: Decimal) -> &mut Self {
+        self.rdbe = min..=max;
+        self
+    }
+
+    // NOTE: The nitrogen rule only holds for neutral, even-electron molecules built from the most abundant isotopes
+    // of each element — for those molecules, this also rules out any candidates with a fractional RDBE
+    pub fn set_nitrogen_rule(&mut self, enabled: bool) -> &mut Self {
+        self.nitrogen_rule = enabled;
+        self
+    }
+
+    // NOTE: Candidates are returned in order of increasing absolute mass error
+    #[must_use]
+    pub fn search(&self, mass: Decimal, tolerance: MassTolerance) -> Vec<FormulaCandidate<'a>> {
+        let window = tolerance.window(mass);
+        let atoms = SearchAtom::from_bounds(&self.bounds);
+        let mut counts = vec![0; atoms.len()];
+        let mut candidates = Vec::new();
+
+        decompose(
+            &atoms,
+            0,
+            Decimal::ZERO,
+            (mass - window, mass + window),
+            &mut counts,
+            &mut |counts, theoretical| {
+                if let Some(candidate) = self.candidate(&atoms, counts, mass, theoretical) {
+                    candidates.push(candidate);
+                }
+            },
+        );
+
+        candidates
+            .into_iter()
+            .sorted_by_cached_key(|candidate| {
+                (candidate.ppm_error.abs(), candidate.formula.to_string())
+            })
+            .collect()
+    }
+}
+
+impl MolecularFormula<'_> {
+    // NOTE: Ring and double-bond equivalents are calculated from the valence of each element, so this returns `None`
+    // for any formulae containing elements with an unknown valence
+    #[must_use]
+    pub fn rdbe(&self) -> Option<Decimal> {
+        let bonds: Option<Decimal> = self
+            .elements
+            .iter()
+            .map(|&(ref element, count)| {
+                Some(Decimal::from(count) * (valence(element)? - Decimal::TWO))
+            })
+            .sum();
+        bonds.map(|bonds| Decimal::ONE + bonds / Decimal::TWO)
+    }
+}
+
+// Private Types and Helper Functions ==================================================================================
+
+#[derive(Clone, Debug)]
+struct SearchAtom<'a, 'b> {
+    bounds: &'b ElementBounds<'a>,
+    mass: Decimal,
+    nominal_mass: i64,
+    valence: Decimal,
+    // NOTE: The smallest and largest masses that could be contributed by the atoms searched after this one
+    remaining_min: Decimal,
+    remaining_max: Decimal,
+}
+
+impl<'a, 'b> SearchAtom<'a, 'b> {
+    // PERF: Searching the heaviest atoms first means the lightest atom (usually hydrogen) is searched last, and its
+    // count can be read straight off of the remaining mass, instead of being enumerated
+    fn from_bounds(bounds: &'b [ElementBounds<'a>]) -> Vec<Self> {
+        let mut atoms: Vec<_> = bounds
+            .iter()
+            .map(|bounds| {
+                let mass = bounds.element.monoisotopic_mass().0;
+                // SAFETY: Elements without a valence are rejected by `FormulaSearch::set_bounds()`
+                let valence = valence(&bounds.element).unwrap();
+                Self {
+                    bounds,
+                    mass,
+                    nominal_mass: mass.round().to_i64().unwrap_or_default(),
+                    valence,
+                    remaining_min: Decimal::ZERO,
+                    remaining_max: Decimal::ZERO,
+                }
+            })
+            .sorted_by(|a, b| b.mass.cmp(&a.mass))
+            .collect();
+
+        for i in (1..atoms.len()).rev() {
+            let next = &atoms[i];
+            let remaining_min = next.remaining_min + next.mass * Decimal::from(next.bounds.min);
+            let remaining_max = next.remaining_max + next.mass * Decimal::from(next.bounds.max);
+            atoms[i - 1].remaining_min = remaining_min;
+            atoms[i - 1].remaining_max = remaining_max;
+        }
+
+        atoms
+    }
+}
+
+impl<'a> FormulaSearch<'a> {
+    fn parse_element(&self, formula: &str) -> Result<Element<'a>> {
+        let element = ChemicalComposition::new(self.atomic_db, formula)?
+            .into_single_atom()
+            .ok_or_else(|| PolychemError::invalid_search_element(formula))?;
+        if valence(&element).is_none() {
+            return Err(PolychemError::unknown_valence(&element).into());
+        }
+
+        Ok(element)
+    }
+
+    fn candidate(
+        &self,
+        atoms: &[SearchAtom<'a, '_>],
+        counts: &[u32],
+        mass: Decimal,
+        theoretical: Decimal,
+    ) -> Option<FormulaCandidate<'a>> {
+        // NOTE: A formula without any atoms isn't much of a candidate
+        if theoretical.is_zero() {
+            return None;
+        }
+
+        let bonds: Decimal = atoms
+            .iter()
+            .zip(counts)
+            .map(|(atom, &count)| Decimal::from(count) * (atom.valence - Decimal::TWO))
+            .sum();
+        let rdbe = Decimal::ONE + bonds / Decimal::TWO;
+        if !self.rdbe.contains(&rdbe) {
+            return None;
+        }
+
+        if self.nitrogen_rule {
+            let nominal_mass: i64 = atoms
+                .iter()
+                .zip(counts)
+                .map(|(atom, &count)| atom.nominal_mass * i64::from(count))
+                .sum();
+            let nitrogen: u32 = atoms
+                .iter()
+                .zip(counts)
+                .filter(|(atom, _)| {
+                    let element = &atom.bounds.element;
+                    element.symbol == "N" && element.mass_number.is_none()
+                })
+                .map(|(_, &count)| count)
+                .sum();
+            if nominal_mass % 2 != i64::from(nitrogen % 2) {
+                return None;
+            }
+        }
+
+        let elements = atoms
+            .iter()
+            .zip(counts)
+            .filter(|(_, &count)| count > 0)
+            .map(|(atom, &count)| (atom.bounds.element.clone(), i64::from(count)))
+            .collect();
+        let formula = MolecularFormula {
+            elements,
+            particles: Vec::new(),
+        };
+
+        Some(FormulaCandidate {
+            formula,
+            monoisotopic_mass: MonoisotopicMass(theoretical),
+            ppm_error: (mass - theoretical) / theoretical * Decimal::from(1_000_000),
+            rdbe,
+        })
+    }
+}
+
+// NOTE: Recursively enumerates every count of every atom that keeps the total mass within `low` and `high` — counts
+// too small to reach `low` (even with the most of every remaining atom), or too large to stay under `high` (even with
+// the least of every remaining atom), are skipped entirely
+fn decompose(
+    atoms: &[SearchAtom],
+    depth: usize,
+    partial: Decimal,
+    (low, high): (Decimal, Decimal),
+    counts: &mut [u32],
+    found: &mut impl FnMut(&[u32], Decimal),
+) {
+    let Some(atom) = atoms.get(depth) else {
+        found(counts, partial);
+        return;
+    };
+
+    let fewest = ((low - partial - atom.remaining_max) / atom.mass).ceil();
+    let most = ((high - partial - atom.remaining_min) / atom.mass).floor();
+    let fewest = fewest.max(atom.bounds.min.into());
+    let most = most.min(atom.bounds.max.into());
+    // NOTE: If no count of this atom could land within the mass window, then there is nothing left to search
+    if fewest > most {
+        return;
+    }
+
+    // SAFETY: Both counts are now clamped to the `u32` bounds of this atom, so they can't fail to convert
+    for count in fewest.to_u32().unwrap()..=most.to_u32().unwrap() {
+        counts[depth] = count;
+        let partial = partial + atom.mass * Decimal::from(count);
+        decompose(atoms, depth + 1, partial, (low, high), counts, found);
+    }
+}
+
+fn valence(element: &Element) -> Option<Decimal> {
+    let valence = match element.symbol {
+        "H" | "D" | "T" | "Li" | "Na" | "K" | "F" | "Cl" | "Br" | "I" => 1,
+        "O" | "S" | "Se" | "Mg" | "Ca" => 2,
+        "B" | "N" | "P" => 3,
+        "C" | "Si" => 4,
+        _ => return None,
+    };
+    Some(Decimal::from(valence))
+}
+
+// Module Tests ========================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use rust_decimal_macros::dec;
+
+    use crate::Formulaic;
+
+    use super::*;
+
+    static DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+
+    const GM_AEJA: Decimal = dec!(941.40770207247);
+
+    fn formulae(candidates: &[FormulaCandidate]) -> Vec<String> {
+        candidates
+            .iter()
+            .map(|candidate| candidate.formula.to_string())
+            .collect()
+    }
+
+    fn chno_search() -> FormulaSearch<'static> {
+        let mut search = FormulaSearch::new(&DB).unwrap();
+        for element in ["P", "S", "Na", "K"] {
+            search.set_bounds(element, 0, 0).unwrap();
+        }
+        search
+    }
+
+    #[test]
+    fn small_molecules() {
+        let search = FormulaSearch::new(&DB).unwrap();
+        let alanine = search.search(dec!(89.04767846918), MassTolerance::Ppm(dec!(5)));
+        assert_eq!(formulae(&alanine), ["C3H7NO2"]);
+        assert_eq!(alanine[0].ppm_error, Decimal::ZERO);
+        assert_eq!(alanine[0].rdbe, Decimal::ONE);
+        assert_eq!(
+            alanine[0].monoisotopic_mass,
+            MonoisotopicMass(dec!(89.04767846918))
+        );
+
+        let shifted = search.search(dec!(89.0481), MassTolerance::Da(dec!(0.001)));
+        assert_eq!(formulae(&shifted), ["C3H7NO2"]);
+        assert!(shifted[0].ppm_error > Decimal::ZERO);
+
+        assert!(search
+            .search(Decimal::ZERO, MassTolerance::Da(dec!(0.5)))
+            .is_empty());
+    }
+
+    #[test]
+    fn muropeptides() {
+        let mut search = chno_search();
+        let candidates = search.search(GM_AEJA, MassTolerance::Ppm(dec!(2)));
+        assert_eq!(candidates.len(), 20);
+        assert_eq!(
+            formulae(&candidates[..3]),
+            ["C37H63N7O21", "C35H51N21O11", "C33H39N35O"]
+        );
+        assert_eq!(candidates[0].rdbe, dec!(10));
+
+        // NOTE: Every candidate is within the tolerance, sorted by error, and obeys the RDBE and nitrogen rules
+        assert!(candidates
+            .iter()
+            .all(|candidate| candidate.ppm_error.abs() <= dec!(2)));
+        assert!(candidates
+            .windows(2)
+            .all(|w| w[0].ppm_error.abs() <= w[1].ppm_error.abs()));
+        for candidate in &candidates {
+            assert_eq!(candidate.formula.rdbe(), Some(candidate.rdbe));
+            assert!(candidate.rdbe >= Decimal::ZERO);
+            assert!(candidate.rdbe.fract().is_zero());
+            assert_eq!(
+                candidate.formula.monoisotopic_mass(),
+                candidate.monoisotopic_mass
+            );
+        }
+
+        let radicals = search
+            .set_nitrogen_rule(false)
+            .search(GM_AEJA, MassTolerance::Ppm(dec!(2)));
+        assert_eq!(radicals.len(), 43);
+        assert!(radicals
+            .iter()
+            .any(|candidate| !candidate.rdbe.fract().is_zero()));
+
+        let saturated = search
+            .set_nitrogen_rule(true)
+            .set_rdbe(Decimal::ZERO, dec!(9))
+            .search(GM_AEJA, MassTolerance::Ppm(dec!(2)));
+        assert_eq!(saturated.len(), 4);
+        assert!(!formulae(&saturated).contains(&"C37H63N7O21".to_owned()));
+    }
+
+    #[test]
+    fn element_bounds() {
+        let mut search = FormulaSearch::new(&DB).unwrap();
+        let alanine = dec!(89.04767846918);
+        search.set_bounds("C", 0, 2).unwrap();
+        assert!(search
+            .search(alanine, MassTolerance::Ppm(dec!(5)))
+            .is_empty());
+        search.set_bounds("C", 3, 3).unwrap();
+        assert_eq!(
+            formulae(&search.search(alanine, MassTolerance::Ppm(dec!(5)))),
+            ["C3H7NO2"]
+        );
+        search.set_bounds("N", 2, 10).unwrap();
+        assert!(search
+            .search(alanine, MassTolerance::Ppm(dec!(5)))
+            .is_empty());
+
+        // NOTE: Isotopes can be searched too, but heavy isotopes break the nitrogen rule
+        let mut search = chno_search();
+        search.set_bounds("[13C]", 0, 3).unwrap();
+        let labelled = ChemicalComposition::new(&DB, "[13C]3H7NO2").unwrap();
+        assert!(search
+            .search(labelled.monoisotopic_mass().0, MassTolerance::Ppm(dec!(1)))
+            .is_empty());
+        search.set_nitrogen_rule(false);
+        let candidates = search.search(labelled.monoisotopic_mass().0, MassTolerance::Ppm(dec!(1)));
+        assert_eq!(formulae(&candidates), ["[13C]3H7NO2"]);
+    }
+
+    #[test]
+    fn invalid_bounds() {
+        let mut search = FormulaSearch::new(&DB).unwrap();
+        let error = |result: Result<&mut FormulaSearch>| result.unwrap_err().to_string();
+        assert_eq!(
+            error(search.set_bounds("H2O", 0, 1)),
+            "the composition \"H2O\" is not a single element, so it can't be used in a formula search"
+        );
+        assert_eq!(
+            error(search.set_bounds("N+p", 0, 1)),
+            "the composition \"N+p\" is not a single element, so it can't be used in a formula search"
+        );
+        assert_eq!(
+            error(search.set_bounds("Fe", 0, 1)),
+            "the valence of Fe is unknown, so it can't be used in a formula search"
+        );
+        assert_eq!(
+            error(search.set_bounds("C", 5, 1)),
+            "the lower bound of C (5) is larger than its upper bound (1)"
+        );
+        assert!(search.set_bounds("Xx", 0, 1).is_err());
+    }
+
+    #[test]
+    fn formula_rdbe() {
+        let rdbe = |formula| {
+            ChemicalComposition::new(&DB, formula)
+                .unwrap()
+                .formula()
+                .rdbe()
+        };
+        assert_eq!(rdbe("C6H6"), Some(dec!(4)));
+        assert_eq!(rdbe("C2H6"), Some(Decimal::ZERO));
+        assert_eq!(rdbe("C5H5N"), Some(dec!(4)));
+        assert_eq!(rdbe("CH3"), Some(dec!(0.5)));
+        assert_eq!(rdbe("C3H7NO2"), Some(Decimal::ONE));
+        assert_eq!(rdbe("FeCl3"), None);
+    }
+}
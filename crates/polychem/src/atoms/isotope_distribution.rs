@@ -0,0 +1,299 @@
+use itertools::Itertools;
+use rust_decimal::Decimal;
+
+use crate::{
+    Charged, Element, IsotopePeak, IsotopeResolution, MassNumber, MolecularFormula, MonoisotopicMz,
+};
+
+// Public API ==========================================================================================================
+
+impl MolecularFormula<'_> {
+    // NOTE: Peaks are returned in order of increasing m/z, and any with a relative abundance below `threshold` are
+    // pruned. The m/z values are computed using the charge of the formula itself — to get the distribution of an ion,
+    // add its charge carriers (like `+2p`) to the formula first. Uncharged formulas report their neutral masses
+    #[must_use]
+    pub fn isotope_distribution(
+        &self,
+        resolution: IsotopeResolution,
+        threshold: Decimal,
+    ) -> Vec<IsotopePeak> {
+        // NOTE: There is no sensible distribution for a formula that's missing atoms
+        if self.elements.iter().any(|&(_, count)| count < 0) {
+            return Vec::new();
+        }
+
+        let isotopologues = self.elements.iter().fold(
+            vec![Isotopologue::default()],
+            |isotopologues, &(ref element, count)| {
+                let atoms =
+                    element_distribution(element, count.unsigned_abs(), resolution, threshold);
+                convolve(&isotopologues, &atoms, resolution, threshold)
+            },
+        );
+
+        let particle_mass: Decimal = self
+            .particles
+            .iter()
+            .map(|&(ref particle, count)| Decimal::from(count) * particle.mass().0)
+            .sum();
+        let charge = Decimal::from(self.charge().0.unsigned_abs().max(1));
+        let max_abundance = max_abundance(&isotopologues);
+
+        isotopologues
+            .into_iter()
+            .map(|isotopologue| IsotopePeak {
+                mz: MonoisotopicMz((isotopologue.mass + particle_mass) / charge),
+                relative_abundance: isotopologue.abundance / max_abundance,
+            })
+            .collect()
+    }
+}
+
+// Private Types and Helper Functions ==================================================================================
+
+#[derive(Copy, Clone, Debug)]
+struct Isotopologue {
+    nucleons: u64,
+    mass: Decimal,
+    abundance: Decimal,
+}
+
+impl Default for Isotopologue {
+    fn default() -> Self {
+        Self {
+            nucleons: 0,
+            mass: Decimal::ZERO,
+            abundance: Decimal::ONE,
+        }
+    }
+}
+
+// PERF: Raising the single-atom distribution to the power of `count` by repeated squaring only takes a logarithmic
+// number of convolutions
+fn element_distribution(
+    element: &Element,
+    count: u64,
+    resolution: IsotopeResolution,
+    threshold: Decimal,
+) -> Vec<Isotopologue> {
+    let mut atom = element_isotopes(element);
+    let mut distribution = vec![Isotopologue::default()];
+    let mut count = count;
+    while count > 0 {
+        if count & 1 == 1 {
+            distribution = convolve(&distribution, &atom, resolution, threshold);
+        }
+        count >>= 1;
+        if count > 0 {
+            atom = convolve(&atom, &atom, resolution, threshold);
+        }
+    }
+    distribution
+}
+
+// NOTE: Explicit isotopes (like `[13C]`) are assumed to be perfectly pure, otherwise the natural abundances are used
+fn element_isotopes(element: &Element) -> Vec<Isotopologue> {
+    let isotope = |mass_number: &MassNumber, abundance| {
+        let isotope = &element.isotopes[mass_number];
+        Isotopologue {
+            nucleons: mass_number.0.get().into(),
+            mass: isotope.relative_mass.0,
+            abundance,
+        }
+    };
+
+    if let Some(mass_number) = &element.mass_number {
+        vec![isotope(mass_number, Decimal::ONE)]
+    } else {
+        element
+            .isotopes
+            .iter()
+            .filter_map(|(mass_number, i)| Some(isotope(mass_number, i.abundance?.0)))
+            .collect()
+    }
+}
+
+// NOTE: Every pair of isotopologues is combined, then those falling into the same peak are merged and the least
+// abundant are pruned. Pruning at every step (not just at the end) keeps the number of isotopologues manageable
+fn convolve(
+    a: &[Isotopologue],
+    b: &[Isotopologue],
+    resolution: IsotopeResolution,
+    threshold: Decimal,
+) -> Vec<Isotopologue> {
+    let combined = a
+        .iter()
+        .cartesian_product(b)
+        .map(|(a, b)| Isotopologue {
+            nucleons: a.nucleons + b.nucleons,
+            mass: a.mass + b.mass,
+            abundance: a.abundance * b.abundance,
+        })
+        .sorted_unstable_by_key(|i| (i.nucleons, i.mass))
+        .coalesce(|a, b| {
+            let same_peak = match resolution {
+                IsotopeResolution::Coarse => a.nucleons == b.nucleons,
+                IsotopeResolution::Fine => a.mass == b.mass,
+            };
+            if same_peak {
+                Ok(merge(a, b))
+            } else {
+                Err((a, b))
+            }
+        });
+
+    let mut isotopologues: Vec<_> = combined.collect();
+    let cutoff = max_abundance(&isotopologues) * threshold;
+    isotopologues.retain(|i| i.abundance >= cutoff);
+    isotopologues
+}
+
+// NOTE: Merged isotopologues sit at their abundance-weighted average mass
+fn merge(a: Isotopologue, b: Isotopologue) -> Isotopologue {
+    let abundance = a.abundance + b.abundance;
+    let mass = if a.mass == b.mass || abundance.is_zero() {
+        a.mass
+    } else {
+        (a.mass * a.abundance + b.mass * b.abundance) / abundance
+    };
+    Isotopologue {
+        nucleons: a.nucleons,
+        mass,
+        abundance,
+    }
+}
+
+fn max_abundance(isotopologues: &[Isotopologue]) -> Decimal {
+    isotopologues
+        .iter()
+        .map(|i| i.abundance)
+        .max()
+        .unwrap_or(Decimal::ONE)
+}
+
+// Module Tests ========================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use rust_decimal_macros::dec;
+
+    use crate::{AtomicDatabase, ChargedParticle, ChemicalComposition, Count, Formulaic, Massive};
+
+    use super::*;
+
+    static DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+
+    fn formula(composition: &str) -> MolecularFormula<'static> {
+        ChemicalComposition::new(&DB, composition)
+            .unwrap()
+            .formula()
+    }
+
+    fn mzs(peaks: &[IsotopePeak]) -> Vec<Decimal> {
+        peaks.iter().map(|peak| peak.mz.into()).collect()
+    }
+
+    #[test]
+    fn single_atoms() {
+        let carbon = formula("C").isotope_distribution(IsotopeResolution::Coarse, Decimal::ZERO);
+        assert_eq!(mzs(&carbon), [dec!(12), dec!(13.00335483507)]);
+        assert_eq!(carbon[0].relative_abundance, Decimal::ONE);
+        assert_eq!(carbon[1].relative_abundance, dec!(0.0107) / dec!(0.9893));
+
+        // NOTE: Explicit isotopes don't have a distribution
+        let isotopes =
+            formula("[13C]D2").isotope_distribution(IsotopeResolution::Fine, Decimal::ZERO);
+        assert_eq!(mzs(&isotopes), [dec!(17.03155839131)]);
+        assert_eq!(isotopes[0].relative_abundance, Decimal::ONE);
+    }
+
+    #[test]
+    fn coarse_and_fine() {
+        let glycine = formula("C2H5NO2");
+        let coarse = glycine.isotope_distribution(IsotopeResolution::Coarse, Decimal::ZERO);
+        let fine = glycine.isotope_distribution(IsotopeResolution::Fine, Decimal::ZERO);
+        assert!(fine.len() > coarse.len());
+        assert!(coarse.windows(2).all(|w| w[0].mz < w[1].mz));
+        assert!(fine.windows(2).all(|w| w[0].mz < w[1].mz));
+
+        // NOTE: The monoisotopic peak is the same in both, and is the most abundant for small molecules
+        let monoisotopic_mass: Decimal = glycine.monoisotopic_mass().into();
+        for peaks in [&coarse, &fine] {
+            assert_eq!(Decimal::from(peaks[0].mz), monoisotopic_mass);
+            assert_eq!(peaks[0].relative_abundance, Decimal::ONE);
+        }
+
+        // NOTE: Fine peaks add up to the coarse peak of the same nominal mass, and to the same average mass overall
+        let total =
+            |peaks: &[IsotopePeak]| peaks.iter().map(|p| p.relative_abundance).sum::<Decimal>();
+        assert!((total(&coarse) - total(&fine)).abs() < dec!(0.00000000000000000001));
+        let average = |peaks: &[IsotopePeak]| {
+            peaks
+                .iter()
+                .map(|p| Decimal::from(p.mz) * p.relative_abundance)
+                .sum::<Decimal>()
+                / total(peaks)
+        };
+        let average_mass: Decimal = glycine.average_mass().into();
+        assert!((average(&coarse) - average_mass).abs() < dec!(1e-9));
+        assert!((average(&fine) - average_mass).abs() < dec!(1e-9));
+
+        // NOTE: The M+1 isotopologues (mostly ¹³C and ¹⁵N) are resolved in the fine distribution
+        let m1 = |peaks: &[IsotopePeak]| {
+            peaks
+                .iter()
+                .filter(|p| {
+                    (Decimal::from(p.mz) - monoisotopic_mass - Decimal::ONE).abs() < dec!(0.1)
+                })
+                .count()
+        };
+        assert_eq!(m1(&coarse), 1);
+        assert!(m1(&fine) >= 3);
+    }
+
+    #[test]
+    fn large_molecules() {
+        let trimer = formula("C111H185N21O61");
+        let peaks = trimer.isotope_distribution(IsotopeResolution::Coarse, dec!(0.001));
+        assert!(peaks.iter().all(|p| p.relative_abundance >= dec!(0.001)));
+
+        // NOTE: With over a hundred carbons, the M+1 peak is more abundant than the monoisotopic peak
+        assert!(peaks[0].relative_abundance < Decimal::ONE);
+        assert_eq!(peaks[1].relative_abundance, Decimal::ONE);
+        let monoisotopic_mass: Decimal = trimer.monoisotopic_mass().into();
+        assert_eq!(Decimal::from(peaks[0].mz), monoisotopic_mass);
+
+        let pruned = trimer.isotope_distribution(IsotopeResolution::Coarse, dec!(0.5));
+        assert!(pruned.len() < peaks.len());
+        assert!(pruned.iter().all(|p| p.relative_abundance >= dec!(0.5)));
+    }
+
+    #[test]
+    fn charged_distributions() {
+        let neutral = formula("C37H63N7O21");
+        let proton = formula("p");
+        let two = Count::new(2).unwrap();
+        let ion = neutral.clone() + two * proton;
+
+        let neutral_peaks = neutral.isotope_distribution(IsotopeResolution::Coarse, dec!(0.01));
+        let ion_peaks = ion.isotope_distribution(IsotopeResolution::Coarse, dec!(0.01));
+        assert_eq!(neutral_peaks.len(), ion_peaks.len());
+        assert_eq!(ion_peaks[0].mz, ion.monoisotopic_mz().unwrap());
+
+        // NOTE: Doubly charged peaks are spaced about 0.5 m/z apart
+        let spacing = Decimal::from(ion_peaks[1].mz) - Decimal::from(ion_peaks[0].mz);
+        assert!((spacing - dec!(0.5)).abs() < dec!(0.01));
+        for (neutral, ion) in neutral_peaks.iter().zip(&ion_peaks) {
+            assert_eq!(neutral.relative_abundance, ion.relative_abundance);
+        }
+    }
+
+    #[test]
+    fn missing_atoms() {
+        let loss = -formula("H2O");
+        assert!(loss
+            .isotope_distribution(IsotopeResolution::Coarse, Decimal::ZERO)
+            .is_empty());
+    }
+}
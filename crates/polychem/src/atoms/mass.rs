@@ -1,4 +1,5 @@
-use crate::{Abundance, AverageMass, Mass, MonoisotopicMass};
+use crate::{Abundance, AverageMass, Mass, MassTolerance, MonoisotopicMass};
+use rust_decimal::Decimal;
 use std::ops::Mul;
 
 macro_rules! mass_conversion_impls {
@@ -27,3 +28,37 @@ impl Mul<Abundance> for Mass {
         Self(self.0 * rhs.0)
     }
 }
+
+impl MassTolerance {
+    // NOTE: Returns the largest allowed difference (in Da) between an observed mass and the `theoretical` one
+    #[must_use]
+    pub fn window(self, theoretical: Decimal) -> Decimal {
+        match self {
+            Self::Ppm(ppm) => theoretical.abs() * ppm / Decimal::from(1_000_000),
+            Self::Da(da) => da,
+        }
+    }
+
+    #[must_use]
+    pub fn contains(self, theoretical: Decimal, observed: Decimal) -> bool {
+        (observed - theoretical).abs() <= self.window(theoretical)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    #[test]
+    fn tolerance_window() {
+        assert_eq!(MassTolerance::Ppm(dec!(10)).window(dec!(500)), dec!(0.005));
+        assert_eq!(MassTolerance::Ppm(dec!(10)).window(dec!(-500)), dec!(0.005));
+        assert_eq!(MassTolerance::Da(dec!(0.02)).window(dec!(500)), dec!(0.02));
+        assert!(MassTolerance::Ppm(dec!(10)).contains(dec!(500), dec!(500.004)));
+        assert!(MassTolerance::Ppm(dec!(10)).contains(dec!(500), dec!(500.005)));
+        assert!(!MassTolerance::Ppm(dec!(10)).contains(dec!(500), dec!(499.994)));
+        assert!(MassTolerance::Da(dec!(0.02)).contains(dec!(500), dec!(499.99)));
+    }
+}
@@ -4,8 +4,11 @@ pub mod chemical_composition;
 mod count;
 mod element;
 pub mod errors;
+mod formula_search;
+mod isotope_distribution;
 mod mass;
 mod mass_number;
+mod molecular_formula;
 mod mz;
 mod offset_kind;
 mod particle;
@@ -0,0 +1,322 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, Neg, Sub},
+};
+
+use itertools::Itertools;
+use rust_decimal::Decimal;
+
+use crate::{
+    AverageMass, Charge, Charged, ChemicalComposition, Count, Element, Formulaic, Mass, Massive,
+    MolecularFormula, MonoisotopicMass,
+};
+
+// Public API ==========================================================================================================
+
+impl MolecularFormula<'_> {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty() && self.particles.is_empty()
+    }
+}
+
+// Formulaic Trait Implementations =====================================================================================
+
+impl<'a> Formulaic<'a> for ChemicalComposition<'a> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        let mut formula = MolecularFormula::default();
+        for &(ref element, count) in &self.chemical_formula {
+            add_count(&mut formula.elements, element, count_to_i64(count));
+        }
+
+        if let Some((offset_kind, count, ref particle)) = self.particle_offset {
+            add_count(
+                &mut formula.particles,
+                particle,
+                offset_kind.offset(count_to_i64(count)),
+            );
+        }
+
+        formula
+    }
+}
+
+// Massive and Charged Trait Implementations ===========================================================================
+
+impl Massive for MolecularFormula<'_> {
+    fn monoisotopic_mass(&self) -> MonoisotopicMass {
+        self.mass(Element::monoisotopic_mass).into()
+    }
+
+    fn average_mass(&self) -> AverageMass {
+        self.mass(Element::average_mass).into()
+    }
+}
+
+impl Charged for MolecularFormula<'_> {
+    fn charge(&self) -> Charge {
+        self.particles
+            .iter()
+            .map(|&(ref particle, count)| Charge(count * particle.charge().0))
+            .sum()
+    }
+}
+
+// Arithmetic Trait Implementations ====================================================================================
+
+impl AddAssign for MolecularFormula<'_> {
+    fn add_assign(&mut self, rhs: Self) {
+        for (element, count) in &rhs.elements {
+            add_count(&mut self.elements, element, *count);
+        }
+
+        for (particle, count) in &rhs.particles {
+            add_count(&mut self.particles, particle, *count);
+        }
+    }
+}
+
+impl Add for MolecularFormula<'_> {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl Neg for MolecularFormula<'_> {
+    type Output = Self;
+
+    fn neg(mut self) -> Self::Output {
+        let counts = self.elements.iter_mut().map(|(_, count)| count);
+        for count in counts.chain(self.particles.iter_mut().map(|(_, count)| count)) {
+            *count = -*count;
+        }
+        self
+    }
+}
+
+impl Sub for MolecularFormula<'_> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self + -rhs
+    }
+}
+
+impl Sum for MolecularFormula<'_> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+impl<'a> Mul<MolecularFormula<'a>> for Count {
+    type Output = MolecularFormula<'a>;
+
+    fn mul(self, mut rhs: MolecularFormula<'a>) -> Self::Output {
+        let multiplier = count_to_i64(self);
+        let counts = rhs.elements.iter_mut().map(|(_, count)| count);
+        for count in counts.chain(rhs.particles.iter_mut().map(|(_, count)| count)) {
+            *count *= multiplier;
+        }
+        rhs
+    }
+}
+
+// Display Trait Implementation ========================================================================================
+
+// NOTE: Elements are written in Hill order — carbon first, then hydrogen, then everything else alphabetically — unless
+// there is no carbon at all, in which case every element is written alphabetically. Isotopes follow their element
+impl Display for MolecularFormula<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let has_carbon = self
+            .elements
+            .iter()
+            .any(|(element, _)| element.symbol == "C");
+        let (gained, lost): (Vec<_>, Vec<_>) = self
+            .elements
+            .iter()
+            .sorted_by_key(|(element, _)| {
+                let rank = match element.symbol {
+                    "C" if has_carbon => 0,
+                    "H" if has_carbon => 1,
+                    _ => 2,
+                };
+                (rank, element.symbol, element.mass_number)
+            })
+            .partition(|&&(_, count)| count > 0);
+
+        for (element, count) in &gained {
+            write!(f, "{element}")?;
+            write_count(f, *count)?;
+        }
+
+        // NOTE: Negative counts only show up when more atoms are lost than were ever there. Those elements are written
+        // after the rest, following a `-`, just like the offset of a `ChemicalComposition`
+        if !lost.is_empty() {
+            write!(f, "-")?;
+            for (element, count) in lost {
+                write!(f, "{element}")?;
+                write_count(f, *count)?;
+            }
+        }
+
+        let particles = self
+            .particles
+            .iter()
+            .sorted_by_key(|(particle, _)| particle.symbol);
+        for (index, (particle, count)) in particles.enumerate() {
+            if *count < 0 {
+                write!(f, "-")?;
+            } else if index > 0 || !self.elements.is_empty() {
+                write!(f, "+")?;
+            }
+            write_count(f, *count)?;
+            write!(f, "{particle}")?;
+        }
+
+        Ok(())
+    }
+}
+
+// Private Helper Functions ============================================================================================
+
+impl<'a> MolecularFormula<'a> {
+    pub(crate) fn element_count(&self, element: &Element<'a>) -> i64 {
+        self.elements
+            .iter()
+            .find(|(other, _)| other == element)
+            .map_or(0, |&(_, count)| count)
+    }
+
+    pub(crate) fn add_element(&mut self, element: &Element<'a>, count: i64) {
+        add_count(&mut self.elements, element, count);
+    }
+
+    fn mass<T: Into<Mass>>(&self, accessor: impl Fn(&Element<'a>) -> T) -> Mass {
+        let element_masses = self.elements.iter().map(|&(ref element, count)| {
+            let mass: Mass = accessor(element).into();
+            Mass(Decimal::from(count) * mass.0)
+        });
+
+        let particle_masses = self
+            .particles
+            .iter()
+            .map(|&(ref particle, count)| Mass(Decimal::from(count) * particle.mass().0));
+
+        element_masses.chain(particle_masses).sum()
+    }
+}
+
+// NOTE: Merges `count` into any existing entry for `item`, removing entries that drop to zero
+fn add_count<T: Clone + Eq>(counts: &mut Vec<(T, i64)>, item: &T, count: i64) {
+    if let Some(index) = counts.iter().position(|(other, _)| other == item) {
+        counts[index].1 += count;
+        if counts[index].1 == 0 {
+            counts.remove(index);
+        }
+    } else if count != 0 {
+        counts.push((item.clone(), count));
+    }
+}
+
+fn count_to_i64(count: Count) -> i64 {
+    i64::from(u32::from(count))
+}
+
+fn write_count(f: &mut Formatter<'_>, count: i64) -> fmt::Result {
+    let count = count.unsigned_abs();
+    if count > 1 {
+        write!(f, "{count}")?;
+    }
+    Ok(())
+}
+
+// Module Tests ========================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+
+    use crate::AtomicDatabase;
+
+    use super::*;
+
+    static DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+
+    fn formula(composition: &str) -> MolecularFormula<'static> {
+        ChemicalComposition::new(&DB, composition)
+            .unwrap()
+            .formula()
+    }
+
+    #[test]
+    fn hill_notation() {
+        let formulae = [
+            ("2p", "2p"),
+            ("C11H10ON2", "C11H10N2O"),
+            ("C37H63N7O21+p", "C37H63N7O21+p"),
+            ("C3H4O2OHH", "C3H6O3"),
+            ("Ca-2e", "Ca-2e"),
+            ("Cr2O7+2e", "Cr2O7+2e"),
+            ("D2O", "D2O"),
+            ("HOCl", "ClHO"),
+            ("NH3+p", "H3N+p"),
+            ("OH+e", "HO+e"),
+            ("[13C]11H10O[15N]2", "[13C]11H10[15N]2O"),
+            ("C2[13C]2H6", "C2[13C]2H6"),
+            ("[2H]2O", "[2H]2O"),
+            ("[37Cl]5-2p", "[37Cl]5-2p"),
+        ];
+        for (composition, hill) in formulae {
+            assert_eq!(formula(composition).to_string(), hill);
+        }
+    }
+
+    #[test]
+    fn formula_arithmetic() {
+        let water = formula("H2O");
+        let alanine = formula("C3H7NO2");
+        assert_eq!((alanine.clone() + water.clone()).to_string(), "C3H9NO3");
+        assert_eq!((alanine.clone() - water.clone()).to_string(), "C3H5NO");
+        assert_eq!((water.clone() - water.clone()).to_string(), "");
+        assert_eq!(water.clone() - water.clone(), MolecularFormula::default());
+        assert_eq!((-water.clone()).to_string(), "-H2O");
+        assert_eq!((formula("C3") - water.clone()).to_string(), "C3-H2O");
+        assert_eq!((formula("NH3+p") + formula("OH-p")).to_string(), "H4NO");
+        assert_eq!((formula("NH3+p") + formula("e")).to_string(), "H3N+e+p");
+
+        let three = Count::new(3).unwrap();
+        assert_eq!((three * alanine.clone()).to_string(), "C9H21N3O6");
+        assert_eq!((three * formula("H2O+p")).to_string(), "H6O3+3p");
+
+        let total: MolecularFormula = [alanine, water.clone(), -water].into_iter().sum();
+        assert_eq!(total.to_string(), "C3H7NO2");
+    }
+
+    #[test]
+    fn formula_mass_and_charge() {
+        for composition in [
+            "C37H63N7O21+p",
+            "C3H4O2OHH",
+            "[37Cl]5-2p",
+            "Ca-2e",
+            "[13C]11H10O[15N]2",
+        ] {
+            let composition = ChemicalComposition::new(&DB, composition).unwrap();
+            let formula = composition.formula();
+            assert_eq!(formula.monoisotopic_mass(), composition.monoisotopic_mass());
+            assert_eq!(formula.average_mass(), composition.average_mass());
+            assert_eq!(formula.charge(), composition.charge());
+        }
+
+        let loss = -formula("H2O+2p");
+        assert_eq!(
+            loss.monoisotopic_mass(),
+            -formula("H2O+2p").monoisotopic_mass()
+        );
+        assert_eq!(loss.charge(), Charge(-2));
+    }
+}
@@ -21,3 +21,12 @@ macro_rules! mz_div_impls {
 }
 
 mz_div_impls!(MonoisotopicMass => MonoisotopicMz, AverageMass => AverageMz);
+
+impl MonoisotopicMz {
+    // NOTE: Users still need some way to represent the m/z values they've observed with a mass spectrometer, so this
+    // is the one mass type that can be built directly from a `Decimal` — just never by accident
+    #[must_use]
+    pub const fn observed(mz: Decimal) -> Self {
+        Self(mz)
+    }
+}
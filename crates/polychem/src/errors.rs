@@ -1,9 +1,11 @@
+use std::fmt::Display;
+
 use miette::Diagnostic;
 use thiserror::Error;
 
 use crate::{
-    parsers::errors::CompositionError, polymers::errors::FindFreeGroupsError, FunctionalGroup,
-    ModificationId, ModificationInfo, ResidueId,
+    parsers::errors::CompositionError, polymers::errors::FindFreeGroupsError, Element,
+    FunctionalGroup, ModificationId, ModificationInfo, MolecularFormula, ResidueId,
 };
 
 pub type Result<T, E = Box<PolychemError>> = std::result::Result<T, E>;
@@ -87,6 +89,60 @@ pub enum PolychemError {
         "attempted to construct an offset modification with a multiplier of zero, but multipliers must be non-zero"
     )]
     ZeroMultiplier,
+
+    #[error("the formula {formula:?} can't be represented as a chemical composition, since it contains {count} {item}")]
+    #[diagnostic(help(
+        "compositions can't lose more atoms than they contain, or lose particles without containing any atoms"
+    ))]
+    CompositionCount {
+        formula: String,
+        item: String,
+        count: i64,
+    },
+
+    #[error(
+        "the formula {formula:?} offsets more than one kind of particle, but a chemical composition can only offset one"
+    )]
+    MultipleParticleOffsets { formula: String },
+
+    #[error("the composition {formula:?} is not a single element, so it can't be used in a formula search")]
+    #[diagnostic(help(
+        "elements should be written alone, without counts or particle offsets, like C or [13C]"
+    ))]
+    InvalidSearchElement { formula: String },
+
+    #[error("the valence of {element} is unknown, so it can't be used in a formula search")]
+    #[diagnostic(help(
+        "valences are needed to check the ring and double-bond equivalents (RDBE) of candidates"
+    ))]
+    UnknownValence { element: String },
+
+    #[error("the lower bound of {element} ({min}) is larger than its upper bound ({max})")]
+    InvalidElementBounds { element: String, min: u32, max: u32 },
+
+    #[error("{abbr:?} has no mass, so it can't be used to explain a mass difference")]
+    #[diagnostic(help("unknown residues (like X) have no composition, and should be left out of delta-mass searches"))]
+    MasslessDeltaComponent { abbr: String },
+
+    #[error("the composition {formula:?} is not a single isotope, so it can't be used as an isotope label")]
+    #[diagnostic(help(
+        "isotope labels should be a single atom with an explicit mass number, like [13C]"
+    ))]
+    InvalidIsotopeLabel { formula: String },
+
+    #[error(
+        "failed to label {count} atoms of residue {name} ({abbr}) with {isotope}, since it only contains {available} \
+        unlabelled {element} atoms"
+    )]
+    #[diagnostic(help("double-check which residues this label targets, or label fewer atoms"))]
+    InsufficientAtomsForLabel {
+        count: i64,
+        isotope: String,
+        name: String,
+        abbr: String,
+        available: i64,
+        element: String,
+    },
 }
 
 impl PolychemError {
@@ -174,6 +230,80 @@ impl PolychemError {
         }
     }
 
+    pub(crate) fn composition_count(
+        formula: &MolecularFormula,
+        item: &impl Display,
+        count: i64,
+    ) -> Self {
+        let formula = formula.to_string();
+        let item = item.to_string();
+
+        Self::CompositionCount {
+            formula,
+            item,
+            count,
+        }
+    }
+
+    pub(crate) fn multiple_particle_offsets(formula: &MolecularFormula) -> Self {
+        let formula = formula.to_string();
+
+        Self::MultipleParticleOffsets { formula }
+    }
+
+    pub(crate) fn invalid_search_element(formula: &str) -> Self {
+        let formula = formula.to_owned();
+
+        Self::InvalidSearchElement { formula }
+    }
+
+    pub(crate) fn unknown_valence(element: &Element) -> Self {
+        let element = element.to_string();
+
+        Self::UnknownValence { element }
+    }
+
+    pub(crate) fn invalid_element_bounds(element: &Element, min: u32, max: u32) -> Self {
+        let element = element.to_string();
+
+        Self::InvalidElementBounds { element, min, max }
+    }
+
+    pub(crate) fn massless_delta_component(abbr: &str) -> Self {
+        let abbr = abbr.to_owned();
+
+        Self::MasslessDeltaComponent { abbr }
+    }
+
+    pub(crate) fn invalid_isotope_label(formula: &str) -> Self {
+        let formula = formula.to_owned();
+
+        Self::InvalidIsotopeLabel { formula }
+    }
+
+    pub(crate) fn insufficient_atoms_for_label(
+        count: i64,
+        isotope: &Element,
+        name: &str,
+        abbr: &str,
+        available: i64,
+        element: &Element,
+    ) -> Self {
+        let isotope = isotope.to_string();
+        let name = name.to_owned();
+        let abbr = abbr.to_owned();
+        let element = element.to_string();
+
+        Self::InsufficientAtomsForLabel {
+            count,
+            isotope,
+            name,
+            abbr,
+            available,
+            element,
+        }
+    }
+
     pub(crate) fn modification_already_localized(
         modification_id: ModificationId,
         modification_info: &ModificationInfo,
@@ -7,7 +7,7 @@ pub mod polymers;
 #[cfg(test)]
 mod testing_tools;
 
-use std::num::NonZero;
+use std::{num::NonZero, ops::RangeInclusive};
 
 use derive_more::{
     Add, AddAssign, Display, From, Into, IsVariant, Neg, Sub, SubAssign, Sum, Unwrap,
@@ -22,7 +22,7 @@ use rust_decimal::Decimal;
 // FIXME: Work on what's publicly exported / part of the API! — maybe create a prelude?
 pub use atoms::atomic_database::AtomicDatabase;
 pub use errors::Result;
-pub use moieties::polymer_database::PolymerDatabase;
+pub use moieties::{polymer_database::PolymerDatabase, target::Target};
 pub use polymers::polymerizer::Polymerizer;
 
 // FIXME: I've exported a lot of things that previously weren't exported! Make sure that all of that new public API has
@@ -66,6 +66,15 @@ pub struct Residue<'a, 'p> {
     composition: &'p ChemicalComposition<'a>,
     functional_groups: HashMap<FunctionalGroup<'p>, GroupState>,
     offset_modifications: HashSet<ModificationId>,
+    // NOTE: Most residues don't specify a stereochemistry, so it's only serialized for those that do
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stereochemistry: Option<Stereochemistry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stereoisomer_of: Option<&'p str>,
+    // NOTE: The isotopes swapped into this residue by a `LabellingScheme` (like `[15N]-N`) — this is left empty for
+    // unlabelled residues, so it's skipped when serializing them
+    #[serde(skip_serializing_if = "MolecularFormula::is_empty")]
+    isotope_label: MolecularFormula<'a>,
 }
 
 // MISSING: No `Default` — should not be constructable by the user
@@ -100,6 +109,142 @@ pub struct ChemicalComposition<'a> {
     particle_offset: Option<(OffsetKind, Count, Particle<'a>)>,
 }
 
+// NOTE: Unlike a `ChemicalComposition`, the counts here can be negative and any number of particles can be offset at
+// once, so the sum of several compositions (some of which may be lost) can always be represented
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize)]
+pub struct MolecularFormula<'a> {
+    elements: Vec<(Element<'a>, i64)>,
+    particles: Vec<(Particle<'a>, i64)>,
+}
+
+// NOTE: A `Coarse` distribution groups isotopologues by their nucleon count (so ¹³C and ¹⁵N isotopologues share a peak
+// at their average mass), whilst a `Fine` distribution keeps every isotopologue with a distinct mass separate
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize)]
+pub enum IsotopeResolution {
+    Coarse,
+    Fine,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+pub struct IsotopePeak {
+    pub mz: MonoisotopicMz,
+    // NOTE: Scaled so that the most abundant peak of the distribution has a relative abundance of 1
+    pub relative_abundance: Decimal,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+pub enum MassTolerance {
+    Ppm(Decimal),
+    Da(Decimal),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FormulaSearch<'a> {
+    atomic_db: &'a AtomicDatabase,
+    bounds: Vec<ElementBounds<'a>>,
+    rdbe: RangeInclusive<Decimal>,
+    nitrogen_rule: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct ElementBounds<'a> {
+    element: Element<'a>,
+    min: u32,
+    max: u32,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize)]
+pub struct FormulaCandidate<'a> {
+    pub formula: MolecularFormula<'a>,
+    pub monoisotopic_mass: MonoisotopicMass,
+    // NOTE: Follows the usual convention of `(observed - theoretical) / theoretical`, in parts-per-million
+    pub ppm_error: Decimal,
+    // NOTE: The ring and double-bond equivalents of `formula`
+    pub rdbe: Decimal,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct LabellingScheme<'a> {
+    atomic_db: &'a AtomicDatabase,
+    labels: Vec<IsotopeLabel<'a>>,
+}
+
+// NOTE: Residues with a functional group matching `target` have `count` of their atoms swapped for `isotope`, or every
+// atom of that element when `count` is `None`
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct IsotopeLabel<'a> {
+    target: Target,
+    isotope: Element<'a>,
+    count: Option<Count>,
+}
+
+// NOTE: The `heavy` mass includes every isotope label, whilst the `light` mass is that of the same polymer built
+// without a `LabellingScheme`
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize)]
+pub struct LabelledMasses {
+    pub light: MonoisotopicMass,
+    pub heavy: MonoisotopicMass,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DeltaSearch<'a, 'p> {
+    polymer_db: &'p PolymerDatabase<'a>,
+    components: Vec<DeltaComponent<'a, 'p>>,
+    max_components: Count,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize)]
+pub enum DeltaComponent<'a, 'p> {
+    // NOTE: A residue, joined onto (or cut from) the rest of the polymer by `bond`
+    Residue(Residue<'a, 'p>, Bond<'a, 'p>),
+    Modification(NamedMod<'a, 'p>),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize)]
+pub struct DeltaExplanation<'a, 'p> {
+    // NOTE: Positive counts are gained by the observed polymer, and negative counts are lost
+    pub components: Vec<(DeltaComponent<'a, 'p>, i64)>,
+    // NOTE: The theoretical mass difference explained by `components`
+    pub monoisotopic_mass: MonoisotopicMass,
+    // NOTE: Follows the usual convention of `(observed - theoretical) / theoretical`, in parts-per-million, where the
+    // theoretical mass is that of the known polymer plus `monoisotopic_mass`
+    pub ppm_error: Decimal,
+}
+
+// NOTE: Describes the structure of a `Polymer` without any of the `ResidueId`s, `ModificationId`s, or `BondId`s it was
+// built with, so two polymers have equal `CanonicalForm`s if (and only if) they share the same structure
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct CanonicalForm<'p> {
+    // NOTE: Each connected piece of the polymer, with its residues listed in canonical order
+    components: Vec<Vec<CanonicalResidue<'p>>>,
+    unlocalized_modifications: Vec<(CanonicalModification<'p>, u32)>,
+}
+
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+struct CanonicalResidue<'p> {
+    abbr: &'p str,
+    functional_groups: Vec<(FunctionalGroup<'p>, CanonicalGroupState<'p>)>,
+    offset_modifications: Vec<(CanonicalModification<'p>, u32)>,
+    isotope_label: String,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+enum CanonicalGroupState<'p> {
+    Free,
+    Modified(&'p str),
+    // NOTE: Bonds are described by their abbreviation, then the index (within the same connected piece) and
+    // functional group of the residue at their other end
+    Donor(&'p str, usize, FunctionalGroup<'p>),
+    Acceptor(&'p str, usize, FunctionalGroup<'p>),
+}
+
+// NOTE: Offset compositions are stored in Hill notation, so `+OH2` and `+H2O` are described the same way
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+enum CanonicalModification<'p> {
+    Named(&'p str),
+    Offset(OffsetKind, String),
+}
+
 // MISSING: No `Default` — should not be constructable by the user
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize)]
 pub struct FunctionalGroup<'p> {
@@ -118,6 +263,16 @@ pub enum GroupState {
     Acceptor(BondId),
 }
 
+// NOTE: Stereoisomers are stored as separate residues in the polymer database (like `dA` and `lA`), each carrying their
+// own (optional) stereochemistry and naming the residue they're a stereoisomer of (like `A`) — the mass of a residue
+// never depends on it
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize)]
+pub enum Stereochemistry {
+    D,
+    L,
+    Meso,
+}
+
 #[derive(Clone, Eq, PartialEq, Debug, Serialize)]
 pub struct NamedMod<'a, 'p> {
     abbr: &'p str,
@@ -244,6 +399,10 @@ pub trait Charged {
     fn charge(&self) -> Charge;
 }
 
+pub trait Formulaic<'a> {
+    fn formula(&self) -> MolecularFormula<'a>;
+}
+
 // FIXME: Not super sold on that trait name...
 pub trait ChargedParticle: Massive + Charged {
     fn monoisotopic_mz(&self) -> Option<MonoisotopicMz> {
@@ -308,7 +467,7 @@ pub struct MonoisotopicMass(Decimal);
 )]
 pub struct AverageMass(Decimal);
 
-// MISSING: No `Default` — should not be constructable by the user
+// MISSING: No `Default` — should not be constructable by the user (see `MonoisotopicMz::observed()`)
 #[derive(
     Copy,
     Clone,
@@ -1,6 +1,7 @@
 use crate::{
-    AnyMod, AnyModification, AverageMass, Charge, Charged, ChemicalComposition, Count, Massive,
-    Modification, MonoisotopicMass, NamedMod, OffsetKind, OffsetMod, PolymerDatabase, Result,
+    AnyMod, AnyModification, AverageMass, Charge, Charged, ChemicalComposition, Count, Formulaic,
+    Massive, Modification, MolecularFormula, MonoisotopicMass, NamedMod, OffsetKind, OffsetMod,
+    PolymerDatabase, Result,
 };
 
 impl<'a, 'p> AnyMod<'a, 'p> {
@@ -78,6 +79,12 @@ impl Charged for AnyMod<'_, '_> {
     }
 }
 
+impl<'a> Formulaic<'a> for AnyMod<'a, '_> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        dispatch!(self, formula)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::panic;
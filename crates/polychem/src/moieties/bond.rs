@@ -1,5 +1,6 @@
 use crate::{
-    errors::PolychemError, AverageMass, Bond, Charge, Charged, Massive, MonoisotopicMass, Result,
+    errors::PolychemError, AverageMass, Bond, Charge, Charged, Formulaic, Massive,
+    MolecularFormula, MonoisotopicMass, Result,
 };
 
 use super::polymer_database::{BondDescription, PolymerDatabase};
@@ -60,6 +61,12 @@ impl Charged for Bond<'_, '_> {
     }
 }
 
+impl<'a> Formulaic<'a> for Bond<'a, '_> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        self.gained.formula() - self.lost.formula()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use once_cell::sync::Lazy;
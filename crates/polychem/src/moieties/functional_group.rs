@@ -11,6 +11,16 @@ impl<'p> FunctionalGroup<'p> {
     pub(crate) const fn new(name: &'p str, location: &'p str) -> Self {
         Self { name, location }
     }
+
+    #[must_use]
+    pub const fn name(&self) -> &'p str {
+        self.name
+    }
+
+    #[must_use]
+    pub const fn location(&self) -> &'p str {
+        self.location
+    }
 }
 
 impl<'p> From<&'p FunctionalGroupDescription> for FunctionalGroup<'p> {
@@ -39,4 +49,11 @@ mod tests {
         let c_terminal = FunctionalGroup::new("Carboxyl", "C-Terminal");
         assert_eq!(c_terminal.to_string(), r#""Carboxyl" at="C-Terminal""#);
     }
+
+    #[test]
+    fn name_and_location() {
+        let n_terminal = FunctionalGroup::new("Amino", "N-Terminal");
+        assert_eq!(n_terminal.name(), "Amino");
+        assert_eq!(n_terminal.location(), "N-Terminal");
+    }
 }
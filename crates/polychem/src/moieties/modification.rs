@@ -1,8 +1,8 @@
 use std::fmt::{self, Display, Formatter};
 
 use crate::{
-    AnyMod, AverageMass, Charge, Charged, Count, Massive, Modification, MonoisotopicMass, NamedMod,
-    OffsetMod,
+    AnyMod, AverageMass, Charge, Charged, Count, Formulaic, Massive, Modification,
+    MolecularFormula, MonoisotopicMass, NamedMod, OffsetMod,
 };
 
 impl<K> Modification<K> {
@@ -47,6 +47,12 @@ impl<K: Charged> Charged for Modification<K> {
     }
 }
 
+impl<'a, K: Formulaic<'a>> Formulaic<'a> for Modification<K> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        self.multiplier * self.kind.formula()
+    }
+}
+
 // FIXME: These `Display` impls happen to be useful to me now, in the midst of this sickeningly messy code, but they
 // probably shouldn't belong here... I don't think I should be providing `Display` impls for anything that I'm not also
 // providing parsers for... At some point, this should probably be removed and moved to the `muropeptide` crate...
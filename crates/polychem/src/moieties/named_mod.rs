@@ -1,6 +1,6 @@
 use crate::{
-    errors::PolychemError, AverageMass, Charge, Charged, Count, Massive, Modification,
-    MonoisotopicMass, NamedMod, Result,
+    errors::PolychemError, AverageMass, Charge, Charged, Count, Formulaic, Massive, Modification,
+    MolecularFormula, MonoisotopicMass, NamedMod, Result,
 };
 
 use super::polymer_database::{ModificationDescription, PolymerDatabase};
@@ -64,6 +64,12 @@ impl Charged for NamedMod<'_, '_> {
     }
 }
 
+impl<'a> Formulaic<'a> for NamedMod<'a, '_> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        self.gained.formula() - self.lost.formula()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use once_cell::sync::Lazy;
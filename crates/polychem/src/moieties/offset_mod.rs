@@ -1,6 +1,6 @@
 use crate::{
-    AverageMass, Charge, Charged, ChemicalComposition, Count, Massive, Modification,
-    MonoisotopicMass, OffsetKind, OffsetMod,
+    AverageMass, Charge, Charged, ChemicalComposition, Count, Formulaic, Massive, Modification,
+    MolecularFormula, MonoisotopicMass, OffsetKind, OffsetMod,
 };
 
 impl<'a> OffsetMod<'a> {
@@ -13,6 +13,11 @@ impl<'a> OffsetMod<'a> {
     pub const fn kind(&self) -> OffsetKind {
         self.kind
     }
+
+    #[must_use]
+    pub const fn composition(&self) -> &ChemicalComposition<'a> {
+        &self.composition
+    }
 }
 
 impl<'a> From<OffsetMod<'a>> for Modification<OffsetMod<'a>> {
@@ -37,6 +42,12 @@ impl Charged for OffsetMod<'_> {
     }
 }
 
+impl<'a> Formulaic<'a> for OffsetMod<'a> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        self.kind.offset(self.composition.formula())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use once_cell::sync::Lazy;
@@ -16,7 +16,10 @@ use thiserror::Error;
 
 // Local Crate Imports
 use super::target::{Index, Target};
-use crate::{atoms::atomic_database::AtomicDatabase, errors::PolychemError, ChemicalComposition};
+use crate::{
+    atoms::atomic_database::AtomicDatabase, errors::PolychemError, ChemicalComposition,
+    Stereochemistry,
+};
 
 // Public API ==========================================================================================================
 
@@ -74,6 +77,10 @@ pub struct ResidueDescription<'a> {
     pub name: String,
     pub composition: ChemicalComposition<'a>,
     pub functional_groups: Vec<FunctionalGroupDescription>,
+    #[cfg_attr(test, serde(skip_serializing_if = "Option::is_none"))]
+    pub stereochemistry: Option<Stereochemistry>,
+    #[cfg_attr(test, serde(skip_serializing_if = "Option::is_none"))]
+    pub stereoisomer_of: Option<String>,
 }
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
@@ -179,6 +186,8 @@ struct ResidueKdl {
     name: String,
     #[knuffel(child, unwrap(argument))]
     composition: NullOr<ChemicalCompositionKdl>,
+    #[knuffel(child)]
+    stereo: Option<StereoKdl>,
     #[knuffel(children(name = "functional-group"))]
     functional_groups: Vec<FunctionalGroupKdl>,
 }
@@ -200,6 +209,17 @@ struct TargetKdl {
 
 type ChemicalCompositionKdl = Spanned<String, Span>;
 
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct StereoKdl {
+    #[knuffel(span)]
+    span: Span,
+    #[knuffel(argument)]
+    descriptor: Spanned<String, Span>,
+    #[knuffel(property(name = "of"))]
+    residue: Option<String>,
+}
+
 #[derive(Clone, Decode, Debug)]
 #[knuffel(span_type=Span)]
 struct FunctionalGroupKdl {
@@ -248,6 +268,20 @@ impl<'a> ValidateInto<'a, Residues<'a>> for ResiduesKdl {
 
     fn validate(self, ctx: Self::Context) -> ChemResult<Residues<'a>> {
         let types = self.types.validate(())?;
+
+        // NOTE: Stereoisomers can only name residues defined in this same database
+        let abbrs: Vec<_> = self.residues.iter().map(|r| r.abbr.as_str()).collect();
+        let undefined_residue = self.residues.iter().find_map(|r| {
+            let StereoKdl { span, residue, .. } = r.stereo.as_ref()?;
+            let residue = residue.as_ref()?;
+            (!abbrs.contains(&residue.as_str())).then(|| (*span, residue.clone()))
+        });
+        if let Some((span, residue)) = undefined_residue {
+            return Err(ChemistryErrorKind::UndefinedStereoisomerResidue(
+                span, residue,
+            ));
+        }
+
         self.residues
             .into_iter()
             .map(|r| r.validate((ctx, &types)))
@@ -317,12 +351,28 @@ impl<'a: 'r, 'r> ValidateInto<'r, ResidueEntry<'a>> for ResidueKdl {
             };
         }
 
+        let stereochemistry = self
+            .stereo
+            .as_ref()
+            .map(|StereoKdl { descriptor, .. }| {
+                Stereochemistry::from_descriptor(descriptor).ok_or_else(|| {
+                    ChemistryErrorKind::UnknownStereochemistry(
+                        *descriptor.span(),
+                        (**descriptor).clone(),
+                    )
+                })
+            })
+            .transpose()?;
+        let stereoisomer_of = self.stereo.and_then(|stereo| stereo.residue);
+
         Ok((
             self.abbr,
             ResidueDescription {
                 name: self.name,
                 composition: self.composition.validate(ctx.0)?,
                 functional_groups: seen_groups.into_keys().collect(),
+                stereochemistry,
+                stereoisomer_of,
             },
         ))
     }
@@ -538,6 +588,14 @@ enum ChemistryErrorKind {
     #[diagnostic(help("double-check for typos, or add {1:?} to the types section"))]
     UndefinedResidueType(Span, String),
 
+    #[error("the stereochemistry {1:?} is not recognised")]
+    #[diagnostic(help("residues can be marked as \"D\", \"L\", or \"meso\""))]
+    UnknownStereochemistry(Span, String),
+
+    #[error("the residue {1:?} is undefined, so it can't have stereoisomers")]
+    #[diagnostic(help("double-check for typos, or add {1:?} to the residues section"))]
+    UndefinedStereoisomerResidue(Span, String),
+
     #[error("the functional group {2:?} has already been defined at {3:?}")]
     #[diagnostic(help("double-check for typos, or remove the duplicate functional group"))]
     DuplicateFunctionalGroup(Span, Span, String, String),
@@ -569,6 +627,8 @@ impl ChemistryErrorKind {
                 vec![(s1, "first defined here"), (s2, "then again here")]
             }
             Self::UndefinedResidueType(s, _) => vec![(s, "undefined residue type")],
+            Self::UnknownStereochemistry(s, _) => vec![(s, "unknown stereochemistry")],
+            Self::UndefinedStereoisomerResidue(s, _) => vec![(s, "undefined residue")],
             Self::NonexistentTarget(s, _) => vec![(s, "targets nothing")],
             Self::OverlappingTargets(s1, ss, _) => iter::once((s1, "this target"))
                 .chain(zip(ss, iter::repeat("overlaps with")))
@@ -765,6 +825,81 @@ mod tests {
         assert_miette_snapshot!(residues);
     }
 
+    #[test]
+    fn parse_residues_with_stereochemistry() {
+        let kdl = indoc! {r#"
+            types {
+                AminoAcid
+            }
+            AminoAcid "A" "Alanine" {
+                composition "C3H7NO2"
+            }
+            AminoAcid "dA" "D-Alanine" {
+                composition "C3H7NO2"
+                stereo "D" of="A"
+            }
+            AminoAcid "lA" "L-Alanine" {
+                composition "C3H7NO2"
+                stereo "L" of="A"
+            }
+            AminoAcid "mJ" "meso-Diaminopimelic Acid" {
+                composition "C7H14N2O4"
+                stereo "meso"
+            }
+        "#};
+        let residues = parse_residues(kdl).unwrap();
+        let stereochemistry = |abbr| residues[abbr].stereochemistry;
+        assert_eq!(stereochemistry("A"), None);
+        assert_eq!(stereochemistry("dA"), Some(Stereochemistry::D));
+        assert_eq!(stereochemistry("lA"), Some(Stereochemistry::L));
+        assert_eq!(stereochemistry("mJ"), Some(Stereochemistry::Meso));
+        assert_eq!(residues["dA"].composition, residues["lA"].composition);
+
+        let stereoisomer_of = |abbr| residues[abbr].stereoisomer_of.as_deref();
+        assert_eq!(stereoisomer_of("A"), None);
+        assert_eq!(stereoisomer_of("dA"), Some("A"));
+        assert_eq!(stereoisomer_of("lA"), Some("A"));
+        assert_eq!(stereoisomer_of("mJ"), None);
+    }
+
+    #[test]
+    fn parse_residues_with_undefined_stereoisomer_residue() {
+        let kdl = indoc! {r#"
+            types {
+                AminoAcid
+            }
+            AminoAcid "dE" "D-Glutamic Acid" {
+                composition "C5H9NO4"
+                stereo "D" of="E"
+            }
+        "#};
+        let error = parse_residues(kdl).unwrap_err();
+        assert_eq!(
+            error.kind.to_string(),
+            r#"the residue "E" is undefined, so it can't have stereoisomers"#
+        );
+        assert_eq!(error.kind.labels()[0].1, "undefined residue");
+    }
+
+    #[test]
+    fn parse_residues_with_unknown_stereochemistry() {
+        let kdl = indoc! {r#"
+            types {
+                AminoAcid
+            }
+            AminoAcid "K" "Lysine" {
+                composition "C6H14N2O2"
+                stereo "R"
+            }
+        "#};
+        let error = parse_residues(kdl).unwrap_err();
+        assert_eq!(
+            error.kind.to_string(),
+            r#"the stereochemistry "R" is not recognised"#
+        );
+        assert_eq!(error.kind.labels()[0].1, "unknown stereochemistry");
+    }
+
     #[test]
     fn parse_residues_with_duplicate_functional_groups() {
         let kdl = indoc! {r#"
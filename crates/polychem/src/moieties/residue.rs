@@ -1,8 +1,8 @@
 use ahash::{HashSet, HashSetExt};
 
 use crate::{
-    errors::PolychemError, AverageMass, Charge, Charged, FunctionalGroup, GroupState, Massive,
-    ModificationId, MonoisotopicMass, Residue, Result,
+    errors::PolychemError, AverageMass, Charge, Charged, Formulaic, FunctionalGroup, GroupState,
+    Massive, ModificationId, MolecularFormula, MonoisotopicMass, Residue, Result, Stereochemistry,
 };
 
 use super::polymer_database::{PolymerDatabase, ResidueDescription};
@@ -16,6 +16,8 @@ impl<'a, 'p> Residue<'a, 'p> {
                 name,
                 composition,
                 functional_groups,
+                stereochemistry,
+                stereoisomer_of,
             },
         ) = db
             .residues
@@ -32,6 +34,9 @@ impl<'a, 'p> Residue<'a, 'p> {
             composition,
             functional_groups,
             offset_modifications,
+            stereochemistry: *stereochemistry,
+            stereoisomer_of: stereoisomer_of.as_deref(),
+            isotope_label: MolecularFormula::default(),
         })
     }
 
@@ -55,6 +60,24 @@ impl<'a, 'p> Residue<'a, 'p> {
         self.offset_modifications.iter().copied()
     }
 
+    // NOTE: Returns `None` for residues that don't specify a stereochemistry in the polymer database
+    #[must_use]
+    pub const fn stereochemistry(&self) -> Option<Stereochemistry> {
+        self.stereochemistry
+    }
+
+    // NOTE: Residues match their own abbreviation, but stereoisomers (like `dE`) also match the residue they're a
+    // stereoisomer of (like `E`), so rules written for a residue cover all of its stereoisomers too
+    #[must_use]
+    pub fn matches_abbr(&self, abbr: &str) -> bool {
+        self.abbr == abbr || self.stereoisomer_of == Some(abbr)
+    }
+
+    #[must_use]
+    pub const fn isotope_label(&self) -> &MolecularFormula<'a> {
+        &self.isotope_label
+    }
+
     pub fn group_state(&self, functional_group: &FunctionalGroup<'p>) -> Result<&GroupState> {
         self.functional_groups.get(functional_group).ok_or_else(|| {
             PolychemError::group_lookup(*functional_group, self.name, self.abbr).into()
@@ -81,11 +104,11 @@ impl<'a, 'p> Residue<'a, 'p> {
 
 impl Massive for Residue<'_, '_> {
     fn monoisotopic_mass(&self) -> MonoisotopicMass {
-        self.composition.monoisotopic_mass()
+        self.composition.monoisotopic_mass() + self.isotope_label.monoisotopic_mass()
     }
 
     fn average_mass(&self) -> AverageMass {
-        self.composition.average_mass()
+        self.composition.average_mass() + self.isotope_label.average_mass()
     }
 }
 
@@ -95,6 +118,12 @@ impl Charged for Residue<'_, '_> {
     }
 }
 
+impl<'a> Formulaic<'a> for Residue<'a, '_> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        self.composition.formula() + self.isotope_label.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use once_cell::sync::Lazy;
@@ -0,0 +1,52 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::Stereochemistry;
+
+impl Stereochemistry {
+    // NOTE: Descriptors are written just as they are in the polymer database, so `D`, `L`, or `meso`
+    pub(crate) fn from_descriptor(descriptor: &str) -> Option<Self> {
+        match descriptor {
+            "D" => Some(Self::D),
+            "L" => Some(Self::L),
+            "meso" => Some(Self::Meso),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Stereochemistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::D => "D",
+                Self::L => "L",
+                Self::Meso => "meso",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_descriptors() {
+        for stereochemistry in [
+            Stereochemistry::D,
+            Stereochemistry::L,
+            Stereochemistry::Meso,
+        ] {
+            let descriptor = stereochemistry.to_string();
+            assert_eq!(
+                Stereochemistry::from_descriptor(&descriptor),
+                Some(stereochemistry)
+            );
+        }
+        assert_eq!(Stereochemistry::from_descriptor("d"), None);
+        assert_eq!(Stereochemistry::from_descriptor("Meso"), None);
+        assert_eq!(Stereochemistry::from_descriptor("LL"), None);
+    }
+}
@@ -0,0 +1,558 @@
+use ahash::{HashMap, HashSet, HashSetExt};
+use itertools::Itertools;
+
+use crate::{
+    AnyMod, BondInfo, CanonicalForm, CanonicalGroupState, CanonicalModification, CanonicalResidue,
+    Formulaic, FunctionalGroup, GroupState, ModificationInfo, OffsetKind, OffsetMod, Polymer,
+    ResidueGroup, ResidueId,
+};
+
+// Public API ==========================================================================================================
+
+impl<'p> Polymer<'_, 'p> {
+    #[must_use]
+    pub fn canonical_form(&self) -> CanonicalForm<'p> {
+        let components = self
+            .canonical_components()
+            .into_iter()
+            .map(|(component, _)| component)
+            .collect();
+        let unlocalized_modifications =
+            merge_modifications(self.modifications.values().filter_map(|info| {
+                if let ModificationInfo::Unlocalized(modification) = info {
+                    Some((
+                        CanonicalModification::from(modification.kind()),
+                        u32::from(modification.multiplier()),
+                    ))
+                } else {
+                    None
+                }
+            }));
+
+        CanonicalForm {
+            components,
+            unlocalized_modifications,
+        }
+    }
+
+    // NOTE: Lists every residue in the same order as the `canonical_form()`, so residues with the same index in the
+    // labellings of two polymers with equal `canonical_form()`s are structurally interchangeable. When a polymer is
+    // symmetric, there is more than one valid labelling, and which of those is returned is left unspecified
+    #[must_use]
+    pub fn canonical_labelling(&self) -> Vec<ResidueId> {
+        self.canonical_components()
+            .into_iter()
+            .flat_map(|(_, residues)| residues)
+            .collect()
+    }
+
+    // NOTE: Unlike hashing a `Polymer` directly, this hash is independent of the order that residues were added (and
+    // the `ResidueId`s they were given), so it's the same for structurally identical polymers built separately — like
+    // the same fragment generated from two different parent polymers. The `CanonicalForm` is fed to the hasher field
+    // by field (instead of through its `Hash` implementation, which is free to change between Rust versions), so this
+    // hash is also stable between runs, builds, and platforms
+    #[must_use]
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::new();
+        self.canonical_form().stable_hash(&mut hasher);
+        hasher.0
+    }
+}
+
+// Private Methods =====================================================================================================
+
+impl<'p> Polymer<'_, 'p> {
+    // NOTE: Returns every connected piece of this polymer (sorted), each paired with its residues (in canonical order)
+    fn canonical_components(&self) -> Vec<(Vec<CanonicalResidue<'p>>, Vec<ResidueId>)> {
+        let mut visited = HashSet::with_capacity(self.residues.len());
+        self.residues
+            .keys()
+            .copied()
+            .sorted_unstable()
+            .filter_map(|root| {
+                if visited.contains(&root) {
+                    return None;
+                }
+                let component = self.traverse(root);
+                visited.extend(component.iter().copied());
+                Some(self.canonical_component(&component))
+            })
+            .sorted_unstable()
+            .collect()
+    }
+
+    // DESIGN: Each functional group holds at most one bond, so visiting the bonds of each residue in the order of their
+    // functional groups means that a breadth-first traversal is fully determined by the residue it starts from. That
+    // means the canonical labelling of a connected piece can be found by starting a traversal from each of the residues
+    // it could start from, and keeping the smallest description produced. A discarded alternative was the colour
+    // refinement used for general graph canonisation, but that's only needed when a node's neighbours can't be ordered
+    fn canonical_component(
+        &self,
+        component: &[ResidueId],
+    ) -> (Vec<CanonicalResidue<'p>>, Vec<ResidueId>) {
+        // NOTE: Only residues with the smallest description (ignoring where their bonds lead) can start the traversal
+        let descriptions: Vec<_> = component
+            .iter()
+            .map(|&id| self.canonical_residue(id, |_| 0))
+            .collect();
+        // SAFETY: Every component contains at least the residue its traversal started from
+        let first_description = descriptions.iter().min().unwrap();
+
+        component
+            .iter()
+            .zip(&descriptions)
+            .filter(|&(_, description)| description == first_description)
+            .map(|(&root, _)| {
+                let residues = self.traverse(root);
+                let index: HashMap<_, _> = residues
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &id)| (id, i))
+                    .collect();
+                let component = residues
+                    .iter()
+                    .map(|&id| self.canonical_residue(id, |other| index[&other]))
+                    .collect();
+                (component, residues)
+            })
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            // SAFETY: The residue with the smallest description is always one of the roots tried
+            .unwrap()
+    }
+
+    fn traverse(&self, root: ResidueId) -> Vec<ResidueId> {
+        let mut residues = vec![root];
+        let mut visited = HashSet::from_iter([root]);
+        let mut next = 0;
+        while let Some(&id) = residues.get(next) {
+            for neighbour in self.bonded_residues(id) {
+                if visited.insert(neighbour) {
+                    residues.push(neighbour);
+                }
+            }
+            next += 1;
+        }
+        residues
+    }
+
+    // NOTE: Returns the residue at the other end of each bond formed by the residue `id`, in the order of the
+    // functional groups those bonds are formed on
+    fn bonded_residues(&self, id: ResidueId) -> impl Iterator<Item = ResidueId> + '_ {
+        self.residues[&id]
+            .functional_groups
+            .iter()
+            .filter_map(|(group, state)| {
+                let neighbour = match state {
+                    GroupState::Donor(bond_id) => {
+                        let BondInfo(_, _, ResidueGroup(acceptor, _)) = &self.bonds[bond_id];
+                        acceptor
+                    }
+                    GroupState::Acceptor(bond_id) => {
+                        let BondInfo(ResidueGroup(donor, _), _, _) = &self.bonds[bond_id];
+                        donor
+                    }
+                    GroupState::Free | GroupState::Modified(_) => return None,
+                };
+                Some((group, *neighbour))
+            })
+            .sorted_unstable()
+            .map(|(_, neighbour)| neighbour)
+    }
+
+    // NOTE: Describes the residue `id`, using `index` to number the residues it's bonded to
+    fn canonical_residue(
+        &self,
+        id: ResidueId,
+        index: impl Fn(ResidueId) -> usize,
+    ) -> CanonicalResidue<'p> {
+        // SAFETY: The `ResidueId`s, `ModificationId`s, and `BondId`s stored in a `Polymer` always refer to components
+        // of that same `Polymer`, so none of these lookups can fail
+        let residue = &self.residues[&id];
+        let functional_groups = residue
+            .functional_groups
+            .iter()
+            .map(|(&group, state)| {
+                let state = match state {
+                    GroupState::Free => CanonicalGroupState::Free,
+                    GroupState::Modified(mod_id) => {
+                        let ModificationInfo::Named(named_mod, _) = &self.modifications[mod_id]
+                        else {
+                            unreachable!();
+                        };
+                        CanonicalGroupState::Modified(named_mod.abbr())
+                    }
+                    GroupState::Donor(bond_id) => {
+                        let BondInfo(_, bond, ResidueGroup(acceptor, group)) = &self.bonds[bond_id];
+                        CanonicalGroupState::Donor(bond.abbr(), index(*acceptor), *group)
+                    }
+                    GroupState::Acceptor(bond_id) => {
+                        let BondInfo(ResidueGroup(donor, group), bond, _) = &self.bonds[bond_id];
+                        CanonicalGroupState::Acceptor(bond.abbr(), index(*donor), *group)
+                    }
+                };
+                (group, state)
+            })
+            .sorted_unstable()
+            .collect();
+        let offset_modifications =
+            merge_modifications(residue.offset_modifications.iter().map(|mod_id| {
+                let ModificationInfo::Offset(modification, _) = &self.modifications[mod_id] else {
+                    unreachable!();
+                };
+                (
+                    CanonicalModification::from(modification.kind()),
+                    u32::from(modification.multiplier()),
+                )
+            }));
+
+        CanonicalResidue {
+            abbr: residue.abbr,
+            functional_groups,
+            offset_modifications,
+            isotope_label: residue.isotope_label.to_string(),
+        }
+    }
+}
+
+// Private Helper Functions ============================================================================================
+
+// NOTE: Modifications of the same kind are merged, so a single `2xAm` and two separate `Am`s are described the same way
+fn merge_modifications<'p>(
+    modifications: impl Iterator<Item = (CanonicalModification<'p>, u32)>,
+) -> Vec<(CanonicalModification<'p>, u32)> {
+    modifications
+        .sorted_unstable()
+        .coalesce(|(kind, count), (next_kind, next_count)| {
+            if kind == next_kind {
+                Ok((kind, count + next_count))
+            } else {
+                Err(((kind, count), (next_kind, next_count)))
+            }
+        })
+        .collect()
+}
+
+// NOTE: A 64-bit FNV-1a hash — chosen because it's tiny, well-specified, and never changes
+struct FnvHasher(u64);
+
+impl FnvHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    const fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+// NOTE: Every variable-length value is prefixed by its length, and every enum by a tag for its variant, so two
+// different `CanonicalForm`s never feed the hasher the same bytes
+trait StableHash {
+    fn stable_hash(&self, hasher: &mut FnvHasher);
+}
+
+impl StableHash for u32 {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        hasher.write(&self.to_le_bytes());
+    }
+}
+
+impl StableHash for usize {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        // NOTE: Always written as 64 bits, so the hash doesn't depend on the platform's pointer width
+        hasher.write(&(*self as u64).to_le_bytes());
+    }
+}
+
+impl StableHash for str {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        self.len().stable_hash(hasher);
+        hasher.write(self.as_bytes());
+    }
+}
+
+impl<T: StableHash> StableHash for Vec<T> {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        self.len().stable_hash(hasher);
+        for value in self {
+            value.stable_hash(hasher);
+        }
+    }
+}
+
+impl<A: StableHash, B: StableHash> StableHash for (A, B) {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        self.0.stable_hash(hasher);
+        self.1.stable_hash(hasher);
+    }
+}
+
+impl StableHash for CanonicalForm<'_> {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        self.components.stable_hash(hasher);
+        self.unlocalized_modifications.stable_hash(hasher);
+    }
+}
+
+impl StableHash for CanonicalResidue<'_> {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        self.abbr.stable_hash(hasher);
+        self.functional_groups.stable_hash(hasher);
+        self.offset_modifications.stable_hash(hasher);
+        self.isotope_label.stable_hash(hasher);
+    }
+}
+
+impl StableHash for FunctionalGroup<'_> {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        self.name.stable_hash(hasher);
+        self.location.stable_hash(hasher);
+    }
+}
+
+impl StableHash for CanonicalGroupState<'_> {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        match self {
+            Self::Free => hasher.write(&[0]),
+            Self::Modified(abbr) => {
+                hasher.write(&[1]);
+                abbr.stable_hash(hasher);
+            }
+            Self::Donor(abbr, index, group) | Self::Acceptor(abbr, index, group) => {
+                hasher.write(&[if matches!(self, Self::Donor(..)) {
+                    2
+                } else {
+                    3
+                }]);
+                abbr.stable_hash(hasher);
+                index.stable_hash(hasher);
+                group.stable_hash(hasher);
+            }
+        }
+    }
+}
+
+impl StableHash for CanonicalModification<'_> {
+    fn stable_hash(&self, hasher: &mut FnvHasher) {
+        match self {
+            Self::Named(abbr) => {
+                hasher.write(&[0]);
+                abbr.stable_hash(hasher);
+            }
+            Self::Offset(kind, formula) => {
+                hasher.write(&[1]);
+                hasher.write(match kind {
+                    OffsetKind::Add => b"+",
+                    OffsetKind::Remove => b"-",
+                });
+                formula.stable_hash(hasher);
+            }
+        }
+    }
+}
+
+impl<'p> From<&AnyMod<'_, 'p>> for CanonicalModification<'p> {
+    fn from(value: &AnyMod<'_, 'p>) -> Self {
+        match value {
+            AnyMod::Named(named_mod) => Self::Named(named_mod.abbr()),
+            AnyMod::Offset(offset_mod) => offset_mod.into(),
+        }
+    }
+}
+
+impl From<&OffsetMod<'_>> for CanonicalModification<'_> {
+    fn from(value: &OffsetMod<'_>) -> Self {
+        Self::Offset(value.kind(), value.composition().formula().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::once;
+
+    use once_cell::sync::Lazy;
+
+    use crate::{
+        polymers::polymerizer::Polymerizer, AtomicDatabase, Massive, OffsetKind, PolymerDatabase,
+    };
+
+    use super::*;
+
+    const STEM_RESIDUES: [&str; 4] = ["A", "E", "J", "A"];
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "test_polymer_database.kdl",
+            include_str!("../../tests/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+
+    // NOTE: Builds a MurNAc residue with a `stem` peptide attached, returning its `ResidueId`s in that same order.
+    // When `reversed` is set, residues are added in the opposite order, so they're given different `ResidueId`s
+    fn monomer(stem: &[&str], reversed: bool) -> (Polymer<'static, 'static>, Vec<ResidueId>) {
+        let mut polymer = POLYMERIZER.new_polymer();
+        let abbrs: Vec<_> = once("m").chain(stem.iter().copied()).collect();
+        let mut residues: Vec<_> = if reversed {
+            abbrs.iter().rev().collect()
+        } else {
+            abbrs.iter().collect()
+        }
+        .into_iter()
+        .map(|abbr| polymer.new_residue(abbr).unwrap())
+        .collect();
+        if reversed {
+            residues.reverse();
+        }
+
+        polymer
+            .bond_residues("Stem", residues[0], residues[1])
+            .unwrap();
+        polymer.bond_chain("Pep", &residues[1..]).unwrap();
+        (polymer, residues)
+    }
+
+    fn canonical_abbrs(polymer: &Polymer<'static, 'static>) -> Vec<&'static str> {
+        polymer
+            .canonical_labelling()
+            .into_iter()
+            .map(|id| polymer.residue(id).unwrap().abbr())
+            .collect()
+    }
+
+    #[test]
+    fn independent_of_residue_ids() {
+        let (forward, forward_ids) = monomer(&STEM_RESIDUES, false);
+        let (reversed, reversed_ids) = monomer(&STEM_RESIDUES, true);
+        assert_ne!(forward_ids, reversed_ids);
+        assert_eq!(forward.canonical_form(), reversed.canonical_form());
+        assert_eq!(forward.structural_hash(), reversed.structural_hash());
+
+        // NOTE: Traversal starts from the free C-terminal alanine, since its only bond is a `Pep` (which sorts before
+        // the `Stem` bond of the other alanine)
+        assert_eq!(canonical_abbrs(&forward), ["A", "J", "E", "A", "m"]);
+        assert_eq!(canonical_abbrs(&reversed), ["A", "J", "E", "A", "m"]);
+        assert_eq!(forward.canonical_labelling()[0], forward_ids[4]);
+        assert_eq!(reversed.canonical_labelling()[0], reversed_ids[4]);
+    }
+
+    #[test]
+    fn distinguishes_structures() {
+        let canonical_form = |stem| monomer(stem, false).0.canonical_form();
+        assert_ne!(
+            canonical_form(&["A", "E", "J", "A"]),
+            canonical_form(&["A", "E", "A", "J"])
+        );
+        assert_ne!(
+            canonical_form(&["A", "E", "J", "A"]),
+            canonical_form(&["A", "E", "J"])
+        );
+
+        // NOTE: The same modification, on different residues
+        let (mut glutamate, ids) = monomer(&STEM_RESIDUES, false);
+        glutamate.modify_only_group("Am", ids[2]).unwrap();
+        let (mut diaminopimelate, ids) = monomer(&STEM_RESIDUES, false);
+        diaminopimelate.modify_only_group("Am", ids[3]).unwrap();
+        assert_eq!(
+            glutamate.monoisotopic_mass(),
+            diaminopimelate.monoisotopic_mass()
+        );
+        assert_ne!(glutamate.canonical_form(), diaminopimelate.canonical_form());
+        assert_ne!(
+            glutamate.structural_hash(),
+            diaminopimelate.structural_hash()
+        );
+
+        // NOTE: The same offset, localized and unlocalized
+        let (mut localized, ids) = monomer(&STEM_RESIDUES, false);
+        localized
+            .offset_residue(OffsetKind::Remove, 1, "H2O", ids[1])
+            .unwrap();
+        let (mut unlocalized, _) = monomer(&STEM_RESIDUES, false);
+        unlocalized
+            .new_offset(OffsetKind::Remove, 1, "H2O")
+            .unwrap();
+        assert_eq!(
+            localized.monoisotopic_mass(),
+            unlocalized.monoisotopic_mass()
+        );
+        assert_ne!(localized.canonical_form(), unlocalized.canonical_form());
+    }
+
+    #[test]
+    fn merged_modifications() {
+        let (mut merged, ids) = monomer(&STEM_RESIDUES, false);
+        merged.new_modification(2, "Am").unwrap();
+        merged.new_offset(OffsetKind::Remove, 2, "H2O").unwrap();
+        merged
+            .offset_residue(OffsetKind::Add, 2, "H", ids[2])
+            .unwrap();
+
+        let (mut separate, ids) = monomer(&STEM_RESIDUES, true);
+        for _ in 0..2 {
+            separate.new_modification(1, "Am").unwrap();
+            separate.new_offset(OffsetKind::Remove, 1, "OH2").unwrap();
+            separate
+                .offset_residue(OffsetKind::Add, 1, "H", ids[2])
+                .unwrap();
+        }
+
+        assert_eq!(merged.canonical_form(), separate.canonical_form());
+        assert_eq!(merged.structural_hash(), separate.structural_hash());
+    }
+
+    #[test]
+    fn identical_fragments() {
+        // NOTE: Two different parents that both leave behind an `m-AEJ` fragment
+        let (mut tetrapeptide, ids) = monomer(&STEM_RESIDUES, false);
+        tetrapeptide.remove_residue(ids[4]);
+        let (mut lysine, ids) = monomer(&["A", "E", "J", "K"], true);
+        lysine.remove_residue(ids[4]);
+        let (tripeptide, _) = monomer(&["A", "E", "J"], false);
+
+        assert_eq!(tetrapeptide.canonical_form(), lysine.canonical_form());
+        assert_eq!(tetrapeptide.canonical_form(), tripeptide.canonical_form());
+        assert_eq!(lysine.structural_hash(), tripeptide.structural_hash());
+
+        // NOTE: Cutting a residue from the middle leaves two pieces, which are compared regardless of which residues
+        // were added first
+        let (mut tetrapeptide, ids) = monomer(&STEM_RESIDUES, false);
+        tetrapeptide.remove_residue(ids[2]);
+        let mut pieces = POLYMERIZER.new_polymer();
+        let diaminopimelate = pieces.new_residue("J").unwrap();
+        let alanine = pieces.new_residue("A").unwrap();
+        pieces
+            .bond_residues("Pep", diaminopimelate, alanine)
+            .unwrap();
+        let murnac = pieces.new_residue("m").unwrap();
+        let alanine = pieces.new_residue("A").unwrap();
+        pieces.bond_residues("Stem", murnac, alanine).unwrap();
+
+        assert_eq!(tetrapeptide.canonical_form(), pieces.canonical_form());
+        assert_eq!(tetrapeptide.structural_hash(), pieces.structural_hash());
+        assert_eq!(canonical_abbrs(&pieces), ["A", "J", "A", "m"]);
+    }
+
+    #[test]
+    fn fnv_hasher() {
+        // NOTE: Test vectors from the reference implementation of FNV-1a
+        for (bytes, hash) in [
+            (&b""[..], 0xcbf2_9ce4_8422_2325),
+            (&b"a"[..], 0xaf63_dc4c_8601_ec8c),
+            (&b"foobar"[..], 0x8594_4171_f739_67e8),
+        ] {
+            let mut hasher = FnvHasher::new();
+            hasher.write(bytes);
+            assert_eq!(hasher.0, hash);
+        }
+    }
+}
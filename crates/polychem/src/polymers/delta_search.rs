@@ -0,0 +1,461 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    iter,
+};
+
+use itertools::Itertools;
+use rust_decimal::Decimal;
+
+use crate::{
+    errors::PolychemError, AverageMass, Bond, Count, DeltaComponent, DeltaExplanation, DeltaSearch,
+    Formulaic, MassTolerance, Massive, MolecularFormula, MonoisotopicMass, NamedMod,
+    PolymerDatabase, Residue, Result,
+};
+
+// NOTE: Combinations of more than a few components can explain just about any mass difference, so a low default keeps
+// results meaningful (and searches quick)
+const DEFAULT_MAX_COMPONENTS: u32 = 3;
+
+// Public API ==========================================================================================================
+
+impl<'a, 'p> DeltaSearch<'a, 'p> {
+    #[must_use]
+    pub fn new(polymer_db: &'p PolymerDatabase<'a>) -> Self {
+        Self {
+            polymer_db,
+            components: Vec::new(),
+            // SAFETY: The default is a non-zero constant
+            max_components: Count::new(DEFAULT_MAX_COMPONENTS).unwrap(),
+        }
+    }
+
+    #[must_use]
+    pub const fn polymer_db(&self) -> &'p PolymerDatabase<'a> {
+        self.polymer_db
+    }
+
+    // NOTE: Limits the total number of components gained or lost in a single explanation, so `+2xA, -Am` counts as 3
+    pub fn set_max_components(&mut self, max_components: Count) -> &mut Self {
+        self.max_components = max_components;
+        self
+    }
+
+    pub fn add_residue(
+        &mut self,
+        abbr: impl AsRef<str>,
+        bond: impl AsRef<str>,
+    ) -> Result<&mut Self> {
+        let residue = Residue::new(self.polymer_db, abbr)?;
+        let bond = Bond::new(self.polymer_db, bond)?;
+        self.push_component(DeltaComponent::Residue(residue, bond))
+    }
+
+    pub fn add_modification(&mut self, abbr: impl AsRef<str>) -> Result<&mut Self> {
+        let modification = NamedMod::new(self.polymer_db, abbr)?;
+        self.push_component(DeltaComponent::Modification(modification))
+    }
+
+    // NOTE: Adds every modification in the `PolymerDatabase`, in alphabetical order of their abbreviations
+    pub fn add_all_modifications(&mut self) -> Result<&mut Self> {
+        for abbr in self.polymer_db.modifications.keys().sorted_unstable() {
+            self.add_modification(abbr)?;
+        }
+
+        Ok(self)
+    }
+
+    // NOTE: Explains the difference between the mass of some `known` structure and an `observed` mass — explanations
+    // are returned in order of increasing absolute mass error, then by how many components they contain
+    #[must_use]
+    pub fn search(
+        &self,
+        known: &impl Massive,
+        observed: Decimal,
+        tolerance: MassTolerance,
+    ) -> Vec<DeltaExplanation<'a, 'p>> {
+        let known = known.monoisotopic_mass().0;
+        let delta = observed - known;
+        let window = tolerance.window(observed);
+        let masses: Vec<_> = self
+            .components
+            .iter()
+            .map(|component| component.monoisotopic_mass().0)
+            .collect();
+        let mut counts = vec![0; masses.len()];
+        let mut explanations = Vec::new();
+
+        combine(
+            &masses,
+            0,
+            self.max_components.into(),
+            Decimal::ZERO,
+            (delta - window, delta + window),
+            &mut counts,
+            &mut |counts, theoretical| {
+                if is_minimal(&masses, counts) {
+                    explanations.push(self.explanation(counts, known, observed, theoretical));
+                }
+            },
+        );
+
+        explanations
+            .into_iter()
+            .sorted_by_cached_key(|explanation| {
+                let components: i64 = explanation
+                    .components
+                    .iter()
+                    .map(|(_, count)| count.abs())
+                    .sum();
+                (
+                    explanation.ppm_error.abs(),
+                    components,
+                    explanation.to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl<'a, 'p> DeltaComponent<'a, 'p> {
+    #[must_use]
+    pub const fn abbr(&self) -> &'p str {
+        match self {
+            Self::Residue(residue, _) => residue.abbr(),
+            Self::Modification(modification) => modification.abbr(),
+        }
+    }
+
+    #[must_use]
+    pub const fn name(&self) -> &'p str {
+        match self {
+            Self::Residue(residue, _) => residue.name(),
+            Self::Modification(modification) => modification.name(),
+        }
+    }
+}
+
+impl Massive for DeltaComponent<'_, '_> {
+    fn monoisotopic_mass(&self) -> MonoisotopicMass {
+        match self {
+            Self::Residue(residue, bond) => residue.monoisotopic_mass() + bond.monoisotopic_mass(),
+            Self::Modification(modification) => modification.monoisotopic_mass(),
+        }
+    }
+
+    fn average_mass(&self) -> AverageMass {
+        match self {
+            Self::Residue(residue, bond) => residue.average_mass() + bond.average_mass(),
+            Self::Modification(modification) => modification.average_mass(),
+        }
+    }
+}
+
+impl<'a> Formulaic<'a> for DeltaComponent<'a, '_> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        match self {
+            Self::Residue(residue, bond) => residue.formula() + bond.formula(),
+            Self::Modification(modification) => modification.formula(),
+        }
+    }
+}
+
+impl Display for DeltaComponent<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.abbr())
+    }
+}
+
+impl<'a> Formulaic<'a> for DeltaExplanation<'a, '_> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        self.components
+            .iter()
+            .map(|(component, count)| {
+                // SAFETY: Explanations only contain non-zero counts, no larger than `max_components`
+                let multiplier = u32::try_from(count.unsigned_abs())
+                    .ok()
+                    .and_then(Count::new)
+                    .unwrap();
+                let formula = multiplier * component.formula();
+                if *count < 0 {
+                    -formula
+                } else {
+                    formula
+                }
+            })
+            .sum()
+    }
+}
+
+// NOTE: Written like `+G, +Met` or `-2xA, +Am`
+impl Display for DeltaExplanation<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let terms = self.components.iter().map(|(component, count)| {
+            let sign = if *count < 0 { '-' } else { '+' };
+            match count.unsigned_abs() {
+                1 => format!("{sign}{component}"),
+                count => format!("{sign}{count}x{component}"),
+            }
+        });
+        write!(f, "{}", terms.format(", "))
+    }
+}
+
+// Private Methods and Helper Functions ================================================================================
+
+impl<'a, 'p> DeltaSearch<'a, 'p> {
+    fn push_component(&mut self, component: DeltaComponent<'a, 'p>) -> Result<&mut Self> {
+        if component.monoisotopic_mass().0.is_zero() {
+            return Err(PolychemError::massless_delta_component(component.abbr()).into());
+        }
+
+        if !self.components.contains(&component) {
+            self.components.push(component);
+        }
+
+        Ok(self)
+    }
+
+    fn explanation(
+        &self,
+        counts: &[i64],
+        known: Decimal,
+        observed: Decimal,
+        delta: Decimal,
+    ) -> DeltaExplanation<'a, 'p> {
+        let components = self
+            .components
+            .iter()
+            .zip(counts)
+            .filter(|(_, &count)| count != 0)
+            .map(|(component, &count)| (component.clone(), count))
+            .collect();
+        let theoretical = known + delta;
+
+        DeltaExplanation {
+            components,
+            monoisotopic_mass: MonoisotopicMass(delta),
+            ppm_error: (observed - theoretical) / theoretical * Decimal::from(1_000_000),
+        }
+    }
+}
+
+// NOTE: Recursively enumerates every signed count of every component, using no more than `budget` components in total,
+// and passes along each combination with a total mass between `low` and `high`
+fn combine(
+    masses: &[Decimal],
+    depth: usize,
+    budget: u32,
+    partial: Decimal,
+    (low, high): (Decimal, Decimal),
+    counts: &mut [i64],
+    found: &mut impl FnMut(&[i64], Decimal),
+) {
+    let Some(&mass) = masses.get(depth) else {
+        if counts.iter().any(|&count| count != 0) && (low..=high).contains(&partial) {
+            found(counts, partial);
+        }
+        return;
+    };
+
+    for count in -i64::from(budget)..=i64::from(budget) {
+        counts[depth] = count;
+        // SAFETY: `count` is never larger than `budget`, which is already a `u32`
+        let remaining = budget - u32::try_from(count.unsigned_abs()).unwrap();
+        let partial = partial + mass * Decimal::from(count);
+        combine(
+            masses,
+            depth + 1,
+            remaining,
+            partial,
+            (low, high),
+            counts,
+            found,
+        );
+    }
+    counts[depth] = 0;
+}
+
+// NOTE: Explanations containing any group of components that cancel out entirely (like `+Ac, +DeAc`) can be extended
+// indefinitely, and say nothing more than the explanation without that group, so they're skipped
+// PERF: This checks every subset of components, but that stays cheap for any reasonable `max_components`
+fn is_minimal(masses: &[Decimal], counts: &[i64]) -> bool {
+    let terms: Vec<_> = masses
+        .iter()
+        .zip(counts)
+        .flat_map(|(&mass, &count)| {
+            let mass = if count < 0 { -mass } else { mass };
+            // SAFETY: `count` is never larger than `max_components`, which is a `u32`
+            iter::repeat(mass).take(usize::try_from(count.unsigned_abs()).unwrap())
+        })
+        .collect();
+
+    !(1..=terms.len())
+        .flat_map(|size| terms.iter().combinations(size))
+        .any(|subset| subset.into_iter().sum::<Decimal>().is_zero())
+}
+
+// Module Tests ========================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use rust_decimal_macros::dec;
+
+    use crate::{AtomicDatabase, ChemicalComposition};
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "test_polymer_database.kdl",
+            include_str!("../../tests/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+
+    // NOTE: The composition of gm-AEJA
+    static KNOWN: Lazy<ChemicalComposition> =
+        Lazy::new(|| ChemicalComposition::new(&ATOMIC_DB, "C37H63N7O21").unwrap());
+
+    static SEARCH: Lazy<DeltaSearch> = Lazy::new(|| {
+        let mut search = DeltaSearch::new(&POLYMER_DB);
+        search
+            .add_residue("A", "Pep")
+            .unwrap()
+            .add_residue("G", "Pep")
+            .unwrap()
+            .add_all_modifications()
+            .unwrap();
+        search
+    });
+
+    fn explain(delta: Decimal, tolerance: MassTolerance) -> Vec<String> {
+        let observed = KNOWN.monoisotopic_mass().0 + delta;
+        SEARCH
+            .search(&*KNOWN, observed, tolerance)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn components() {
+        assert_eq!(
+            SEARCH
+                .components
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["A", "G", "Ac", "Am", "Anh", "Ca", "DeAc", "Met", "Poly", "Red"]
+        );
+
+        let alanine = &SEARCH.components[0];
+        assert_eq!((alanine.abbr(), alanine.name()), ("A", "Alanine"));
+        assert_eq!(alanine.formula().to_string(), "C3H5NO");
+        assert_eq!(
+            alanine.monoisotopic_mass(),
+            MonoisotopicMass(dec!(71.03711378515))
+        );
+
+        let amidation = &SEARCH.components[3];
+        assert_eq!((amidation.abbr(), amidation.name()), ("Am", "Amidation"));
+        assert_eq!(amidation.formula().to_string(), "HN-O");
+
+        // NOTE: Adding the same component twice doesn't change the search
+        let mut search = SEARCH.clone();
+        search
+            .add_residue("A", "Pep")
+            .unwrap()
+            .add_modification("Am")
+            .unwrap();
+        assert_eq!(search, *SEARCH);
+    }
+
+    #[test]
+    fn explain_deltas() {
+        let alanine = dec!(71.03711378515);
+        assert_eq!(
+            explain(alanine, MassTolerance::Ppm(dec!(10))),
+            ["+A", "+G, +Met"]
+        );
+
+        let amidation = dec!(-0.98401558291);
+        assert_eq!(
+            explain(amidation - alanine, MassTolerance::Ppm(dec!(10))),
+            ["-A, +Am", "-G, +Am, -Met"]
+        );
+
+        assert_eq!(
+            explain(dec!(42.0106), MassTolerance::Ppm(dec!(5))),
+            ["+Ac", "-DeAc"]
+        );
+        assert!(explain(dec!(42.0106), MassTolerance::Ppm(dec!(0.01))).is_empty());
+        assert_eq!(
+            explain(dec!(42.0106), MassTolerance::Da(dec!(0.001))),
+            ["+Ac", "-DeAc"]
+        );
+
+        // NOTE: Nothing needs explaining if there is no mass difference
+        assert!(explain(Decimal::ZERO, MassTolerance::Ppm(dec!(10))).is_empty());
+    }
+
+    #[test]
+    fn explanation_details() {
+        let observed = KNOWN.monoisotopic_mass().0 + dec!(142.07422757030);
+        let explanations = SEARCH.search(&*KNOWN, observed, MassTolerance::Ppm(dec!(1)));
+        let best = &explanations[0];
+        assert_eq!(best.to_string(), "+2xA");
+        assert_eq!(best.components.len(), 1);
+        assert_eq!(best.components[0].1, 2);
+        assert_eq!(
+            best.monoisotopic_mass,
+            MonoisotopicMass(dec!(142.07422757030))
+        );
+        assert_eq!(best.ppm_error, Decimal::ZERO);
+        assert_eq!(best.formula().to_string(), "C6H10N2O2");
+
+        let observed = KNOWN.monoisotopic_mass().0 - dec!(72.02112936806);
+        let explanations = SEARCH.search(&*KNOWN, observed, MassTolerance::Ppm(dec!(1)));
+        assert_eq!(explanations[0].formula().to_string(), "-C3H4O2");
+    }
+
+    #[test]
+    fn max_components() {
+        let mut search = SEARCH.clone();
+        let observed = KNOWN.monoisotopic_mass().0 + dec!(71.03711378515);
+        search.set_max_components(Count::new(1).unwrap());
+        let explanations = search.search(&*KNOWN, observed, MassTolerance::Ppm(dec!(10)));
+        assert_eq!(
+            explanations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["+A"]
+        );
+    }
+
+    #[test]
+    fn invalid_components() {
+        let mut search = DeltaSearch::new(&POLYMER_DB);
+        let error = |result: Result<&mut DeltaSearch>| result.unwrap_err().to_string();
+        assert_eq!(
+            error(search.add_residue("X", "Pep")),
+            "\"X\" has no mass, so it can't be used to explain a mass difference"
+        );
+        assert_eq!(
+            error(search.add_residue("Ala", "Pep")),
+            "the residue \"Ala\" could not be found in the supplied polymer database"
+        );
+        assert_eq!(
+            error(search.add_residue("A", "Pop")),
+            "the bond \"Pop\" could not be found in the supplied polymer database"
+        );
+        assert_eq!(
+            error(search.add_modification("Arg")),
+            "the modification \"Arg\" could not be found in the supplied polymer database"
+        );
+        assert!(search.components.is_empty());
+    }
+}
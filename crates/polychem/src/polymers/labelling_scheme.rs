@@ -0,0 +1,246 @@
+use crate::{
+    errors::PolychemError, AtomicDatabase, ChemicalComposition, Count, Element, Formulaic,
+    IsotopeLabel, LabellingScheme, Residue, Result, Target,
+};
+
+// Public API ==========================================================================================================
+
+impl<'a> LabellingScheme<'a> {
+    #[must_use]
+    pub const fn new(atomic_db: &'a AtomicDatabase) -> Self {
+        Self {
+            atomic_db,
+            labels: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub const fn atomic_db(&self) -> &'a AtomicDatabase {
+        self.atomic_db
+    }
+
+    pub fn label_all(
+        &mut self,
+        target: impl Into<Target>,
+        isotope: impl AsRef<str>,
+    ) -> Result<&mut Self> {
+        self.push_label(target, isotope, None)
+    }
+
+    pub fn label_atoms(
+        &mut self,
+        target: impl Into<Target>,
+        isotope: impl AsRef<str>,
+        count: Count,
+    ) -> Result<&mut Self> {
+        self.push_label(target, isotope, Some(count))
+    }
+}
+
+// Crate API ===========================================================================================================
+
+impl<'a> LabellingScheme<'a> {
+    // NOTE: Labels are applied in the order they were added, and only ever swap out atoms that haven't already been
+    // labelled — so a second `[13C]` label targeting the same residue can only claim the carbon left over by the first
+    pub(crate) fn label_residue(&self, residue: &mut Residue<'a, '_>) -> Result<()> {
+        for label in &self.labels {
+            if !label.targets(residue) {
+                continue;
+            }
+
+            let isotope = &label.isotope;
+            let element = Element {
+                mass_number: None,
+                ..isotope.clone()
+            };
+            let available = residue.formula().element_count(&element);
+            let count = label
+                .count
+                .map_or(available, |count| i64::from(u32::from(count)));
+            if count > available {
+                return Err(PolychemError::insufficient_atoms_for_label(
+                    count,
+                    isotope,
+                    residue.name(),
+                    residue.abbr(),
+                    available,
+                    &element,
+                )
+                .into());
+            }
+
+            residue.isotope_label.add_element(isotope, count);
+            residue.isotope_label.add_element(&element, -count);
+        }
+
+        Ok(())
+    }
+}
+
+// Private Methods =====================================================================================================
+
+impl<'a> LabellingScheme<'a> {
+    fn push_label(
+        &mut self,
+        target: impl Into<Target>,
+        isotope: impl AsRef<str>,
+        count: Option<Count>,
+    ) -> Result<&mut Self> {
+        let target = target.into();
+        let isotope = self.parse_isotope(isotope.as_ref())?;
+        self.labels.push(IsotopeLabel {
+            target,
+            isotope,
+            count,
+        });
+
+        Ok(self)
+    }
+
+    fn parse_isotope(&self, formula: &str) -> Result<Element<'a>> {
+        ChemicalComposition::new(self.atomic_db, formula)?
+            .into_single_atom()
+            .filter(|element| element.mass_number.is_some())
+            .ok_or_else(|| PolychemError::invalid_isotope_label(formula).into())
+    }
+}
+
+impl IsotopeLabel<'_> {
+    fn targets(&self, residue: &Residue) -> bool {
+        let target = Target::from(&self.target);
+        residue
+            .functional_groups()
+            .any(|(group, _)| Target::from_residue_and_group(residue, group).matches(&target))
+    }
+}
+
+// Module Tests ========================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use rust_decimal_macros::dec;
+
+    use crate::{Massive, MonoisotopicMass, Polymer, PolymerDatabase, Polymerizer, ResidueId};
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "test_polymer_database.kdl",
+            include_str!("../../tests/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+
+    const AMINO_ACIDS: Target<&str> = Target::new("Amino", Some("N-Terminal"), None);
+    const GLCNAC: Target<&str> = Target::new("Hydroxyl", None, Some("N-Acetylglucosamine"));
+
+    static LABELLING: Lazy<LabellingScheme> = Lazy::new(|| {
+        let mut labelling = LabellingScheme::new(&ATOMIC_DB);
+        labelling
+            .label_all(AMINO_ACIDS, "[15N]")
+            .unwrap()
+            .label_atoms(GLCNAC, "[13C]", Count::new(6).unwrap())
+            .unwrap();
+        labelling
+    });
+
+    fn labelled_polymer(residues: &[&str]) -> (Polymer<'static, 'static>, Vec<ResidueId>) {
+        let polymerizer = Polymerizer::new(&ATOMIC_DB, &POLYMER_DB).with_labelling(&LABELLING);
+        let mut polymer = polymerizer.new_polymer();
+        let ids = residues
+            .iter()
+            .map(|abbr| polymer.new_residue(abbr).unwrap())
+            .collect();
+        (polymer, ids)
+    }
+
+    #[test]
+    fn label_residues() {
+        let (polymer, ids) = labelled_polymer(&["A", "K", "g", "m", "x", "X"]);
+        let formulae: Vec<_> = ids
+            .into_iter()
+            .map(|id| polymer.residue(id).unwrap().formula().to_string())
+            .collect();
+        assert_eq!(
+            formulae,
+            [
+                "C3H7[15N]O2",
+                "C6H14[15N]2O2",
+                "C2[13C]6H15NO6",
+                "C11H19NO8",
+                "",
+                ""
+            ]
+        );
+
+        let (polymer, ids) = labelled_polymer(&["A"]);
+        let alanine = polymer.residue(ids[0]).unwrap();
+        assert_eq!(alanine.isotope_label().to_string(), "[15N]-N");
+        assert_eq!(
+            alanine.monoisotopic_mass(),
+            MonoisotopicMass(dec!(90.04471336363))
+        );
+    }
+
+    #[test]
+    fn labelled_masses() {
+        let unlabelled = Polymerizer::new(&ATOMIC_DB, &POLYMER_DB);
+        let mut light = unlabelled.new_polymer();
+        for abbr in ["g", "m", "A", "E", "J", "A"] {
+            light.new_residue(abbr).unwrap();
+        }
+        let (heavy, _) = labelled_polymer(&["g", "m", "A", "E", "J", "A"]);
+
+        assert_eq!(heavy.isotope_label().to_string(), "[13C]6[15N]5-C6N5");
+        let masses = heavy.labelled_masses();
+        assert_eq!(masses.light, light.monoisotopic_mass());
+        assert_eq!(masses.heavy, heavy.monoisotopic_mass());
+        assert_eq!(
+            masses.heavy - masses.light,
+            MonoisotopicMass(dec!(11.00530348267))
+        );
+
+        let masses = light.labelled_masses();
+        assert_eq!(masses.light, masses.heavy);
+    }
+
+    #[test]
+    fn insufficient_atoms() {
+        let mut labelling = LabellingScheme::new(&ATOMIC_DB);
+        labelling
+            .label_atoms(GLCNAC, "[13C]", Count::new(9).unwrap())
+            .unwrap();
+        let polymerizer = Polymerizer::new(&ATOMIC_DB, &POLYMER_DB).with_labelling(&labelling);
+        let mut polymer = polymerizer.new_polymer();
+        assert!(polymer.new_residue("A").is_ok());
+        assert_eq!(
+            polymer.new_residue("g").unwrap_err().to_string(),
+            "failed to label 9 atoms of residue N-Acetylglucosamine (g) with [13C], since it only contains 8 \
+            unlabelled C atoms"
+        );
+        assert_eq!(polymer.residue_ids().count(), 1);
+    }
+
+    #[test]
+    fn invalid_isotopes() {
+        let mut labelling = LabellingScheme::new(&ATOMIC_DB);
+        for isotope in ["C", "[13C]2", "[13C][15N]", "[15N]+p", "D"] {
+            assert_eq!(
+                labelling
+                    .label_all(AMINO_ACIDS, isotope)
+                    .unwrap_err()
+                    .to_string(),
+                format!(
+                    "the composition {isotope:?} is not a single isotope, so it can't be used as an isotope label"
+                )
+            );
+        }
+        assert!(labelling.label_all(AMINO_ACIDS, "[99C]").is_err());
+        assert!(labelling.label_all(AMINO_ACIDS, "[2H]").is_ok());
+        assert_eq!(labelling.labels.len(), 1);
+    }
+}
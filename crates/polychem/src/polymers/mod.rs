@@ -1,4 +1,7 @@
+mod canonical_form;
+mod delta_search;
 pub(crate) mod errors;
+mod labelling_scheme;
 pub(crate) mod modification_info;
 mod polymer;
 pub mod polymerizer;
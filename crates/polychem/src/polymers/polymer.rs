@@ -11,9 +11,9 @@ use crate::{
         target::Target,
     },
     AnyMod, AnyModification, AtomicDatabase, AverageMass, Bond, BondId, BondInfo, Charge, Charged,
-    ChemicalComposition, Count, FunctionalGroup, GroupState, Massive, Modification, ModificationId,
-    ModificationInfo, MonoisotopicMass, NamedMod, OffsetKind, OffsetMod, Polymer, PolymerDatabase,
-    Residue, ResidueGroup, ResidueId, Result,
+    ChemicalComposition, Count, Formulaic, FunctionalGroup, GroupState, LabelledMasses, Massive,
+    Modification, ModificationId, ModificationInfo, MolecularFormula, MonoisotopicMass, NamedMod,
+    OffsetKind, OffsetMod, Polymer, PolymerDatabase, Residue, ResidueGroup, ResidueId, Result,
 };
 
 use super::{errors::FindFreeGroupsError, polymerizer_state::PolymerizerState};
@@ -30,7 +30,10 @@ impl<'a, 'p> Polymer<'a, 'p> {
     }
 
     pub fn new_residue(&mut self, abbr: impl AsRef<str>) -> Result<ResidueId> {
-        let residue = Residue::new(self.polymer_db(), abbr)?;
+        let mut residue = Residue::new(self.polymer_db(), abbr)?;
+        if let Some(labelling) = self.polymerizer_state.polymerizer.labelling() {
+            labelling.label_residue(&mut residue)?;
+        }
         let id = ResidueId(self.polymerizer_state.next_id());
         self.polymerizer_state.index_residue_groups(id, &residue);
         self.residues.insert(id, residue);
@@ -79,6 +82,21 @@ impl<'a, 'p> Polymer<'a, 'p> {
         self.residues.keys().copied()
     }
 
+    #[must_use]
+    pub fn isotope_label(&self) -> MolecularFormula<'a> {
+        self.residues
+            .values()
+            .map(|residue| residue.isotope_label().clone())
+            .sum()
+    }
+
+    #[must_use]
+    pub fn labelled_masses(&self) -> LabelledMasses {
+        let heavy = self.monoisotopic_mass();
+        let light = heavy - self.isotope_label().monoisotopic_mass();
+        LabelledMasses { light, heavy }
+    }
+
     // FIXME: This feels a bit inconsistent with the `named_mod` that I've been using elsewhere? Maybe instead of
     // `named_mod`s and `offset_mod`s, I should just go with `modification`s and `offset`s?
     pub fn new_modification(
@@ -617,6 +635,12 @@ impl Charged for Polymer<'_, '_> {
     }
 }
 
+impl<'a> Formulaic<'a> for Polymer<'a, '_> {
+    fn formula(&self) -> MolecularFormula<'a> {
+        sum_parts!(self, Formulaic::formula)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use insta::assert_ron_snapshot;
@@ -1635,6 +1659,60 @@ mod tests {
         assert_miette_snapshot!(residue_from_wrong_polymer);
     }
 
+    #[test]
+    fn formula() {
+        let mut polymer = POLYMERIZER.new_polymer();
+        assert_eq!(polymer.formula().to_string(), "");
+
+        let alanine = polymer.new_residue("A").unwrap();
+        assert_eq!(polymer.formula().to_string(), "C3H7NO2");
+
+        polymer
+            .offset_residue(OffsetKind::Add, 1, "p", alanine)
+            .unwrap();
+        polymer
+            .offset_residue(OffsetKind::Add, 2, "e", alanine)
+            .unwrap();
+        assert_eq!(polymer.formula().to_string(), "C3H7NO2+2e+p");
+
+        let murnac = polymer.new_residue("m").unwrap();
+        polymer
+            .offset_residue(OffsetKind::Add, 1, "H2O+p", murnac)
+            .unwrap();
+        polymer
+            .offset_residue(OffsetKind::Remove, 4, "CO", alanine)
+            .unwrap();
+        assert_eq!(polymer.formula().to_string(), "C10H28N2O7+2e+2p");
+        assert_eq!(
+            polymer.formula().monoisotopic_mass(),
+            polymer.monoisotopic_mass()
+        );
+        assert_eq!(polymer.formula().average_mass(), polymer.average_mass());
+        assert_eq!(polymer.formula().charge(), polymer.charge());
+
+        // NOTE: Removing more atoms than the polymer contains leaves some negative counts
+        polymer
+            .offset_residue(OffsetKind::Remove, 8, "N", alanine)
+            .unwrap();
+        assert_eq!(polymer.formula().to_string(), "C10H28O7-N6+2e+2p");
+
+        let mut polymer = POLYMERIZER.new_polymer();
+        let glcnac = polymer.new_residue("g").unwrap();
+        let murnac = polymer.new_residue("m").unwrap();
+        polymer.bond_residues("Gly", glcnac, murnac).unwrap();
+        assert_eq!(polymer.formula().to_string(), "C19H32N2O13");
+
+        let (stem, _) = polymer.new_chain("Pep", STEM_RESIDUES).unwrap();
+        polymer.bond_residues("Stem", murnac, stem[0]).unwrap();
+        polymer.modify_polymer("Red").unwrap();
+        assert_eq!(polymer.formula().to_string(), "C37H63N7O21");
+        assert_eq!(
+            polymer.formula().monoisotopic_mass(),
+            polymer.monoisotopic_mass()
+        );
+        assert_eq!(polymer.formula().average_mass(), polymer.average_mass());
+    }
+
     #[test]
     fn offset_residue_with_composition() {
         let mut polymer_a = POLYMERIZER.new_polymer();
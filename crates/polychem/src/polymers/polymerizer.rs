@@ -1,6 +1,6 @@
 use ahash::{HashMap, HashMapExt};
 
-use crate::{AtomicDatabase, Polymer, PolymerDatabase};
+use crate::{AtomicDatabase, LabellingScheme, Polymer, PolymerDatabase};
 
 use super::polymerizer_state::PolymerizerState;
 
@@ -8,6 +8,7 @@ use super::polymerizer_state::PolymerizerState;
 pub struct Polymerizer<'a, 'p> {
     atomic_db: &'a AtomicDatabase,
     polymer_db: &'p PolymerDatabase<'a>,
+    labelling: Option<&'p LabellingScheme<'a>>,
 }
 
 impl<'a, 'p> Polymerizer<'a, 'p> {
@@ -16,6 +17,17 @@ impl<'a, 'p> Polymerizer<'a, 'p> {
         Self {
             atomic_db,
             polymer_db,
+            labelling: None,
+        }
+    }
+
+    // NOTE: Every residue added to polymers built by the returned `Polymerizer` is labelled using `labelling` — the
+    // labelling scheme is borrowed for as long as the `PolymerDatabase` is
+    #[must_use]
+    pub const fn with_labelling(self, labelling: &'p LabellingScheme<'a>) -> Self {
+        Self {
+            labelling: Some(labelling),
+            ..self
         }
     }
 
@@ -29,6 +41,11 @@ impl<'a, 'p> Polymerizer<'a, 'p> {
         self.polymer_db
     }
 
+    #[must_use]
+    pub const fn labelling(&self) -> Option<&'p LabellingScheme<'a>> {
+        self.labelling
+    }
+
     #[must_use]
     pub fn new_polymer(&self) -> Polymer<'a, 'p> {
         let polymerizer_state = PolymerizerState::new(self);
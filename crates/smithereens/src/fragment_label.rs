@@ -0,0 +1,148 @@
+use std::fmt::{self, Display, Formatter};
+
+use itertools::Itertools;
+use polychem::BondId;
+
+use crate::{Derivation, FragmentIon};
+
+// NOTE: Owns all of its data, so that labels can outlive both the `FragmentationRules` and the parent polymer that
+// they were generated from
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct FragmentLabel {
+    // NOTE: Records each bond broken to form this fragment, and the terminus left behind on this fragment's side of it
+    cuts: Vec<(BondId, String)>,
+    residues: usize,
+    charge: i64,
+    derivation: Option<Derivation>,
+}
+
+impl FragmentLabel {
+    pub(crate) fn new(ion: &FragmentIon, charge: i64) -> Self {
+        // NOTE: Cuts are sorted by their terminus, so that equivalent ions are labelled identically
+        let cuts = ion
+            .cuts
+            .iter()
+            .map(|&(bond, name)| (bond, name.to_owned()))
+            .sorted_unstable_by(|(a_bond, a_name), (b_bond, b_name)| {
+                (a_name, a_bond).cmp(&(b_name, b_bond))
+            })
+            .collect();
+
+        Self {
+            cuts,
+            residues: ion.polymer.residue_ids().count(),
+            charge,
+            derivation: ion.derivation.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn cuts(&self) -> &[(BondId, String)] {
+        &self.cuts
+    }
+
+    #[must_use]
+    pub const fn residues(&self) -> usize {
+        self.residues
+    }
+
+    #[must_use]
+    pub const fn charge(&self) -> i64 {
+        self.charge
+    }
+
+    #[must_use]
+    pub const fn derivation(&self) -> Option<&Derivation> {
+        self.derivation.as_ref()
+    }
+}
+
+// NOTE: Ions are labelled with each terminus they carry, followed by the number of residues they contain — `y2` for an
+// ion formed by a single cut, or `BY3` for one formed by two. Ions without any termini are unfragmented, so they're
+// labelled `M`. Any neutral loss is appended (`y2-NH3`), freed ions are labelled as oxonium ions (`g oxonium
+// 168.0655`), and charges other than +1 are written after the label in brackets (`[BY3]2+`)
+impl Display for FragmentLabel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let label = match &self.derivation {
+            Some(freed @ Derivation::Freed { .. }) => freed.to_string(),
+            derivation => {
+                let ion = if self.cuts.is_empty() {
+                    "M".to_owned()
+                } else {
+                    let termini = self.cuts.iter().map(|(_, name)| name).join("");
+                    format!("{termini}{}", self.residues)
+                };
+                match derivation {
+                    Some(loss) => format!("{ion}{loss}"),
+                    None => ion,
+                }
+            }
+        };
+
+        match self.charge {
+            1 => write!(f, "{label}"),
+            0 => write!(f, "[{label}]0"),
+            charge if charge > 0 => write!(f, "[{label}]{charge}+"),
+            charge => write!(f, "[{label}]{}-", charge.unsigned_abs()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use crate::testing_tools::{fragment_ions, muropeptide};
+
+    // NOTE: Checks that an ion with each label is generated from `gm-AEJA`, with the given m/z (rounded to four decimal
+    // places)
+    fn assert_labelled(expected: &[(&str, Decimal)]) {
+        let ions = fragment_ions(&muropeptide(&["A", "E", "J", "A"]));
+        for &(label, mz) in expected {
+            assert!(
+                ions.iter().any(|(l, m)| l == label && m.round_dp(4) == mz),
+                "no {label} ion at m/z {mz}"
+            );
+        }
+    }
+
+    #[test]
+    fn display_primary_ions() {
+        assert_labelled(&[
+            // NOTE: The unfragmented muropeptide
+            ("M", dec!(942.4150)),
+            ("[M]2+", dec!(471.7111)),
+            // NOTE: Ions formed by a single cut
+            ("C1", dec!(222.0972)),
+            ("Z5", dec!(721.3250)),
+            ("b-lac2", dec!(409.1817)),
+            ("y-lac4", dec!(534.2406)),
+            ("[y2]2+", dec!(131.5735)),
+            // NOTE: Internal ions formed by two cuts — the lone glutamate, MurNAc, and MurNAc-Ala, and Ala-Glu with the
+            // lactyl group of MurNAc
+            ("by1", dec!(130.0499)),
+            ("Yb1", dec!(278.1234)),
+            ("Zb-lac1", dec!(188.0917)),
+            ("Yb2", dec!(349.1605)),
+            ("by-lac2", dec!(273.1081)),
+        ]);
+    }
+
+    #[test]
+    fn display_secondary_ions() {
+        assert_labelled(&[
+            // NOTE: Neutral losses, including the immonium ion of glutamate
+            ("y2-H2O", dec!(244.1292)),
+            ("y3-H2OCONH2", dec!(329.1581)),
+            ("by1-CO", dec!(102.0550)),
+            // NOTE: Freed ions are labelled by their m/z, and are never given any extra charges
+            ("g oxonium 168.0655", dec!(168.0655)),
+            ("m oxonium 138.0550", dec!(138.0550)),
+        ]);
+        let ions = fragment_ions(&muropeptide(&["A", "E", "J", "A"]));
+        assert!(!ions
+            .iter()
+            .any(|(label, _)| label.starts_with("[g oxonium")));
+    }
+}
@@ -0,0 +1,1177 @@
+// Standard Library Imports
+use std::collections::hash_map::Entry;
+
+// External Crate Imports
+use ahash::{HashMap, HashMapExt};
+use itertools::Itertools;
+use knuffel::{
+    span::{Span, Spanned},
+    Decode,
+};
+use miette::{Diagnostic, LabeledSpan, NamedSource, Result};
+use polychem::{
+    errors::PolychemError, AtomicDatabase, Charged, ChemicalComposition, PolymerDatabase,
+};
+use thiserror::Error;
+
+// Public API ==========================================================================================================
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FragmentationRules<'a> {
+    pub max_depth: Option<usize>,
+    pub termini: Termini<'a>,
+    pub cleavages: Cleavages,
+    pub secondary_losses: Vec<SecondaryLoss<'a>>,
+    pub charges: ChargeDescription<'a>,
+}
+
+impl<'a> FragmentationRules<'a> {
+    pub fn new(
+        atomic_db: &'a AtomicDatabase,
+        polymer_db: &PolymerDatabase<'a>,
+        file_name: impl AsRef<str>,
+        kdl_text: impl AsRef<str>,
+    ) -> Result<Self> {
+        let parsed_rules: FragmentationRulesKdl =
+            knuffel::parse(file_name.as_ref(), kdl_text.as_ref())?;
+        parsed_rules
+            .validate((atomic_db, polymer_db))
+            .map_err(|e| e.finalize(file_name, kdl_text).into())
+    }
+}
+
+// Private Types =======================================================================================================
+
+type Termini<'a> = HashMap<String, TerminusDescription<'a>>;
+// NOTE: These are keyed by the abbreviation of the bond being cleaved, as it's defined in the polymer database
+type Cleavages = HashMap<String, CleavageDescription>;
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TerminusDescription<'a> {
+    pub lost: ChemicalComposition<'a>,
+    pub gained: ChemicalComposition<'a>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CleavageDescription {
+    pub donors: Vec<String>,
+    pub acceptors: Vec<String>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum SecondaryLoss<'a> {
+    Residue {
+        abbr: String,
+        termini: Option<TerminiFilter>,
+        freed: Vec<ChemicalComposition<'a>>,
+        lost: Vec<ChemicalComposition<'a>>,
+    },
+    Fragment {
+        residues: Option<u32>,
+        termini: Option<TerminiFilter>,
+        lost: Vec<ChemicalComposition<'a>>,
+    },
+    Group {
+        name: String,
+        location: Option<String>,
+        lost: Vec<ChemicalComposition<'a>>,
+    },
+}
+
+// NOTE: Each inner `Vec` of `has` lists a set of alternative termini — at least one of them must be present for that
+// condition to be satisfied
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TerminiFilter {
+    pub count: Option<u32>,
+    pub has: Vec<Vec<String>>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ChargeDescription<'a> {
+    pub min: u32,
+    pub max: u32,
+    // NOTE: The most charges that each residue of a fragment can carry — when this is `None`, every fragment can take
+    // on every charge state between `min` and `max`
+    pub per_residue: Option<u32>,
+    pub carriers: Vec<ChargeCarrier<'a>>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ChargeCarrier<'a> {
+    pub composition: ChemicalComposition<'a>,
+    pub max: Option<u32>,
+}
+
+// Charge State Enumeration ============================================================================================
+
+pub type ChargeState<'c, 'a> = Vec<(&'c ChemicalComposition<'a>, u32)>;
+
+impl<'a> ChargeDescription<'a> {
+    // NOTE: Carriers that aren't used (have a count of zero) are left out of the returned charge states
+    #[must_use]
+    pub fn charge_states(&self) -> Vec<ChargeState<'_, 'a>> {
+        let charge_of = |carrier: &ChargeCarrier| i64::from(carrier.composition.charge());
+        let (min, max) = (i64::from(self.min), i64::from(self.max));
+
+        self.carriers
+            .iter()
+            .map(|carrier| {
+                // NOTE: Validation ensures that no carriers are uncharged, so this division is safe
+                let most_by_charge =
+                    u32::try_from(max / charge_of(carrier).abs()).unwrap_or(u32::MAX);
+                let most = carrier
+                    .max
+                    .map_or(most_by_charge, |m| m.min(most_by_charge));
+                (0..=most).map(move |count| (carrier, count))
+            })
+            .multi_cartesian_product()
+            .filter(|carriers| {
+                let charge: i64 = carriers
+                    .iter()
+                    .map(|&(carrier, count)| charge_of(carrier) * i64::from(count))
+                    .sum();
+                (min..=max).contains(&charge)
+            })
+            .map(|carriers| {
+                carriers
+                    .into_iter()
+                    .filter(|&(_, count)| count != 0)
+                    .map(|(carrier, count)| (&carrier.composition, count))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// Termini Filtering ===================================================================================================
+
+impl TerminiFilter {
+    // NOTE: `termini` should yield the name of each terminus present, along with the number of times it appears
+    #[must_use]
+    pub fn matches<'t>(&self, termini: impl Iterator<Item = (&'t str, u32)> + Clone) -> bool {
+        let count_matches = self.count.map_or(true, |count| {
+            termini.clone().map(|(_, n)| n).sum::<u32>() == count
+        });
+        let has_matches = self.has.iter().all(|alternatives| {
+            termini
+                .clone()
+                .any(|(name, _)| alternatives.iter().any(|alternative| alternative == name))
+        });
+        count_matches && has_matches
+    }
+}
+
+// KDL File Schema =====================================================================================================
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct FragmentationRulesKdl {
+    #[knuffel(child)]
+    cleavages: CleavagesKdl,
+    #[knuffel(child)]
+    secondary_losses: Option<SecondaryLossesKdl>,
+    #[knuffel(child)]
+    charges: ChargesKdl,
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct CleavagesKdl {
+    #[knuffel(property)]
+    max: Option<usize>,
+    #[knuffel(child, unwrap(children))]
+    termini: Vec<TerminusKdl>,
+    #[knuffel(children)]
+    cleavages: Vec<CleavageKdl>,
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct SecondaryLossesKdl {
+    #[knuffel(children)]
+    losses: Vec<SecondaryLossKdl>,
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct ChargesKdl {
+    #[knuffel(span)]
+    span: Span,
+    #[knuffel(property, default = 1)]
+    min: u32,
+    #[knuffel(property)]
+    max: u32,
+    #[knuffel(property)]
+    per_residue: Option<u32>,
+    #[knuffel(children(non_empty))]
+    carriers: Vec<ChargeCarrierKdl>,
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct TerminusKdl {
+    #[knuffel(span)]
+    span: Span,
+    #[knuffel(node_name)]
+    name: String,
+    #[knuffel(property)]
+    lost: Option<ChemicalCompositionKdl>,
+    #[knuffel(property)]
+    gained: Option<ChemicalCompositionKdl>,
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct CleavageKdl {
+    #[knuffel(span)]
+    span: Span,
+    #[knuffel(node_name)]
+    bond: String,
+    #[knuffel(child, unwrap(arguments))]
+    donor: Vec<TerminusNameKdl>,
+    #[knuffel(child, unwrap(arguments))]
+    acceptor: Vec<TerminusNameKdl>,
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+enum SecondaryLossKdl {
+    Residue(ResidueLossKdl),
+    Fragment(FragmentLossKdl),
+    Group(GroupLossKdl),
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct ChargeCarrierKdl {
+    #[knuffel(span)]
+    span: Span,
+    #[knuffel(node_name)]
+    composition: String,
+    #[knuffel(property)]
+    max: Option<u32>,
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct ResidueLossKdl {
+    #[knuffel(span)]
+    span: Span,
+    #[knuffel(argument)]
+    abbr: String,
+    #[knuffel(child)]
+    termini: Option<TerminiFilterKdl>,
+    #[knuffel(children(name = "freed"))]
+    freed: Vec<FreedKdl>,
+    #[knuffel(child, unwrap(arguments))]
+    lost: Option<Vec<ChemicalCompositionKdl>>,
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct FragmentLossKdl {
+    #[knuffel(child, unwrap(argument))]
+    residues: Option<u32>,
+    #[knuffel(child)]
+    termini: Option<TerminiFilterKdl>,
+    #[knuffel(child, unwrap(arguments))]
+    lost: Vec<ChemicalCompositionKdl>,
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct GroupLossKdl {
+    #[knuffel(span)]
+    span: Span,
+    #[knuffel(argument)]
+    name: String,
+    #[knuffel(child, unwrap(argument))]
+    at: Option<String>,
+    #[knuffel(child, unwrap(arguments))]
+    lost: Vec<ChemicalCompositionKdl>,
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct TerminiFilterKdl {
+    #[knuffel(argument)]
+    count: Option<u32>,
+    #[knuffel(children(name = "has"))]
+    has: Vec<HasKdl>,
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct HasKdl {
+    #[knuffel(arguments)]
+    termini: Vec<TerminusNameKdl>,
+}
+
+#[derive(Debug, Decode)]
+#[knuffel(span_type=Span)]
+struct FreedKdl {
+    #[knuffel(argument)]
+    composition: ChemicalCompositionKdl,
+}
+
+type ChemicalCompositionKdl = Spanned<String, Span>;
+type TerminusNameKdl = Spanned<String, Span>;
+
+// Contextual Validation Trait  ========================================================================================
+
+type RulesResult<T> = Result<T, RulesErrorKind>;
+
+trait ValidateInto<'c, T> {
+    type Context: 'c;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<T>;
+}
+
+// Fragmentation Rules Validation ======================================================================================
+
+impl<'a: 'r, 'r> ValidateInto<'r, FragmentationRules<'a>> for FragmentationRulesKdl {
+    type Context = (&'a AtomicDatabase, &'r PolymerDatabase<'a>);
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<FragmentationRules<'a>> {
+        let (max_depth, termini, cleavages) = self.cleavages.validate(ctx)?;
+        let secondary_losses = self
+            .secondary_losses
+            .map_or_else(|| Ok(Vec::new()), |l| l.validate((ctx.0, ctx.1, &termini)))?;
+
+        Ok(FragmentationRules {
+            max_depth,
+            termini,
+            cleavages,
+            secondary_losses,
+            charges: self.charges.validate(ctx.0)?,
+        })
+    }
+}
+
+// Validate Cleavages and Termini ======================================================================================
+
+type CleavageRules<'a> = (Option<usize>, Termini<'a>, Cleavages);
+
+impl<'a: 'r, 'r> ValidateInto<'r, CleavageRules<'a>> for CleavagesKdl {
+    type Context = (&'a AtomicDatabase, &'r PolymerDatabase<'a>);
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<CleavageRules<'a>> {
+        let termini = self.termini.validate(ctx.0)?;
+
+        let mut seen_cleavages = HashMap::new();
+        for cleavage in self.cleavages {
+            let span = cleavage.span;
+            let (bond, description) = cleavage.validate((ctx.1, &termini))?;
+
+            match seen_cleavages.entry(bond) {
+                Entry::Occupied(e) => {
+                    let (bond, (first_defined_at, _)) = e.remove_entry();
+                    return Err(RulesErrorKind::DuplicateCleavage(
+                        first_defined_at,
+                        span,
+                        bond,
+                    ));
+                }
+                Entry::Vacant(e) => e.insert((span, description)),
+            };
+        }
+        let cleavages = seen_cleavages
+            .into_iter()
+            .map(|(k, (_, v))| (k, v))
+            .collect();
+
+        Ok((self.max, termini, cleavages))
+    }
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+impl<'a> ValidateInto<'a, Termini<'a>> for Vec<TerminusKdl> {
+    type Context = &'a AtomicDatabase;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<Termini<'a>> {
+        let mut seen_termini = HashMap::new();
+
+        for terminus in self {
+            let description = TerminusDescription {
+                lost: terminus.lost.validate(ctx)?,
+                gained: terminus.gained.validate(ctx)?,
+            };
+
+            match seen_termini.entry(terminus.name) {
+                Entry::Occupied(e) => {
+                    let (terminus_name, (first_defined_at, _)) = e.remove_entry();
+                    return Err(RulesErrorKind::DuplicateTerminus(
+                        first_defined_at,
+                        terminus.span,
+                        terminus_name,
+                    ));
+                }
+                Entry::Vacant(e) => e.insert((terminus.span, description)),
+            };
+        }
+
+        Ok(seen_termini.into_iter().map(|(k, (_, v))| (k, v)).collect())
+    }
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+type CleavageEntry = (String, CleavageDescription);
+
+impl<'a: 'r, 'r> ValidateInto<'r, CleavageEntry> for CleavageKdl {
+    type Context = (&'r PolymerDatabase<'a>, &'r Termini<'a>);
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<CleavageEntry> {
+        if !ctx.0.bonds.contains_key(&self.bond) {
+            return Err(RulesErrorKind::UndefinedBond(self.span, self.bond));
+        }
+
+        let validate_side = |names: Vec<TerminusNameKdl>, side| {
+            if names.is_empty() {
+                Err(RulesErrorKind::MissingTermini(
+                    self.span,
+                    self.bond.clone(),
+                    side,
+                ))
+            } else {
+                validate_terminus_names(names, ctx.1)
+            }
+        };
+        let donors = validate_side(self.donor, "donor")?;
+        let acceptors = validate_side(self.acceptor, "acceptor")?;
+
+        Ok((self.bond, CleavageDescription { donors, acceptors }))
+    }
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+// NOTE: This is a bare function (and not a `ValidateInto` implementation), since `TerminusNameKdl` and
+// `ChemicalCompositionKdl` are the same underlying type, and a second `Vec<_>` implementation would make every call
+// to `.validate()` ambiguous
+fn validate_terminus_names(
+    names: Vec<TerminusNameKdl>,
+    termini: &Termini,
+) -> RulesResult<Vec<String>> {
+    names
+        .into_iter()
+        .map(|name| {
+            if termini.contains_key(&*name) {
+                Ok((*name).clone())
+            } else {
+                Err(RulesErrorKind::UndefinedTerminus(
+                    *name.span(),
+                    (*name).clone(),
+                ))
+            }
+        })
+        .collect()
+}
+
+// Validate Secondary Losses ===========================================================================================
+
+type LossContext<'a, 'r> = (&'a AtomicDatabase, &'r PolymerDatabase<'a>, &'r Termini<'a>);
+
+impl<'a: 'r, 'r> ValidateInto<'r, Vec<SecondaryLoss<'a>>> for SecondaryLossesKdl {
+    type Context = LossContext<'a, 'r>;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<Vec<SecondaryLoss<'a>>> {
+        self.losses.into_iter().map(|l| l.validate(ctx)).collect()
+    }
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+impl<'a: 'r, 'r> ValidateInto<'r, SecondaryLoss<'a>> for SecondaryLossKdl {
+    type Context = LossContext<'a, 'r>;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<SecondaryLoss<'a>> {
+        let (atomic_db, polymer_db, termini) = ctx;
+        match self {
+            Self::Residue(ResidueLossKdl {
+                span,
+                abbr,
+                termini: filter,
+                freed,
+                lost,
+            }) => {
+                if !polymer_db.residues.contains_key(&abbr) {
+                    return Err(RulesErrorKind::UndefinedResidue(span, abbr));
+                }
+
+                Ok(SecondaryLoss::Residue {
+                    abbr,
+                    termini: filter.map(|f| f.validate(termini)).transpose()?,
+                    freed: freed
+                        .into_iter()
+                        .map(|f| f.composition.validate(atomic_db))
+                        .try_collect()?,
+                    lost: lost.unwrap_or_default().validate(atomic_db)?,
+                })
+            }
+            Self::Fragment(FragmentLossKdl {
+                residues,
+                termini: filter,
+                lost,
+            }) => Ok(SecondaryLoss::Fragment {
+                residues,
+                termini: filter.map(|f| f.validate(termini)).transpose()?,
+                lost: lost.validate(atomic_db)?,
+            }),
+            Self::Group(GroupLossKdl {
+                span,
+                name,
+                at,
+                lost,
+            }) => {
+                let group_exists = polymer_db
+                    .residues
+                    .values()
+                    .flat_map(|r| &r.functional_groups)
+                    .any(|g| g.name == name && at.as_ref().map_or(true, |at| &g.location == at));
+
+                if !group_exists {
+                    let group =
+                        at.map_or_else(|| format!("{name:?}"), |at| format!("{name:?} at={at:?}"));
+                    return Err(RulesErrorKind::NonexistentGroup(span, group));
+                }
+
+                Ok(SecondaryLoss::Group {
+                    name,
+                    location: at,
+                    lost: lost.validate(atomic_db)?,
+                })
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+impl<'a: 'r, 'r> ValidateInto<'r, TerminiFilter> for TerminiFilterKdl {
+    type Context = &'r Termini<'a>;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<TerminiFilter> {
+        Ok(TerminiFilter {
+            count: self.count,
+            has: self
+                .has
+                .into_iter()
+                .map(|h| validate_terminus_names(h.termini, ctx))
+                .try_collect()?,
+        })
+    }
+}
+
+// Validate Charges ====================================================================================================
+
+impl<'a> ValidateInto<'a, ChargeDescription<'a>> for ChargesKdl {
+    type Context = &'a AtomicDatabase;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<ChargeDescription<'a>> {
+        if self.min > self.max {
+            return Err(RulesErrorKind::InvalidChargeRange(
+                self.span, self.min, self.max,
+            ));
+        }
+
+        Ok(ChargeDescription {
+            min: self.min,
+            max: self.max,
+            per_residue: self.per_residue,
+            carriers: self
+                .carriers
+                .into_iter()
+                .map(|c| c.validate(ctx))
+                .try_collect()?,
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+impl<'a> ValidateInto<'a, ChargeCarrier<'a>> for ChargeCarrierKdl {
+    type Context = &'a AtomicDatabase;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<ChargeCarrier<'a>> {
+        let composition = ChemicalComposition::new(ctx, &self.composition)
+            .map_err(|e| RulesErrorKind::Composition(self.span, *e))?;
+
+        if i64::from(composition.charge()) == 0 {
+            return Err(RulesErrorKind::UnchargedCarrier(
+                self.span,
+                self.composition,
+            ));
+        }
+
+        Ok(ChargeCarrier {
+            composition,
+            max: self.max,
+        })
+    }
+}
+
+// Validate Chemical Compositions ======================================================================================
+
+impl<'a> ValidateInto<'a, Vec<ChemicalComposition<'a>>> for Vec<ChemicalCompositionKdl> {
+    type Context = &'a AtomicDatabase;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<Vec<ChemicalComposition<'a>>> {
+        self.into_iter().map(|c| c.validate(ctx)).collect()
+    }
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+impl<'a> ValidateInto<'a, ChemicalComposition<'a>> for Option<ChemicalCompositionKdl> {
+    type Context = &'a AtomicDatabase;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<ChemicalComposition<'a>> {
+        self.map_or_else(|| Ok(ChemicalComposition::default()), |c| c.validate(ctx))
+    }
+}
+
+// ---------------------------------------------------------------------------------------------------------------------
+
+impl<'a> ValidateInto<'a, ChemicalComposition<'a>> for ChemicalCompositionKdl {
+    type Context = &'a AtomicDatabase;
+
+    fn validate(self, ctx: Self::Context) -> RulesResult<ChemicalComposition<'a>> {
+        ChemicalComposition::new(ctx, &*self)
+            .map_err(|e| RulesErrorKind::Composition(*self.span(), *e))
+    }
+}
+
+// Validation Error Types and Trait Implementations  ===================================================================
+
+#[derive(Debug, Error)]
+#[error("failed to validate fragmentation rules file")]
+struct RulesError {
+    kdl: NamedSource<String>,
+    #[source]
+    kind: RulesErrorKind,
+}
+
+// NOTE: This is manually implemented because the list of labels is dynamic and needs to be extracted from `self.kind`
+impl Diagnostic for RulesError {
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.kdl)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        Some(Box::new(self.kind.labels().into_iter().map(|(s, l)| {
+            LabeledSpan::new_with_span(Some(l.to_owned()), *s)
+        })))
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        Some(&self.kind)
+    }
+}
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+enum RulesErrorKind {
+    #[error("the terminus {2:?} has already been defined")]
+    #[diagnostic(help("consider consolidating duplicate termini or picking a new terminus name"))]
+    DuplicateTerminus(Span, Span, String),
+
+    #[error("the terminus {1:?} is undefined")]
+    #[diagnostic(help("double-check for typos, or add {1:?} to the termini section"))]
+    UndefinedTerminus(Span, String),
+
+    #[error("cleavage rules for the bond {2:?} have already been defined")]
+    #[diagnostic(help("consider consolidating the duplicate cleavage rules"))]
+    DuplicateCleavage(Span, Span, String),
+
+    #[error("the bond {1:?} could not be found in the supplied polymer database")]
+    #[diagnostic(help(
+        "double-check for typos — bonds should be referenced using the same abbreviation as the polymer database"
+    ))]
+    UndefinedBond(Span, String),
+
+    #[error("the cleavage rules for the bond {1:?} don't list any {2} termini")]
+    #[diagnostic(help("add at least one terminus to both the donor and acceptor nodes"))]
+    MissingTermini(Span, String, &'static str),
+
+    #[error("the residue {1:?} could not be found in the supplied polymer database")]
+    #[diagnostic(help("double-check for typos, or add {1:?} to the polymer database"))]
+    UndefinedResidue(Span, String),
+
+    #[error(
+        "the functional group {1} is not present on any residue in the supplied polymer database"
+    )]
+    #[diagnostic(help(
+        "double-check for typos, or add this functional group to the residues in the polymer database"
+    ))]
+    NonexistentGroup(Span, String),
+
+    #[error("the charge carrier {1:?} has no charge")]
+    #[diagnostic(help("charge carriers must include a charged particle, like `p` or `-e`"))]
+    UnchargedCarrier(Span, String),
+
+    #[error("the minimum charge ({1}) is greater than the maximum charge ({2})")]
+    #[diagnostic(help("swap the min= and max= values, or adjust one of them"))]
+    InvalidChargeRange(Span, u32, u32),
+
+    #[error("fragmentation rules file contained an invalid chemical composition")]
+    Composition(
+        Span,
+        #[source]
+        #[diagnostic_source]
+        PolychemError,
+    ),
+}
+
+impl RulesErrorKind {
+    fn labels(&self) -> Vec<(&Span, &'static str)> {
+        match self {
+            Self::DuplicateTerminus(s1, s2, _) | Self::DuplicateCleavage(s1, s2, _) => {
+                vec![(s1, "first defined here"), (s2, "then again here")]
+            }
+            Self::UndefinedTerminus(s, _) => vec![(s, "undefined terminus")],
+            Self::UndefinedBond(s, _) => vec![(s, "undefined bond")],
+            Self::MissingTermini(s, _, _) => vec![(s, "missing termini")],
+            Self::UndefinedResidue(s, _) => vec![(s, "undefined residue")],
+            Self::NonexistentGroup(s, _) => vec![(s, "targets nothing")],
+            Self::UnchargedCarrier(s, _) => vec![(s, "uncharged carrier")],
+            Self::InvalidChargeRange(s, _, _) => vec![(s, "invalid charge range")],
+            Self::Composition(s, _) => vec![(s, "invalid chemical composition")],
+        }
+    }
+
+    fn finalize(self, file_name: impl AsRef<str>, kdl: impl AsRef<str>) -> RulesError {
+        let kdl = NamedSource::new(file_name, kdl.as_ref().to_owned());
+        RulesError { kdl, kind: self }
+    }
+}
+
+// Module Tests ========================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use once_cell::sync::Lazy;
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../../muropeptide/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+
+    const SIMPLE_KDL: &str = include_str!("../tests/data/fragmentation_rules_simple.kdl");
+    const HCD_KDL: &str = include_str!("../../muropeptide/data/hcd_rules.kdl");
+
+    fn parse_rules(kdl: &str) -> Result<FragmentationRules, RulesError> {
+        let rules: FragmentationRulesKdl = knuffel::parse("test", kdl).unwrap();
+        rules
+            .validate((&ATOMIC_DB, &POLYMER_DB))
+            .map_err(|e| e.finalize("test", kdl))
+    }
+
+    #[test]
+    fn parse_simple_rules() {
+        let rules = FragmentationRules::new(&ATOMIC_DB, &POLYMER_DB, "simple.kdl", SIMPLE_KDL);
+        let rules = rules.unwrap();
+
+        assert_eq!(rules.max_depth, Some(1));
+        assert_eq!(rules.termini.keys().sorted().collect_vec(), ["b", "y"]);
+        assert_eq!(
+            rules.cleavages["Pep"],
+            CleavageDescription {
+                donors: vec!["b".to_owned()],
+                acceptors: vec!["y".to_owned()]
+            }
+        );
+        assert_eq!(rules.termini["b"].lost.to_string(), "OHH");
+        assert_eq!(rules.termini["b"].gained, ChemicalComposition::default());
+        assert!(rules.secondary_losses.is_empty());
+        assert_eq!((rules.charges.min, rules.charges.max), (1, 5));
+        assert_eq!(rules.charges.carriers.len(), 2);
+        assert_eq!(rules.charges.carriers[1].max, Some(2));
+    }
+
+    #[test]
+    fn parse_hcd_rules() {
+        let rules = FragmentationRules::new(&ATOMIC_DB, &POLYMER_DB, "hcd_rules.kdl", HCD_KDL);
+        let rules = rules.unwrap();
+
+        assert_eq!(rules.max_depth, None);
+        assert_eq!(rules.termini.len(), 8);
+        assert_eq!(
+            rules.cleavages.keys().sorted().collect_vec(),
+            ["CToN", "Gly", "Link", "NToC", "Pep", "Stem"]
+        );
+        assert_eq!(rules.cleavages["Gly"].donors, ["B", "C"]);
+        assert_eq!(rules.secondary_losses.len(), 7);
+        assert!(matches!(
+            &rules.secondary_losses[0],
+            SecondaryLoss::Residue { abbr, freed, .. } if abbr == "g" && freed.len() == 5
+        ));
+        assert!(matches!(
+            &rules.secondary_losses[2],
+            SecondaryLoss::Fragment { residues: Some(1), termini: Some(TerminiFilter { count: Some(2), has }), .. }
+                if has.len() == 2
+        ));
+        assert!(matches!(
+            &rules.secondary_losses[5],
+            SecondaryLoss::Residue { abbr, lost, .. } if abbr == "E" && lost.len() == 2
+        ));
+        assert_eq!(rules.charges.per_residue, Some(1));
+        assert_eq!(rules.charges.carriers.len(), 3);
+    }
+
+    #[test]
+    fn simple_charge_states() {
+        let rules = FragmentationRules::new(&ATOMIC_DB, &POLYMER_DB, "simple.kdl", SIMPLE_KDL);
+        let rules = rules.unwrap();
+        let charge_states = rules.charges.charge_states();
+
+        // NOTE: Up to 5 protons, and up to 2 sodium ions, making a total charge of 1 to 5
+        assert_eq!(charge_states.len(), 14);
+        for charge_state in &charge_states {
+            let charge: i64 = charge_state
+                .iter()
+                .map(|&(carrier, count)| i64::from(carrier.charge()) * i64::from(count))
+                .sum();
+            assert!((1..=5).contains(&charge));
+            assert!(charge_state.iter().all(|&(_, count)| count != 0));
+        }
+
+        let sodiated = charge_states
+            .iter()
+            .filter(|cs| cs.iter().any(|(c, _)| c.to_string() == "Na-e"));
+        assert!(sodiated
+            .flat_map(|cs| cs.iter())
+            .all(|&(c, n)| c.to_string() != "Na-e" || n <= 2));
+    }
+
+    #[test]
+    fn termini_filter_matches() {
+        let filter = TerminiFilter {
+            count: Some(2),
+            has: vec![
+                vec!["b".to_owned()],
+                vec!["y".to_owned(), "y-lac".to_owned()],
+            ],
+        };
+
+        assert!(filter.matches([("b", 1), ("y", 1)].into_iter()));
+        assert!(filter.matches([("b", 1), ("y-lac", 1)].into_iter()));
+        assert!(!filter.matches([("b", 1), ("y", 2)].into_iter()));
+        assert!(!filter.matches([("b", 2)].into_iter()));
+        assert!(!filter.matches([("y", 1), ("y-lac", 1)].into_iter()));
+
+        let filter = TerminiFilter {
+            count: None,
+            has: vec![vec!["y".to_owned()]],
+        };
+        assert!(filter.matches([("y", 3)].into_iter()));
+        assert!(!filter.matches([].into_iter()));
+    }
+
+    #[test]
+    fn parse_undefined_bond() {
+        let kdl = indoc! {r#"
+            cleavages {
+                termini {
+                    b lost="OHH"
+                    y lost="H" gained="H"
+                }
+                Peptide {
+                    donor "b"
+                    acceptor "y"
+                }
+            }
+            charges max=1 {
+                p
+            }
+        "#};
+        let rules = parse_rules(kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::UndefinedBond(_, bond) if bond == "Peptide"
+        ));
+    }
+
+    #[test]
+    fn parse_undefined_terminus() {
+        let kdl = indoc! {r#"
+            cleavages {
+                termini {
+                    b lost="OHH"
+                    y lost="H" gained="H"
+                }
+                Pep {
+                    donor "b"
+                    acceptor "y" "z"
+                }
+            }
+            charges max=1 {
+                p
+            }
+        "#};
+        let rules = parse_rules(kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::UndefinedTerminus(_, terminus) if terminus == "z"
+        ));
+    }
+
+    #[test]
+    fn parse_duplicate_termini() {
+        let kdl = indoc! {r#"
+            cleavages {
+                termini {
+                    b lost="OHH"
+                    y lost="H" gained="H"
+                    b lost="H2O"
+                }
+                Pep {
+                    donor "b"
+                    acceptor "y"
+                }
+            }
+            charges max=1 {
+                p
+            }
+        "#};
+        let rules = parse_rules(kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::DuplicateTerminus(_, _, terminus) if terminus == "b"
+        ));
+    }
+
+    #[test]
+    fn parse_duplicate_cleavages() {
+        let kdl = indoc! {r#"
+            cleavages {
+                termini {
+                    b lost="OHH"
+                    y lost="H" gained="H"
+                }
+                Pep {
+                    donor "b"
+                    acceptor "y"
+                }
+                Pep {
+                    donor "y"
+                    acceptor "b"
+                }
+            }
+            charges max=1 {
+                p
+            }
+        "#};
+        let rules = parse_rules(kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::DuplicateCleavage(_, _, bond) if bond == "Pep"
+        ));
+    }
+
+    #[test]
+    fn parse_missing_termini() {
+        let kdl = indoc! {r#"
+            cleavages {
+                termini {
+                    b lost="OHH"
+                }
+                Pep {
+                    donor "b"
+                    acceptor
+                }
+            }
+            charges max=1 {
+                p
+            }
+        "#};
+        let rules = parse_rules(kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::MissingTermini(_, bond, "acceptor") if bond == "Pep"
+        ));
+    }
+
+    #[test]
+    fn parse_invalid_terminus_composition() {
+        let kdl = indoc! {r#"
+            cleavages {
+                termini {
+                    b lost="OHh"
+                }
+                Pep {
+                    donor "b"
+                    acceptor "b"
+                }
+            }
+            charges max=1 {
+                p
+            }
+        "#};
+        let rules = parse_rules(kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::Composition(..)
+        ));
+    }
+
+    fn rules_with_losses(secondary_losses: &str) -> String {
+        let kdl = indoc! {r#"
+            cleavages {
+                termini {
+                    b lost="OHH"
+                    y lost="H" gained="H"
+                }
+                Pep {
+                    donor "b"
+                    acceptor "y"
+                }
+            }
+            charges max=1 {
+                p
+            }
+        "#};
+        format!("{kdl}secondary-losses {{\n{secondary_losses}}}\n")
+    }
+
+    #[test]
+    fn parse_secondary_loss_with_undefined_residue() {
+        let kdl = rules_with_losses(indoc! {r#"
+            residue "Z" {
+                lost "H2O"
+            }
+        "#});
+        let rules = parse_rules(&kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::UndefinedResidue(_, residue) if residue == "Z"
+        ));
+    }
+
+    #[test]
+    fn parse_secondary_loss_with_nonexistent_group() {
+        let kdl = rules_with_losses(indoc! {r#"
+            group "Carboxyl" {
+                at "C-terminal"
+                lost "H2O"
+            }
+        "#});
+        let rules = parse_rules(&kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::NonexistentGroup(_, group) if group == r#""Carboxyl" at="C-terminal""#
+        ));
+
+        let kdl = kdl.replace("C-terminal", "C-Terminal");
+        assert!(parse_rules(&kdl).is_ok());
+    }
+
+    #[test]
+    fn parse_secondary_loss_without_group_location() {
+        let kdl = rules_with_losses(indoc! {r#"
+            group "Carboxyl" {
+                lost "H2O"
+            }
+        "#});
+        let rules = parse_rules(&kdl).unwrap();
+        assert_eq!(
+            rules.secondary_losses,
+            [SecondaryLoss::Group {
+                name: "Carboxyl".to_owned(),
+                location: None,
+                lost: vec![ChemicalComposition::new(&ATOMIC_DB, "H2O").unwrap()]
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_secondary_loss_with_undefined_terminus() {
+        let kdl = rules_with_losses(indoc! {r#"
+            fragment {
+                termini {
+                    has "b" "x"
+                }
+                lost "CO"
+            }
+        "#});
+        let rules = parse_rules(&kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::UndefinedTerminus(_, terminus) if terminus == "x"
+        ));
+    }
+
+    fn rules_with_charges(charges: &str) -> String {
+        let kdl = indoc! {r#"
+            cleavages {
+                termini {
+                    b lost="OHH"
+                    y lost="H" gained="H"
+                }
+                Pep {
+                    donor "b"
+                    acceptor "y"
+                }
+            }
+        "#};
+        format!("{kdl}{charges}")
+    }
+
+    #[test]
+    fn parse_charges_with_invalid_range() {
+        let kdl = rules_with_charges(indoc! {"
+            charges min=3 max=2 {
+                p
+            }
+        "});
+        let rules = parse_rules(&kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::InvalidChargeRange(_, 3, 2)
+        ));
+    }
+
+    #[test]
+    fn parse_charges_with_uncharged_carrier() {
+        let kdl = rules_with_charges(indoc! {"
+            charges max=2 {
+                Na
+            }
+        "});
+        let rules = parse_rules(&kdl);
+        assert!(matches!(
+            rules.unwrap_err().kind,
+            RulesErrorKind::UnchargedCarrier(_, carrier) if carrier == "Na"
+        ));
+    }
+
+    #[test]
+    fn parse_charges_with_default_min() {
+        let kdl = rules_with_charges(indoc! {"
+            charges max=2 {
+                Na-e
+            }
+        "});
+        let rules = parse_rules(&kdl).unwrap();
+        assert_eq!((rules.charges.min, rules.charges.max), (1, 2));
+        assert_eq!(rules.charges.per_residue, None);
+    }
+}
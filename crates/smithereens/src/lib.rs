@@ -1,3 +1,9 @@
+mod fragment_label;
+pub mod fragmentation_rules;
+mod secondary_losses;
+#[cfg(test)]
+mod testing_tools;
+
 use std::{
     cmp::Ordering,
     convert::identity,
@@ -5,12 +11,20 @@ use std::{
     hash::{Hash, Hasher},
     mem,
     ops::{Index, IndexMut},
+    slice,
 };
 
 use ahash::{HashSet, HashSetExt};
 use derive_more::IsVariant;
+use fragmentation_rules::{ChargeDescription, ChargeState, TerminusDescription};
 use itertools::Itertools;
-use polychem::{BondId, BondInfo, OffsetKind, Polymer, ResidueGroup, ResidueId};
+use polychem::{
+    BondId, BondInfo, Charged, ChemicalComposition, OffsetKind, Polymer, ResidueGroup, ResidueId,
+};
+
+pub use fragment_label::FragmentLabel;
+pub use fragmentation_rules::FragmentationRules;
+pub use secondary_losses::{Derivation, LossSource};
 
 // FIXME: Consider using newtype? Especially if this is made public!
 type BondAbbr<'p> = &'p str;
@@ -21,6 +35,23 @@ enum Terminal<'p> {
     Acceptor(BondAbbr<'p>),
 }
 
+impl<'p> Terminal<'p> {
+    const fn bond_abbr(self) -> BondAbbr<'p> {
+        match self {
+            Terminal::Donor(abbr) | Terminal::Acceptor(abbr) => abbr,
+        }
+    }
+
+    fn termini<'r>(self, rules: &'r FragmentationRules) -> &'r [String] {
+        // SAFETY: Only bonds with cleavage rules are ever cut, so this lookup should never fail
+        let cleavage = &rules.cleavages[self.bond_abbr()];
+        match self {
+            Terminal::Donor(_) => &cleavage.donors,
+            Terminal::Acceptor(_) => &cleavage.acceptors,
+        }
+    }
+}
+
 impl Display for Terminal<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -124,6 +155,19 @@ impl Display for Fragment<'_> {
     }
 }
 
+// NOTE: A fragment that has been converted back into a `Polymer` — the `termini` list the name and count of every
+// terminus formed on each residue, and are kept around for checking the filters of secondary-loss rules, whilst the
+// `cuts` list the terminus formed at each broken bond for labelling
+#[derive(Clone, Debug)]
+struct FragmentIon<'a, 'p, 'r> {
+    polymer: Polymer<'a, 'p>,
+    lost_residues: Vec<ResidueId>,
+    broken_bonds: Vec<BondId>,
+    termini: Vec<(ResidueId, &'r str, u32)>,
+    cuts: Vec<(BondId, &'r str)>,
+    derivation: Option<Derivation>,
+}
+
 type NodeId = usize;
 
 #[derive(Clone, Debug)]
@@ -166,22 +210,51 @@ pub trait Dissociable<'s, 'a: 'p + 's, 'p: 's>: Sized {
         &self,
         fragmented_polymer: Polymer<'a, 'p>,
         lost_residues: Vec<ResidueId>,
-        broken_bonds: Vec<BondId>,
+        label: FragmentLabel,
     ) -> Self;
     // FIXME: Christ... That's messy...
     #[must_use]
-    fn fragment(&'s self, max_depth: Option<usize>) -> impl Iterator<Item = Self> {
+    fn fragment(&'s self, rules: &'s FragmentationRules<'a>) -> impl Iterator<Item = Self> {
         // FIXME: It's the closure capturing this `&'s Polymer` that leads to issues...
         let polymer = self.polymer();
         let node_mapping = NodeMapping::new(polymer);
-        Fragment::new(&node_mapping, polymer)
-            .fragment(max_depth)
-            .into_iter()
-            .map(move |piece| {
-                let (fragmented_polymer, lost_residues, broken_bonds) =
-                    piece.build_fragment_ion(&node_mapping, polymer);
-                self.new_fragment(fragmented_polymer, lost_residues, broken_bonds)
-            })
+        let charge_states = rules.charges.charge_states();
+        let uncharged = ChargeState::new();
+        let mut freed_ions = HashSet::new();
+        let whole = Fragment::new(&node_mapping, polymer);
+        whole.fragment(rules).into_iter().flat_map(move |piece| {
+            let primary_ions = piece.build_fragment_ions(&node_mapping, &whole, polymer, rules);
+            let secondary_ions: Vec<_> = primary_ions
+                .iter()
+                .flat_map(|ion| ion.secondary_ions(polymer, rules, &mut freed_ions))
+                .collect();
+            // PERF: Collecting here isn't ideal, but the returned iterator can't borrow `charge_states`
+            primary_ions
+                .into_iter()
+                .chain(secondary_ions)
+                .flat_map(|ion| {
+                    // NOTE: Ions that are already charged (like freed oxonium ions) aren't given any more charges
+                    let charge_states = if i64::from(ion.polymer.charge()) == 0 {
+                        charge_states.as_slice()
+                    } else {
+                        slice::from_ref(&uncharged)
+                    };
+                    let max_charge = ion.max_charge(&rules.charges);
+                    charge_states
+                        .iter()
+                        .filter(|charge_state| {
+                            max_charge.map_or(true, |max| charge_of(charge_state).abs() <= max)
+                        })
+                        .map(|charge_state| {
+                            let charged_polymer = charge_fragment_ion(&ion.polymer, charge_state);
+                            let charge = i64::from(charged_polymer.charge());
+                            let label = FragmentLabel::new(&ion, charge);
+                            self.new_fragment(charged_polymer, ion.lost_residues.clone(), label)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        })
     }
 }
 
@@ -212,31 +285,35 @@ impl<'p> Fragment<'p> {
         }
     }
 
+    // NOTE: Cutting the graph only keeps track of the type of bond broken and which side of it each residue was on —
+    // this is where those terminals are expanded into every combination of termini that they could form. Since the
+    // chemistry of each terminus is left unlocalized, only the number of each terminus formed at a terminal matters,
+    // so combinations (with replacement) are generated instead of permutations
     // FIXME: Naming?
-    fn build_fragment_ion<'a>(
+    fn build_fragment_ions<'a, 'r>(
         self,
         node_mapping: &NodeMapping,
+        whole: &Self,
         polymer: &Polymer<'a, 'p>,
-    ) -> (Polymer<'a, 'p>, Vec<ResidueId>, Vec<BondId>) {
+        rules: &'r FragmentationRules<'a>,
+    ) -> Vec<FragmentIon<'a, 'p, 'r>> {
         let mut fragmented_polymer = polymer.clone();
         let mut lost_residues = Vec::new();
+        let mut terminals = Vec::new();
 
         for (id, opt_residue) in self.residues.into_iter().enumerate() {
             let residue_id = node_mapping.0[id];
             if let Some(residue) = opt_residue {
-                // FIXME: This is hacky, hard-coded logic for PG — this will need to be generalized for other molecules!
-                let donor_count: u32 = residue
-                    .terminals
-                    .into_iter()
-                    .filter_map(|(t, c)| t.is_donor().then_some(c))
-                    .sum();
-                if donor_count != 0 {
-                    // SAFETY: This should only panic if `residue_id` doesn't exist (which it should), or if `H2O` isn't a
-                    // valid chemical formula (but it is)
-                    fragmented_polymer
-                        .offset_residue(OffsetKind::Remove, donor_count, "H2O", residue_id)
-                        .unwrap();
-                }
+                terminals.extend(residue.terminals.into_iter().map(|(terminal, count)| {
+                    // NOTE: Finds the `count` bonds that were broken to leave this terminal behind
+                    let cuts: Vec<_> = whole[id]
+                        .bonds
+                        .iter()
+                        .filter(|bond| bond.end == terminal && self.broken_bonds.contains(&bond.id))
+                        .map(|bond| bond.id)
+                        .collect();
+                    (residue_id, terminal, count, cuts)
+                }));
             } else {
                 // FIXME: Not a fan of this mutable state approach... Could I do this with a `.map()`?
                 lost_residues.push(residue_id);
@@ -245,19 +322,71 @@ impl<'p> Fragment<'p> {
         }
 
         // FIXME: Should this be returning an Iterator instead of a `Vec`?
-        let broken_bonds = self
+        let broken_bonds: Vec<_> = self
             .broken_bonds
             .into_iter()
             .filter_map(|id| fragmented_polymer.remove_bond(id).map(|_| id))
             .collect();
 
-        // FIXME: Hard-coded to generate the 1+ ions!
-        // SAFETY: `p` is a valid formula, so this shouldn't panic
-        fragmented_polymer
-            .new_offset(OffsetKind::Add, 1, "p")
-            .unwrap();
+        // NOTE: If there are no terminals (the fragment is the whole polymer), `.multi_cartesian_product()` yields a
+        // single, empty set of termini
+        terminals
+            .into_iter()
+            .map(|(residue_id, terminal, count, cuts)| {
+                terminal
+                    .termini(rules)
+                    .iter()
+                    .map(String::as_str)
+                    .combinations_with_replacement(count as usize)
+                    .map(move |names| {
+                        // NOTE: Bonds broken at the same terminal are interchangeable (their chemistry is left
+                        // unlocalized), so it doesn't matter which of them is paired with which terminus
+                        let cuts = cuts
+                            .iter()
+                            .zip(&names)
+                            .map(|(&bond, &name)| (bond, name))
+                            .collect_vec();
+                        let termini = names
+                            .into_iter()
+                            .dedup_with_count()
+                            // SAFETY: No terminus can be formed more than `count` times, which is already a `u32`
+                            .map(|(n, name)| (residue_id, name, u32::try_from(n).unwrap()))
+                            .collect_vec();
+                        (termini, cuts)
+                    })
+            })
+            .multi_cartesian_product()
+            .map(|alternatives| {
+                let (termini, cuts): (Vec<_>, Vec<_>) = alternatives.into_iter().unzip();
+                let (termini, cuts) = (termini.concat(), cuts.concat());
+                let mut ion_polymer = fragmented_polymer.clone();
+                for &(_, name, count) in &termini {
+                    // SAFETY: Validation ensures every terminus named in a cleavage rule is defined
+                    let TerminusDescription { lost, gained } = &rules.termini[name];
+                    // NOTE: These offsets are left unlocalized, since they describe the type of ion generated, and
+                    // aren't a modification of any particular residue
+                    let mut offset = |kind, composition: &ChemicalComposition<'a>| {
+                        if composition != &ChemicalComposition::default() {
+                            // SAFETY: `count` is never zero, since terminals are only tracked once they are present
+                            ion_polymer
+                                .new_offset_with_composition(kind, count, composition.clone())
+                                .unwrap();
+                        }
+                    };
+                    offset(OffsetKind::Remove, lost);
+                    offset(OffsetKind::Add, gained);
+                }
 
-        (fragmented_polymer, lost_residues, broken_bonds)
+                FragmentIon {
+                    polymer: ion_polymer,
+                    lost_residues: lost_residues.clone(),
+                    broken_bonds: broken_bonds.clone(),
+                    termini,
+                    cuts,
+                    derivation: None,
+                }
+            })
+            .collect()
     }
 
     // FIXME: I'm pretty sure this assumption holds, but does a fragmentation depth equalling the degree / valency of
@@ -277,18 +406,20 @@ impl<'p> Fragment<'p> {
 
     // PERF: This could be memoized, but I don't know how much that would gain me if I'm not adding sub-fragmentations
     // to the cache... It would only help if the user has duplicate structures they are asking to fragment!
-    fn fragment(&self, max_depth: Option<usize>) -> HashSet<Self> {
+    fn fragment(&self, rules: &FragmentationRules) -> HashSet<Self> {
         let mut processing = vec![self.clone()];
         let mut processing_queue = Vec::new();
         // PERF: Any clever `with_capacity` pre-allocation I could do?
         let mut fragments = HashSet::new();
 
-        let max_depth = max_depth.unwrap_or(usize::MAX);
+        let is_cleavable = |abbr: BondAbbr| rules.cleavages.contains_key(abbr);
+        let max_depth = rules.max_depth.unwrap_or(usize::MAX);
         for depth in 0..=max_depth {
             while let Some(next) = processing.pop() {
                 // FIXME: Can I avoid this clone?
                 if fragments.insert(next.clone()) && depth < max_depth {
-                    processing_queue.extend(next.cut_each_bond().flat_map(divide_fragment));
+                    processing_queue
+                        .extend(next.cut_each_bond(&is_cleavable).flat_map(divide_fragment));
                 }
             }
             if processing_queue.is_empty() {
@@ -304,15 +435,18 @@ impl<'p> Fragment<'p> {
     // FIXME: Clarify type with some aliases?
     // FIXME: Remove the `+ '_` once Rust 2024 is released!
     // FIXME: Should this really be a method?
-    fn cut_each_bond(&self) -> impl Iterator<Item = (NodeId, Self)> + '_ {
+    fn cut_each_bond<'s>(
+        &'s self,
+        is_cleavable: impl Fn(BondAbbr<'p>) -> bool + 's,
+    ) -> impl Iterator<Item = (NodeId, Self)> + 's {
         self.residues
             .iter()
             .enumerate()
             .filter_map(|(node, opt_residue)| opt_residue.as_ref().map(|residue| (node, residue)))
-            .flat_map(|(node, residue)| residue.bonds.iter().map(move |bond| (node, bond.target)))
-            // FIXME: Perhaps here is where I can eventually filter out any unfragmentable bond types!
-            // NOTE: Ensures that the same bonds aren't cut twice
-            .filter(|(a, b)| a < b)
+            .flat_map(|(node, residue)| residue.bonds.iter().map(move |bond| (node, bond)))
+            // NOTE: Ensures that the same bonds aren't cut twice, and that bonds without cleavage rules are never cut
+            .filter(move |(a, bond)| *a < bond.target && is_cleavable(bond.end.bond_abbr()))
+            .map(|(a, bond)| (a, bond.target))
             .map(|(a, b)| {
                 let mut fragment = self.clone();
                 let mut remove_edge = |from, to| {
@@ -339,6 +473,40 @@ impl<'p> Fragment<'p> {
     }
 }
 
+impl FragmentIon<'_, '_, '_> {
+    // NOTE: Returns the largest charge this ion can carry under the `per_residue` limit of the `charges` rules, if
+    // there is one
+    fn max_charge(&self, charges: &ChargeDescription) -> Option<i64> {
+        // SAFETY: A polymer can't have anywhere near `i64::MAX` residues
+        let residues = i64::try_from(self.polymer.residue_ids().count()).unwrap();
+        charges
+            .per_residue
+            .map(|per_residue| i64::from(per_residue) * residues)
+    }
+}
+
+fn charge_of(charge_state: &ChargeState) -> i64 {
+    charge_state
+        .iter()
+        .map(|&(carrier, count)| i64::from(carrier.charge()) * i64::from(count))
+        .sum()
+}
+
+// FIXME: Should this really be a bare function?
+fn charge_fragment_ion<'a, 'p>(
+    fragment_ion: &Polymer<'a, 'p>,
+    charge_state: &ChargeState<'_, 'a>,
+) -> Polymer<'a, 'p> {
+    let mut charged_ion = fragment_ion.clone();
+    for &(carrier, count) in charge_state {
+        // SAFETY: Charge states never include carriers with a count of zero, so this shouldn't panic
+        charged_ion
+            .new_offset_with_composition(OffsetKind::Add, count, carrier.clone())
+            .unwrap();
+    }
+    charged_ion
+}
+
 // FIXME: This is performing a linear search (instead of a binary one) since these vectors should be super small most
 // of the time. That said, I've *not* benchmarked things properly!!!
 fn insert_terminal<'p>(terminals: &mut Vec<(Terminal<'p>, u32)>, terminal: Terminal<'p>) {
@@ -424,4 +592,164 @@ fn swap_remove_first<T>(vec: &mut Vec<T>, f: impl FnMut(&T) -> bool) -> Option<T
 // SEE NOTES FROM APRIL 8TH!
 // use DashMap or quick-cache for a global fragment cache
 
-// FIXME: Tests need adding!
+#[cfg(test)]
+mod tests {
+    use ahash::HashSet;
+    use polychem::{MassTolerance, Massive};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        divide_fragment,
+        testing_tools::{fragment_ions, muropeptide, RULES},
+        Dissociable, Fragment, NodeMapping,
+    };
+
+    #[test]
+    fn literature_ions() {
+        // NOTE: The m/z values usually reported for the singly-protonated ions of a reduced disaccharide tetrapeptide,
+        // rounded to four decimal places — the GlcNAc oxonium ion (B1), the loss of GlcNAc (Y5), and the cleavage of
+        // the stem peptide from MurNAc (b2 and y4) or within it (b4 and y2)
+        let ions = fragment_ions(&muropeptide(&["A", "E", "J", "A"]));
+        let tolerance = MassTolerance::Ppm(dec!(5));
+        for (label, literature_mz) in [
+            ("M", dec!(942.4150)),
+            ("B1", dec!(204.0867)),
+            ("C1", dec!(222.0972)),
+            ("Y5", dec!(739.3356)),
+            ("Z5", dec!(721.3250)),
+            ("b2", dec!(481.2028)),
+            ("y4", dec!(462.2195)),
+            ("b4", dec!(681.2825)),
+            ("y2", dec!(262.1397)),
+        ] {
+            assert!(
+                ions.iter()
+                    .any(|(l, mz)| l == label && tolerance.contains(literature_mz, *mz)),
+                "no {label} ion at m/z {literature_mz}"
+            );
+        }
+    }
+
+    #[test]
+    fn fragments_from_different_parents() {
+        // NOTE: `Fragment`s are only deduplicated within a single parent, but `Polymer::structural_hash()` can match the
+        // same fragment cut from different parents — like the `b2` ion (the disaccharide) of any stem peptide
+        let b2_hashes = |stem: &[&str]| -> HashSet<u64> {
+            muropeptide(stem)
+                .fragment(&RULES)
+                .filter(|fragment| fragment.label.as_ref().unwrap().to_string() == "b2")
+                .map(|fragment| fragment.polymer.structural_hash())
+                .collect()
+        };
+        let tetrapeptide = b2_hashes(&["A", "E", "J", "A"]);
+        let tripeptide = b2_hashes(&["A", "E", "K"]);
+
+        // NOTE: There is one singly-charged `b2` ion for each charge carrier — a proton, potassium, or sodium
+        assert_eq!(tetrapeptide.len(), 3);
+        assert_eq!(tetrapeptide, tripeptide);
+    }
+
+    #[test]
+    fn fragment_charges() {
+        let tetrapeptide = muropeptide(&["A", "E", "J", "A"]);
+
+        // NOTE: No fragment carries more charges than it has residues (freed ions have none, but are singly charged)
+        for fragment in tetrapeptide.fragment(&RULES) {
+            let residues = fragment.polymer.residue_ids().count().max(1);
+            let charge = fragment.label.unwrap().charge();
+            assert!(charge <= i64::try_from(residues).unwrap());
+        }
+
+        let ions = fragment_ions(&tetrapeptide);
+        assert!(!ions.iter().any(|(label, _)| label.starts_with("[y1]")));
+        // NOTE: The y2 ion can carry up to two charges, from any mix of protons, potassium, and sodium ions — the
+        // unfragmented muropeptide can carry every charge up to 5+
+        for (label, mz) in [
+            ("y2", dec!(262.1397)),
+            ("y2", dec!(300.0956)),
+            ("y2", dec!(284.1217)),
+            ("[y2]2+", dec!(131.5735)),
+            ("[y2]2+", dec!(150.5515)),
+            ("[y2]2+", dec!(142.5645)),
+            ("[y2]2+", dec!(169.5294)),
+            ("[M]2+", dec!(471.7111)),
+            ("[M]5+", dec!(189.2888)),
+        ] {
+            assert!(
+                ions.iter().any(|(l, m)| l == label && m.round_dp(4) == mz),
+                "no {label} ion at m/z {mz}"
+            );
+        }
+    }
+
+    #[test]
+    fn build_fragment_ions() {
+        let tetrapeptide = muropeptide(&["A", "E", "J", "A"]);
+        let polymer = &tetrapeptide.polymer;
+        let node_mapping = NodeMapping::new(polymer);
+        let whole = Fragment::new(&node_mapping, polymer);
+        // NOTE: Returns the termini and (uncharged) monoisotopic mass of every ion built from each piece of `whole`
+        // left after cutting the bonds named `abbr`
+        let pieces = |abbr: &str| {
+            whole
+                .cut_each_bond(|bond| bond == abbr)
+                .flat_map(divide_fragment)
+                .map(|piece| {
+                    piece
+                        .build_fragment_ions(&node_mapping, &whole, polymer, &RULES)
+                        .into_iter()
+                        .map(|ion| {
+                            let termini: Vec<_> =
+                                ion.termini.iter().map(|&(_, name, _)| name).collect();
+                            (termini, Decimal::from(ion.polymer.monoisotopic_mass()))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // NOTE: The uncut polymer is a single ion without any termini
+        let ions = whole
+            .clone()
+            .build_fragment_ions(&node_mapping, &whole, polymer, &RULES);
+        assert_eq!(ions.len(), 1);
+        assert!(ions[0].termini.is_empty());
+        assert_eq!(
+            Decimal::from(ions[0].polymer.monoisotopic_mass()),
+            dec!(941.40770207247)
+        );
+
+        // NOTE: Cutting the glycosidic bond leaves GlcNAc with either a B or C terminus, and the rest of the
+        // muropeptide with either a Y or Z terminus
+        assert_eq!(
+            pieces("Gly"),
+            vec![
+                vec![
+                    (vec!["B"], dec!(203.07937252127)),
+                    (vec!["C"], dec!(221.08993720530)),
+                ],
+                vec![
+                    (vec!["Y"], dec!(738.32832955120)),
+                    (vec!["Z"], dec!(720.31776486717)),
+                ],
+            ]
+        );
+
+        // NOTE: Cutting the stem peptide from MurNAc leaves the disaccharide with either a b or b-lac terminus, and the
+        // peptide with either a y or y-lac terminus (the latter keeping the lactyl group of MurNAc)
+        assert_eq!(
+            pieces("Stem"),
+            vec![
+                vec![
+                    (vec!["b"], dec!(480.19552447506)),
+                    (vec!["b-lac"], dec!(408.17439510700)),
+                ],
+                vec![
+                    (vec!["y"], dec!(461.21217759741)),
+                    (vec!["y-lac"], dec!(533.23330696547)),
+                ],
+            ]
+        );
+    }
+}
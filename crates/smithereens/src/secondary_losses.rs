@@ -0,0 +1,294 @@
+use std::fmt::{self, Display, Formatter};
+
+use ahash::HashSet;
+use polychem::{
+    ChargedParticle, ChemicalComposition, MonoisotopicMz, OffsetKind, Polymer, Polymerizer,
+    ResidueId,
+};
+use rust_decimal::Decimal;
+
+use crate::{fragmentation_rules::SecondaryLoss, FragmentIon, FragmentationRules};
+
+// NOTE: Records which secondary-loss rule produced an ion. Compositions are stored as strings so that these
+// descriptions can outlive the `FragmentationRules` they were generated from
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Derivation {
+    Lost {
+        source: LossSource,
+        composition: String,
+    },
+    Freed {
+        residue: String,
+        composition: String,
+        mz: MonoisotopicMz,
+    },
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum LossSource {
+    Residue(String),
+    Fragment,
+    Group(String),
+}
+
+// NOTE: Freed ions are labelled as oxonium ions of the residue they came from, but a single residue can free several
+// of them, so they're told apart by their m/z (rounded to four decimal places) — `g oxonium 168.0655`
+impl Display for Derivation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lost { composition, .. } => write!(f, "-{composition}"),
+            Self::Freed { residue, mz, .. } => {
+                write!(f, "{residue} oxonium {}", Decimal::from(*mz).round_dp(4))
+            }
+        }
+    }
+}
+
+// DESIGN: Secondary losses are only ever applied to primary fragments, so losses of losses (like `y2-H2O-NH3`) are
+// deliberately out of scope. Chaining rules would multiply the number of ions generated (and the number of ways to
+// spell the same loss), while the doubly-derived ions it adds are rarely abundant enough to be worth matching
+impl<'a, 'p, 'r> FragmentIon<'a, 'p, 'r> {
+    // NOTE: `freed_ions` tracks the `(rule, composition)` indices of every freed ion already generated — freed ions
+    // don't depend on the fragment they were freed from, so each should only be generated once per parent
+    pub(crate) fn secondary_ions(
+        &self,
+        parent: &Polymer<'a, 'p>,
+        rules: &FragmentationRules<'a>,
+        freed_ions: &mut HashSet<(usize, usize)>,
+    ) -> Vec<Self> {
+        let mut ions = Vec::new();
+        for (rule_index, rule) in rules.secondary_losses.iter().enumerate() {
+            match rule {
+                SecondaryLoss::Residue {
+                    abbr,
+                    termini,
+                    freed,
+                    lost,
+                } => {
+                    let has_residue = self.polymer.residue_ids().any(|id| {
+                        // SAFETY: `id` was just taken from this polymer, so this lookup can't fail
+                        self.polymer.residue(id).unwrap().matches_abbr(abbr)
+                            && termini
+                                .as_ref()
+                                .map_or(true, |filter| filter.matches(self.residue_termini(id)))
+                    });
+                    if !has_residue {
+                        continue;
+                    }
+
+                    let source = LossSource::Residue(abbr.clone());
+                    ions.extend(lost.iter().map(|c| self.lose(source.clone(), c)));
+                    for (freed_index, composition) in freed.iter().enumerate() {
+                        if freed_ions.insert((rule_index, freed_index)) {
+                            ions.push(Self::free(parent, abbr, composition));
+                        }
+                    }
+                }
+                SecondaryLoss::Fragment {
+                    residues,
+                    termini,
+                    lost,
+                } => {
+                    let residue_count = self.polymer.residue_ids().count();
+                    let residues_match = residues.map_or(true, |residues| {
+                        u32::try_from(residue_count) == Ok(residues)
+                    });
+                    let all_termini = self.termini.iter().map(|&(_, name, count)| (name, count));
+                    let termini_match = termini
+                        .as_ref()
+                        .map_or(true, |filter| filter.matches(all_termini));
+                    if residues_match && termini_match {
+                        ions.extend(lost.iter().map(|c| self.lose(LossSource::Fragment, c)));
+                    }
+                }
+                SecondaryLoss::Group {
+                    name,
+                    location,
+                    lost,
+                } => {
+                    // NOTE: Fragmentation frees up the groups of every bond it breaks, so groups are checked in the
+                    // unfragmented `parent` — only groups that were free to begin with should lose anything
+                    let has_group = self
+                        .polymer
+                        .residue_ids()
+                        .filter_map(|id| parent.residue(id))
+                        .flat_map(|residue| residue.functional_groups())
+                        .any(|(group, state)| {
+                            state.is_free()
+                                && group.name() == name
+                                && location.as_ref().map_or(true, |l| group.location() == l)
+                        });
+                    if has_group {
+                        let source = LossSource::Group(name.clone());
+                        ions.extend(lost.iter().map(|c| self.lose(source.clone(), c)));
+                    }
+                }
+            }
+        }
+        ions
+    }
+
+    fn residue_termini(&self, id: ResidueId) -> impl Iterator<Item = (&'r str, u32)> + Clone + '_ {
+        self.termini
+            .iter()
+            .filter(move |&&(residue, ..)| residue == id)
+            .map(|&(_, name, count)| (name, count))
+    }
+
+    fn lose(&self, source: LossSource, composition: &ChemicalComposition<'a>) -> Self {
+        let mut polymer = self.polymer.clone();
+        // SAFETY: A multiplier of one is always valid, so this shouldn't panic
+        polymer
+            .new_offset_with_composition(OffsetKind::Remove, 1, composition.clone())
+            .unwrap();
+        let derivation = Some(Derivation::Lost {
+            source,
+            composition: composition.to_string(),
+        });
+
+        Self {
+            polymer,
+            lost_residues: self.lost_residues.clone(),
+            broken_bonds: self.broken_bonds.clone(),
+            termini: self.termini.clone(),
+            cuts: self.cuts.clone(),
+            derivation,
+        }
+    }
+
+    // NOTE: Freed ions share no residues with their parent, so they're built from an empty polymer. They're also
+    // oxonium ions, which carry a proton of their own — so they leave here already charged, and are never given any
+    // of the charge carriers listed in the `FragmentationRules`
+    fn free(parent: &Polymer<'a, 'p>, abbr: &str, composition: &ChemicalComposition<'a>) -> Self {
+        let mut polymer = Polymerizer::new(parent.atomic_db(), parent.polymer_db()).new_polymer();
+        // SAFETY: A multiplier of one is always valid, and protons are a part of every `AtomicDatabase`, so neither
+        // of these should panic
+        polymer
+            .new_offset_with_composition(OffsetKind::Add, 1, composition.clone())
+            .unwrap();
+        polymer.new_offset(OffsetKind::Add, 1, "p").unwrap();
+        let derivation = Some(Derivation::Freed {
+            residue: abbr.to_owned(),
+            composition: composition.to_string(),
+            // SAFETY: The proton just added means this polymer is always charged
+            mz: polymer.monoisotopic_mz().unwrap(),
+        });
+
+        Self {
+            polymer,
+            lost_residues: parent.residue_ids().collect(),
+            broken_bonds: parent.bonds().map(|(id, _)| id).collect(),
+            termini: Vec::new(),
+            cuts: Vec::new(),
+            derivation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal_macros::dec;
+
+    use crate::{
+        testing_tools::{muropeptide, RULES},
+        Dissociable,
+    };
+
+    use super::*;
+
+    // NOTE: Returns the m/z (rounded to four decimal places) of every singly-charged ion of the muropeptide with the
+    // given `stem` that was generated by losing `composition` from `source`
+    fn lost_ions(stem: &[&str], source: &LossSource, composition: &str) -> Vec<Decimal> {
+        let derivation = Derivation::Lost {
+            source: source.clone(),
+            composition: composition.to_owned(),
+        };
+        muropeptide(stem)
+            .fragment(&RULES)
+            .filter(|ion| {
+                let label = ion.label.as_ref().unwrap();
+                label.charge() == 1 && label.derivation() == Some(&derivation)
+            })
+            .map(|ion| Decimal::from(ion.polymer.monoisotopic_mz().unwrap()).round_dp(4))
+            .collect()
+    }
+
+    #[test]
+    fn display_derivation() {
+        let lost = Derivation::Lost {
+            source: LossSource::Group("Carboxyl".to_owned()),
+            composition: "H2O".to_owned(),
+        };
+        assert_eq!(lost.to_string(), "-H2O");
+        let freed = Derivation::Freed {
+            residue: "g".to_owned(),
+            composition: "C8H9NO3".to_owned(),
+            mz: MonoisotopicMz::observed(dec!(168.065520)),
+        };
+        assert_eq!(freed.to_string(), "g oxonium 168.0655");
+    }
+
+    #[test]
+    fn group_losses() {
+        // NOTE: Only the C-terminal carboxyl group of the final alanine is free, so only ions containing it (y1 and y2,
+        // for example) can lose water — y2 is 262.1397, and y1 is 90.0550
+        let carboxyl = LossSource::Group("Carboxyl".to_owned());
+        let mzs = lost_ions(&["A", "E", "J", "A"], &carboxyl, "H2O");
+        assert!(mzs.contains(&dec!(244.1292)));
+        assert!(mzs.contains(&dec!(72.0444)));
+    }
+
+    #[test]
+    fn residue_losses() {
+        // NOTE: The e1 and e2 ions are only lost from glutamate residues carrying a y-terminus — y3 is 391.1823
+        let glutamate = LossSource::Residue("E".to_owned());
+        let mzs = lost_ions(&["A", "E", "J", "A"], &glutamate, "H2O");
+        assert!(mzs.contains(&dec!(373.1718)));
+        let mzs = lost_ions(&["A", "E", "J", "A"], &glutamate, "H2OCONH2");
+        assert!(mzs.contains(&dec!(329.1581)));
+
+        // NOTE: The same goes for the q1 and q2 ions of glutamine — y3 is 346.2085
+        let glutamine = LossSource::Residue("Q".to_owned());
+        let mzs = lost_ions(&["A", "Q", "K", "A"], &glutamine, "NH3");
+        assert!(mzs.contains(&dec!(329.1819)));
+        let mzs = lost_ions(&["A", "Q", "K", "A"], &glutamine, "NH3CONH2");
+        assert!(mzs.contains(&dec!(285.1683)));
+
+        // NOTE: Stereoisomers of a residue (like D-glutamate) lose the same ions it does
+        let mzs = lost_ions(&["lA", "dE", "mJ", "dA"], &glutamate, "H2O");
+        assert!(mzs.contains(&dec!(373.1718)));
+
+        // NOTE: Neither residue is present in the other muropeptide, so neither should lose anything there
+        assert!(lost_ions(&["A", "E", "J", "A"], &glutamine, "NH3").is_empty());
+        assert!(lost_ions(&["A", "Q", "K", "A"], &glutamate, "H2O").is_empty());
+    }
+
+    #[test]
+    fn fragment_losses() {
+        // NOTE: Immonium ions are internal b+y ions of a single residue that have lost CO
+        let mzs = lost_ions(&["A", "E", "J", "A"], &LossSource::Fragment, "CO");
+        assert!(mzs.contains(&dec!(102.0550)));
+        assert!(mzs.contains(&dec!(145.0972)));
+        assert!(mzs
+            .iter()
+            .all(|mz| [dec!(44.0495), dec!(102.0550), dec!(145.0972)].contains(mz)));
+    }
+
+    #[test]
+    fn freed_ions() {
+        let freed: Vec<_> = muropeptide(&["A", "E", "J", "A"])
+            .fragment(&RULES)
+            .filter_map(|ion| ion.label)
+            .filter(|label| matches!(label.derivation(), Some(Derivation::Freed { .. })))
+            .collect();
+
+        // NOTE: Each freed ion is generated only once, and is only ever charged by its own proton
+        assert_eq!(freed.len(), 6);
+        assert!(freed.iter().all(|label| label.charge() == 1));
+
+        let labels: Vec<_> = freed.iter().map(ToString::to_string).collect();
+        assert!(labels.contains(&"g oxonium 168.0655".to_owned()));
+        assert!(labels.contains(&"g oxonium 186.0761".to_owned()));
+        assert!(labels.contains(&"m oxonium 138.0550".to_owned()));
+    }
+}
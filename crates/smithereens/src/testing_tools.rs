@@ -0,0 +1,80 @@
+use once_cell::sync::Lazy;
+use polychem::{AtomicDatabase, ChargedParticle, Polymer, PolymerDatabase, Polymerizer, ResidueId};
+use rust_decimal::Decimal;
+
+use crate::{Dissociable, FragmentLabel, FragmentationRules};
+
+pub static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+pub static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+    PolymerDatabase::new(
+        &ATOMIC_DB,
+        "polymer_database.kdl",
+        include_str!("../../muropeptide/data/polymer_database.kdl"),
+    )
+    .unwrap()
+});
+pub static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+pub static RULES: Lazy<FragmentationRules> = Lazy::new(|| {
+    FragmentationRules::new(
+        &ATOMIC_DB,
+        &POLYMER_DB,
+        "hcd_rules.kdl",
+        include_str!("../../muropeptide/data/hcd_rules.kdl"),
+    )
+    .unwrap()
+});
+
+// NOTE: The `muropeptide` crate depends on this one, so its `Muropeptide` can't be used here — this is the smallest
+// `Dissociable` type possible, wrapping a bare `Polymer`
+#[derive(Clone, Debug)]
+pub struct Molecule<'a, 'p> {
+    pub polymer: Polymer<'a, 'p>,
+    pub label: Option<FragmentLabel>,
+}
+
+impl<'s, 'a: 's, 'p: 's> Dissociable<'s, 'a, 'p> for Molecule<'a, 'p> {
+    fn polymer(&self) -> &Polymer<'a, 'p> {
+        &self.polymer
+    }
+
+    fn new_fragment(
+        &self,
+        fragmented_polymer: Polymer<'a, 'p>,
+        _lost_residues: Vec<ResidueId>,
+        label: FragmentLabel,
+    ) -> Self {
+        Self {
+            polymer: fragmented_polymer,
+            label: Some(label),
+        }
+    }
+}
+
+// NOTE: Builds a reduced disaccharide with the `stem` peptide attached to its MurNAc, so `muropeptide(&["A", "E", "J",
+// "A"])` is the same structure that the `muropeptide` crate parses from `gm-AEJA`
+pub fn muropeptide(stem: &[&str]) -> Molecule<'static, 'static> {
+    let mut polymer = POLYMERIZER.new_polymer();
+    let (glycan, _) = polymer.new_chain("Gly", ["g", "m"]).unwrap();
+    let (peptide, _) = polymer.new_chain("Pep", stem).unwrap();
+    polymer
+        .bond_residues("Stem", glycan[1], peptide[0])
+        .unwrap();
+    polymer.modify_polymer("Red").unwrap();
+    Molecule {
+        polymer,
+        label: None,
+    }
+}
+
+// NOTE: Fragments `molecule` with the HCD rules, returning the label and monoisotopic m/z of every ion generated
+pub fn fragment_ions(molecule: &Molecule<'static, 'static>) -> Vec<(String, Decimal)> {
+    molecule
+        .fragment(&RULES)
+        .map(|fragment| {
+            // SAFETY: Every fragment is labelled and charged, so neither of these should panic
+            let label = fragment.label.unwrap().to_string();
+            let mz = Decimal::from(fragment.polymer.monoisotopic_mz().unwrap());
+            (label, mz)
+        })
+        .collect()
+}
@@ -0,0 +1,187 @@
+//! Finding the fragments that tell several (often isobaric) candidate structures apart
+use polychem::{ChargedParticle, MassTolerance, MonoisotopicMz};
+use rust_decimal::prelude::ToPrimitive;
+use smithereens::{Dissociable, FragmentationRules};
+
+use crate::matching::mz_to_f64;
+
+#[derive(Clone, Debug)]
+pub struct DiagnosticIon<F> {
+    pub fragment: F,
+    pub mz: MonoisotopicMz,
+    // NOTE: The distance (in Da) to the closest fragment of any other candidate, or `None` if no other candidate
+    // produced any charged fragments at all
+    pub separation: Option<f64>,
+}
+
+// NOTE: A fragment is diagnostic when no other candidate has a fragment within `min_separation` of its m/z — this is
+// separate from the tolerance used to match peaks, since it should usually be at least as wide. One list of
+// diagnostic ions is returned per candidate (in the same order as `candidates`), sorted by increasing m/z. Several
+// fragments of the same candidate can share an m/z, so the same m/z may appear more than once in a list
+pub fn diagnostic_ions<'c, 'a: 'p + 'c, 'p: 'c, D>(
+    candidates: &'c [D],
+    rules: &'c FragmentationRules<'a>,
+    min_separation: MassTolerance,
+) -> Vec<Vec<DiagnosticIon<D>>>
+where
+    D: Dissociable<'c, 'a, 'p> + ChargedParticle,
+{
+    let fragments: Vec<Vec<_>> = candidates
+        .iter()
+        .map(|candidate| {
+            candidate
+                .fragment(rules)
+                .filter_map(|fragment| {
+                    let mz = fragment.monoisotopic_mz()?;
+                    Some((fragment, mz, mz_to_f64(mz)?))
+                })
+                .collect()
+        })
+        .collect();
+
+    let fragment_mzs: Vec<Vec<_>> = fragments
+        .iter()
+        .map(|fragments| {
+            let mut mzs: Vec<_> = fragments.iter().map(|&(_, _, mz)| mz).collect();
+            mzs.sort_unstable_by(f64::total_cmp);
+            mzs.dedup();
+            mzs
+        })
+        .collect();
+
+    fragments
+        .into_iter()
+        .enumerate()
+        .map(|(i, fragments)| {
+            let mut ions: Vec<_> = fragments
+                .into_iter()
+                .filter_map(|(fragment, mz, theoretical)| {
+                    let separation = fragment_mzs
+                        .iter()
+                        .enumerate()
+                        .filter(|&(j, _)| j != i)
+                        .filter_map(|(_, others)| nearest_distance(others, theoretical))
+                        .min_by(f64::total_cmp);
+                    let window = min_separation.window(mz.into()).to_f64()?;
+                    let diagnostic = separation.is_none_or(|distance| distance > window);
+                    diagnostic.then_some(DiagnosticIon {
+                        fragment,
+                        mz,
+                        separation,
+                    })
+                })
+                .collect();
+            ions.sort_by_key(|ion| ion.mz);
+            ions
+        })
+        .collect()
+}
+
+// NOTE: Expects `mzs` to be sorted
+fn nearest_distance(mzs: &[f64], mz: f64) -> Option<f64> {
+    let start = mzs.partition_point(|&other| other < mz);
+    // NOTE: The closest m/z is either the last one below `mz`, or the first one at or above it
+    mzs[start.saturating_sub(1)..]
+        .iter()
+        .take(2)
+        .map(|&other| (other - mz).abs())
+        .min_by(f64::total_cmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use muropeptide::Muropeptide;
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, PolymerDatabase, Polymerizer};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../../muropeptide/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+    static RULES: Lazy<FragmentationRules> = Lazy::new(|| {
+        FragmentationRules::new(
+            &ATOMIC_DB,
+            &POLYMER_DB,
+            "hcd_rules.kdl",
+            include_str!("../../muropeptide/data/hcd_rules.kdl"),
+        )
+        .unwrap()
+    });
+
+    fn mzs(ions: &[DiagnosticIon<Muropeptide>]) -> Vec<f64> {
+        ions.iter().filter_map(|ion| mz_to_f64(ion.mz)).collect()
+    }
+
+    #[test]
+    fn nearest_distances() {
+        let mzs = [100.0, 200.0, 300.0];
+        assert!((nearest_distance(&mzs, 90.0).unwrap() - 10.0).abs() < 1e-12);
+        assert!((nearest_distance(&mzs, 180.0).unwrap() - 20.0).abs() < 1e-12);
+        assert!((nearest_distance(&mzs, 220.0).unwrap() - 20.0).abs() < 1e-12);
+        assert!(nearest_distance(&mzs, 200.0).unwrap().abs() < 1e-12);
+        assert!((nearest_distance(&mzs, 350.0).unwrap() - 50.0).abs() < 1e-12);
+        assert_eq!(nearest_distance(&[], 350.0), None);
+    }
+
+    #[test]
+    fn isobaric_candidates() {
+        let min_separation = MassTolerance::Ppm(dec!(10));
+        let candidates = ["gm-AEAJ", "gm-AEJA"]
+            .map(|structure| Muropeptide::new(&POLYMERIZER, structure).unwrap());
+        let diagnostic = diagnostic_ions(&candidates, &RULES, min_separation);
+        assert_eq!(diagnostic.len(), 2);
+
+        for (i, ions) in diagnostic.iter().enumerate() {
+            assert!(!ions.is_empty());
+            assert!(ions.len() < candidates[i].fragment(&RULES).count());
+            assert!(ions.windows(2).all(|w| w[0].mz <= w[1].mz));
+
+            // NOTE: No diagnostic ion should be within tolerance of any fragment from the other candidate
+            let others: Vec<_> = candidates[1 - i]
+                .fragment(&RULES)
+                .filter_map(|fragment| fragment.monoisotopic_mz())
+                .filter_map(mz_to_f64)
+                .collect();
+            for ion in ions {
+                let mz = mz_to_f64(ion.mz).unwrap();
+                let separation = ion.separation.unwrap();
+                assert!(separation > min_separation.window(ion.mz.into()).to_f64().unwrap());
+                assert!(others.iter().all(|&other| (other - mz).abs() >= separation));
+                assert!(ion.fragment.label().is_some());
+            }
+        }
+
+        // NOTE: The diagnostic ions of each candidate can't share an m/z with those of the other
+        let (aeaj, aeja) = (mzs(&diagnostic[0]), mzs(&diagnostic[1]));
+        assert!(aeaj.iter().all(|mz| !aeja.contains(mz)));
+    }
+
+    #[test]
+    fn identical_candidates() {
+        let candidates = ["gm-AEJA", "gm-AEJA"]
+            .map(|structure| Muropeptide::new(&POLYMERIZER, structure).unwrap());
+        let diagnostic = diagnostic_ions(&candidates, &RULES, MassTolerance::Ppm(dec!(10)));
+        assert!(diagnostic.iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn lone_candidate() {
+        let candidates = [Muropeptide::new(&POLYMERIZER, "gm-AEJA").unwrap()];
+        let diagnostic = diagnostic_ions(&candidates, &RULES, MassTolerance::Da(dec!(0.02)));
+        assert_eq!(diagnostic.len(), 1);
+
+        // NOTE: With nothing to tell it apart from, every charged fragment is diagnostic
+        let ions = &diagnostic[0];
+        assert_eq!(ions.len(), candidates[0].fragment(&RULES).count());
+        assert!(ions.iter().all(|ion| ion.separation.is_none()));
+    }
+}
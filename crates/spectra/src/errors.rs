@@ -0,0 +1,51 @@
+use std::io;
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Diagnostic, Error)]
+pub enum Error {
+    #[error("failed to read spectral data")]
+    #[diagnostic(help("check that the file exists and that you have permission to read it"))]
+    Io(#[from] io::Error),
+
+    #[error("failed to parse the XML of an mzML file")]
+    #[diagnostic(help("the file may be truncated or corrupted — try exporting it again"))]
+    Xml(#[from] quick_xml::Error),
+
+    #[error("failed to decode the base64 of a binary data array")]
+    #[diagnostic(help("the file may be truncated or corrupted — try exporting it again"))]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("spectrum {id:?} ends without an `END IONS` line")]
+    #[diagnostic(help(
+        "the file may be truncated — check that it was completely written or downloaded"
+    ))]
+    UnterminatedSpectrum { id: String },
+
+    #[error("expected a number for the {name}, but found {value:?}")]
+    #[diagnostic(help("double-check for typos, or for a missing or misplaced value"))]
+    InvalidNumber { name: &'static str, value: String },
+
+    #[error("the binary data array in spectrum {id:?} uses an unsupported {encoding}")]
+    #[diagnostic(help(
+        "try converting the file with uncompressed or zlib-compressed, 32 or 64-bit float arrays"
+    ))]
+    UnsupportedEncoding { id: String, encoding: &'static str },
+
+    #[error("the binary data array in spectrum {id:?} has a length that isn't a multiple of {width} bytes")]
+    #[diagnostic(help("the file may be truncated or corrupted — try exporting it again"))]
+    TruncatedArray { id: String, width: usize },
+
+    #[error("spectrum {id:?} has {mzs} m/z values, but {intensities} intensities")]
+    #[diagnostic(help(
+        "every peak needs both an m/z and an intensity — the file may be corrupted"
+    ))]
+    MismatchedArrays {
+        id: String,
+        mzs: usize,
+        intensities: usize,
+    },
+}
@@ -0,0 +1,42 @@
+//! Responsible for reading mass-spectrometry data into `Spectrum`s
+pub mod diagnostic;
+pub mod errors;
+pub mod matching;
+pub mod mgf;
+pub mod mzml;
+pub mod scoring;
+pub mod search;
+mod theoretical;
+
+pub use diagnostic::{diagnostic_ions, DiagnosticIon};
+pub use errors::{Error, Result};
+pub use matching::{match_fragments, PeakMatch};
+pub use mgf::{MgfReader, MgfWriter};
+pub use mzml::MzMLReader;
+pub use scoring::{rank_candidates, Score, ScoredCandidate};
+pub use search::{search_features, Feature, SearchHit, SearchResult};
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Spectrum {
+    pub id: String,
+    pub ms_level: u8,
+    // NOTE: Always stored in minutes, regardless of the units used by the source file
+    pub retention_time: Option<f64>,
+    // NOTE: Only MSn spectra (where n > 1) have a precursor
+    pub precursor: Option<Precursor>,
+    pub peaks: Vec<Peak>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Precursor {
+    pub mz: f64,
+    // NOTE: Negative for precursors measured in negative ion mode
+    pub charge: Option<i32>,
+    pub intensity: Option<f64>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Peak {
+    pub mz: f64,
+    pub intensity: f64,
+}
@@ -0,0 +1,168 @@
+//! Matching the theoretical fragments of a structure against the peaks of an observed spectrum
+use polychem::{ChargedParticle, MassTolerance, MonoisotopicMz};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use smithereens::{Dissociable, FragmentationRules};
+
+use crate::Peak;
+
+#[derive(Clone, Debug)]
+pub struct PeakMatch<F> {
+    pub fragment: F,
+    pub theoretical_mz: MonoisotopicMz,
+    pub observed_mz: MonoisotopicMz,
+    pub ppm_error: f64,
+    pub intensity: f64,
+}
+
+// NOTE: Each fragment is matched to (at most) the single closest peak within the `tolerance`, but several fragments
+// can still be matched to the same peak. Matches are returned in order of increasing theoretical m/z
+pub fn match_fragments<'s, 'a: 'p + 's, 'p: 's, D>(
+    parent: &'s D,
+    rules: &'s FragmentationRules<'a>,
+    peaks: &[Peak],
+    tolerance: MassTolerance,
+) -> Vec<PeakMatch<D>>
+where
+    D: Dissociable<'s, 'a, 'p> + ChargedParticle,
+{
+    let mut peaks = peaks.to_vec();
+    peaks.sort_unstable_by(|a, b| a.mz.total_cmp(&b.mz));
+
+    let mut matches: Vec<_> = parent
+        .fragment(rules)
+        .filter_map(|fragment| {
+            let theoretical_mz = fragment.monoisotopic_mz()?;
+            let theoretical = mz_to_f64(theoretical_mz)?;
+            let peak = closest_peak(&peaks, theoretical_mz, tolerance)?;
+            let observed_mz = MonoisotopicMz::observed(Decimal::try_from(peak.mz).ok()?);
+            Some(PeakMatch {
+                fragment,
+                theoretical_mz,
+                observed_mz,
+                ppm_error: ppm_error(theoretical, peak.mz),
+                intensity: peak.intensity,
+            })
+        })
+        .collect();
+    matches.sort_by_key(|m| m.theoretical_mz);
+    matches
+}
+
+pub(crate) fn mz_to_f64(mz: MonoisotopicMz) -> Option<f64> {
+    Decimal::from(mz).to_f64()
+}
+
+#[must_use]
+pub fn ppm_error(theoretical: f64, observed: f64) -> f64 {
+    (observed - theoretical) / theoretical * 1_000_000.0
+}
+
+// NOTE: Expects `peaks` to be sorted by m/z
+pub(crate) fn closest_peak(
+    peaks: &[Peak],
+    theoretical: MonoisotopicMz,
+    tolerance: MassTolerance,
+) -> Option<Peak> {
+    let window = tolerance.window(theoretical.into()).to_f64()?;
+    let theoretical = mz_to_f64(theoretical)?;
+    let start = peaks.partition_point(|p| p.mz < theoretical - window);
+    peaks[start..]
+        .iter()
+        .take_while(|p| p.mz <= theoretical + window)
+        .min_by(|a, b| {
+            (a.mz - theoretical)
+                .abs()
+                .total_cmp(&(b.mz - theoretical).abs())
+        })
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use muropeptide::Muropeptide;
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, PolymerDatabase, Polymerizer};
+    use rust_decimal_macros::dec;
+
+    use crate::Spectrum;
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../../muropeptide/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+    static RULES: Lazy<FragmentationRules> = Lazy::new(|| {
+        FragmentationRules::new(
+            &ATOMIC_DB,
+            &POLYMER_DB,
+            "hcd_rules.kdl",
+            include_str!("../../muropeptide/data/hcd_rules.kdl"),
+        )
+        .unwrap()
+    });
+
+    #[test]
+    fn closest_peak_in_window() {
+        let peaks = [100.0, 200.0, 200.001, 200.003, 300.0].map(|mz| Peak {
+            mz,
+            intensity: mz * 10.0,
+        });
+
+        let mz = MonoisotopicMz::observed;
+        let peak = closest_peak(&peaks, mz(dec!(200.0012)), MassTolerance::Da(dec!(0.01))).unwrap();
+        assert!((peak.mz - 200.001).abs() < f64::EPSILON);
+        assert!(closest_peak(&peaks, mz(dec!(250)), MassTolerance::Ppm(dec!(20))).is_none());
+        assert!(closest_peak(&[], mz(dec!(250)), MassTolerance::Ppm(dec!(20))).is_none());
+    }
+
+    #[test]
+    fn ppm_errors() {
+        assert!((ppm_error(500.0, 500.005) - 10.0).abs() < 1e-6);
+        assert!((ppm_error(500.0, 499.995) + 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn match_theoretical_spectrum() {
+        let muropeptide = Muropeptide::new(&POLYMERIZER, "gm-AEJA").unwrap();
+        let spectrum = Spectrum::theoretical("gm-AEJA", None, muropeptide.fragment(&RULES));
+        let tolerance = MassTolerance::Ppm(dec!(1));
+        let matches = match_fragments(&muropeptide, &RULES, &spectrum.peaks, tolerance);
+
+        assert_eq!(matches.len(), muropeptide.fragment(&RULES).count());
+        assert!(matches.iter().all(|m| m.ppm_error.abs() < 1e-6));
+        assert!(matches
+            .windows(2)
+            .all(|w| w[0].theoretical_mz <= w[1].theoretical_mz));
+    }
+
+    #[test]
+    fn match_oxonium_ion() {
+        let muropeptide = Muropeptide::new(&POLYMERIZER, "gm-AEJA").unwrap();
+        let peaks = [Peak {
+            mz: 168.0660,
+            intensity: 1234.5,
+        }];
+
+        let matches = match_fragments(&muropeptide, &RULES, &peaks, MassTolerance::Ppm(dec!(5)));
+        let oxonium = matches
+            .iter()
+            .find(|m| m.fragment.label().unwrap().to_string() == "g oxonium 168.0655")
+            .unwrap();
+        assert!((oxonium.ppm_error - 2.856).abs() < 1e-3);
+        assert!((oxonium.intensity - 1234.5).abs() < f64::EPSILON);
+        assert_eq!(oxonium.observed_mz.to_string(), "168.066");
+        assert!(oxonium.theoretical_mz.to_string().starts_with("168.0655"));
+
+        let matches = match_fragments(&muropeptide, &RULES, &peaks, MassTolerance::Ppm(dec!(1)));
+        assert!(matches.is_empty());
+        let matches = match_fragments(&muropeptide, &RULES, &peaks, MassTolerance::Da(dec!(0.001)));
+        assert!(!matches.is_empty());
+    }
+}
@@ -0,0 +1,434 @@
+//! A reader and writer for Mascot Generic Format (MGF) files
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Lines, Write},
+    path::Path,
+};
+
+use crate::{Error, Peak, Precursor, Result, Spectrum};
+
+pub struct MgfReader<R: BufRead> {
+    lines: Lines<R>,
+    // NOTE: Used as the ID of spectra without a `TITLE`
+    index: usize,
+}
+
+impl MgfReader<BufReader<File>> {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> MgfReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            index: 0,
+        }
+    }
+
+    fn next_spectrum(&mut self) -> Result<Option<Spectrum>> {
+        let mut spectrum: Option<Spectrum> = None;
+        while let Some(line) = self.lines.next().transpose()? {
+            let line = line.trim();
+            // NOTE: MGF allows comments starting with any of `#;!/`
+            if line.is_empty() || line.starts_with(['#', ';', '!', '/']) {
+                continue;
+            }
+
+            let Some(current) = &mut spectrum else {
+                if line == "BEGIN IONS" {
+                    spectrum = Some(Spectrum {
+                        id: self.index.to_string(),
+                        ms_level: 2,
+                        retention_time: None,
+                        precursor: None,
+                        peaks: Vec::new(),
+                    });
+                    self.index += 1;
+                }
+                continue;
+            };
+
+            if line == "END IONS" {
+                return Ok(spectrum);
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                parse_header(current, key, value)?;
+            } else {
+                current.peaks.push(parse_peak(line)?);
+            }
+        }
+
+        // NOTE: Running out of lines inside of a `BEGIN IONS` block means the file has been cut short
+        match spectrum {
+            Some(Spectrum { id, .. }) => Err(Error::UnterminatedSpectrum { id }),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for MgfReader<R> {
+    type Item = Result<Spectrum>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spectrum().transpose()
+    }
+}
+
+// NOTE: Unrecognised headers (like `SCANS` or `SEQ`) are ignored
+fn parse_header(spectrum: &mut Spectrum, key: &str, value: &str) -> Result<()> {
+    let value = value.trim();
+    match key.trim() {
+        "TITLE" => value.clone_into(&mut spectrum.id),
+        "PEPMASS" => {
+            let mut values = value.split_whitespace();
+            let mz = parse_number("precursor m/z", values.next().unwrap_or_default())?;
+            let intensity = values
+                .next()
+                .map(|i| parse_number("precursor intensity", i))
+                .transpose()?;
+            let charge = spectrum.precursor.and_then(|p| p.charge);
+            spectrum.precursor = Some(Precursor {
+                mz,
+                charge,
+                intensity,
+            });
+        }
+        "CHARGE" => {
+            // NOTE: Only the first of several possible charges (`2+ and 3+`) is kept, and charges without a sign are
+            // taken to be positive
+            let charge = value.split_whitespace().next().unwrap_or_default();
+            let (magnitude, sign) = match charge.strip_suffix('-') {
+                Some(magnitude) => (magnitude, -1),
+                None => (charge.strip_suffix('+').unwrap_or(charge), 1),
+            };
+            let charge: i32 = parse_number("precursor charge", magnitude)?;
+            let precursor = spectrum.precursor.get_or_insert(Precursor {
+                mz: 0.0,
+                charge: None,
+                intensity: None,
+            });
+            precursor.charge = Some(sign * charge);
+        }
+        "RTINSECONDS" => {
+            let seconds: f64 = parse_number("retention time", value)?;
+            spectrum.retention_time = Some(seconds / 60.0);
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+fn parse_peak(line: &str) -> Result<Peak> {
+    let mut values = line.split_whitespace();
+    let mz = parse_number("peak m/z", values.next().unwrap_or_default())?;
+    // NOTE: Intensities are optional in MGF files, so peaks without one are given an intensity of zero
+    let intensity = values
+        .next()
+        .map_or(Ok(0.0), |i| parse_number("peak intensity", i))?;
+    Ok(Peak { mz, intensity })
+}
+
+fn parse_number<T: std::str::FromStr>(name: &'static str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| Error::InvalidNumber {
+        name,
+        value: value.to_owned(),
+    })
+}
+
+// MGF Writing =========================================================================================================
+
+pub struct MgfWriter<W: Write> {
+    writer: W,
+}
+
+impl MgfWriter<File> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> MgfWriter<W> {
+    pub const fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write(&mut self, spectrum: &Spectrum) -> Result<()> {
+        let w = &mut self.writer;
+        writeln!(w, "BEGIN IONS")?;
+        writeln!(w, "TITLE={}", spectrum.id)?;
+        if let Some(Precursor {
+            mz,
+            charge,
+            intensity,
+        }) = spectrum.precursor
+        {
+            match intensity {
+                Some(intensity) => writeln!(w, "PEPMASS={mz} {intensity}")?,
+                None => writeln!(w, "PEPMASS={mz}")?,
+            }
+            if let Some(charge) = charge {
+                let sign = if charge < 0 { '-' } else { '+' };
+                writeln!(w, "CHARGE={}{sign}", charge.unsigned_abs())?;
+            }
+        }
+        if let Some(retention_time) = spectrum.retention_time {
+            writeln!(w, "RTINSECONDS={}", retention_time * 60.0)?;
+        }
+        for Peak { mz, intensity } in &spectrum.peaks {
+            writeln!(w, "{mz} {intensity}")?;
+        }
+        writeln!(w, "END IONS")?;
+        writeln!(w)?;
+        Ok(())
+    }
+
+    pub fn write_all<'s>(&mut self, spectra: impl IntoIterator<Item = &'s Spectrum>) -> Result<()> {
+        for spectrum in spectra {
+            self.write(spectrum)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use muropeptide::Muropeptide;
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, PolymerDatabase, Polymerizer};
+    use smithereens::{Dissociable, FragmentationRules};
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../../muropeptide/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+    static RULES: Lazy<FragmentationRules> = Lazy::new(|| {
+        FragmentationRules::new(
+            &ATOMIC_DB,
+            &POLYMER_DB,
+            "hcd_rules.kdl",
+            include_str!("../../muropeptide/data/hcd_rules.kdl"),
+        )
+        .unwrap()
+    });
+
+    const SMALL_MGF: &[u8] = include_bytes!("../tests/data/small.mgf");
+
+    fn assert_close(left: f64, right: f64) {
+        assert!((left - right).abs() < 1e-4, "{left} != {right}");
+    }
+
+    #[test]
+    fn read_spectra() {
+        let spectra: Vec<_> = MgfReader::new(SMALL_MGF).collect::<Result<_>>().unwrap();
+        assert_eq!(spectra.len(), 2);
+
+        let first = &spectra[0];
+        assert_eq!(first.id, "controllerType=0 controllerNumber=1 scan=2");
+        assert_eq!(first.ms_level, 2);
+        assert_close(first.retention_time.unwrap(), 1.05);
+        assert_eq!(
+            first.precursor,
+            Some(Precursor {
+                mz: 499.2012,
+                charge: Some(2),
+                intensity: Some(82000.5)
+            })
+        );
+        assert_eq!(first.peaks.len(), 4);
+        assert_eq!(
+            first.peaks[3],
+            Peak {
+                mz: 699.3142,
+                intensity: 3000.0
+            }
+        );
+
+        let second = &spectra[1];
+        assert_eq!(second.id, "1");
+        assert_close(second.retention_time.unwrap(), 1.26);
+        assert_eq!(
+            second.precursor,
+            Some(Precursor {
+                mz: 942.4,
+                charge: Some(1),
+                intensity: None
+            })
+        );
+        assert_eq!(
+            second.peaks,
+            [
+                Peak {
+                    mz: 138.055,
+                    intensity: 0.0
+                },
+                Peak {
+                    mz: 168.0655,
+                    intensity: 120.5
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn read_from_path() {
+        let reader = MgfReader::from_path("tests/data/small.mgf").unwrap();
+        assert_eq!(reader.count(), 2);
+    }
+
+    #[test]
+    fn invalid_peak() {
+        let mgf = "BEGIN IONS\n168.0655 lots\nEND IONS\n";
+        let error = MgfReader::new(mgf.as_bytes()).next().unwrap().unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidNumber { name: "peak intensity", value } if value == "lots"
+        ));
+    }
+
+    #[test]
+    fn unterminated_spectrum() {
+        let mgf = "BEGIN IONS\nTITLE=gm-AEJA\n168.0655 120.5\n";
+        let mut reader = MgfReader::new(mgf.as_bytes());
+        let error = reader.next().unwrap().unwrap_err();
+        assert!(matches!(error, Error::UnterminatedSpectrum { id } if id == "gm-AEJA"));
+        assert!(reader.next().is_none());
+
+        // NOTE: Complete spectra before the truncated one are still read
+        let mgf = "BEGIN IONS\n168.0655\nEND IONS\n\nBEGIN IONS\n204.0867\n";
+        let spectra: Vec<_> = MgfReader::new(mgf.as_bytes()).collect();
+        assert_eq!(spectra.len(), 2);
+        assert_eq!(spectra[0].as_ref().unwrap().peaks.len(), 1);
+        assert!(matches!(&spectra[1], Err(Error::UnterminatedSpectrum { id }) if id == "1"));
+    }
+
+    #[test]
+    fn write_spectra() {
+        let spectrum = Spectrum {
+            id: "gm-AEJA".to_owned(),
+            ms_level: 2,
+            retention_time: Some(1.5),
+            precursor: Some(Precursor {
+                mz: 471.2,
+                charge: Some(2),
+                intensity: None,
+            }),
+            peaks: vec![
+                Peak {
+                    mz: 168.0655,
+                    intensity: 1.0,
+                },
+                Peak {
+                    mz: 204.0867,
+                    intensity: 0.5,
+                },
+            ],
+        };
+
+        let mut writer = MgfWriter::new(Vec::new());
+        writer.write(&spectrum).unwrap();
+        let mgf = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(
+            mgf,
+            "BEGIN IONS\nTITLE=gm-AEJA\nPEPMASS=471.2\nCHARGE=2+\nRTINSECONDS=90\n168.0655 1\n204.0867 0.5\nEND IONS\n\n"
+        );
+
+        let round_tripped: Vec<_> = MgfReader::new(mgf.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(round_tripped, [spectrum]);
+    }
+
+    #[test]
+    fn signed_charges() {
+        let charge = |charge| {
+            let mgf = format!("BEGIN IONS\nPEPMASS=471.2\nCHARGE={charge}\nEND IONS\n");
+            let spectrum = MgfReader::new(mgf.as_bytes()).next().unwrap().unwrap();
+            spectrum.precursor.unwrap().charge
+        };
+        assert_eq!(charge("2+"), Some(2));
+        assert_eq!(charge("2"), Some(2));
+        assert_eq!(charge("2-"), Some(-2));
+        assert_eq!(charge("1- and 2-"), Some(-1));
+
+        let spectrum = Spectrum {
+            id: "gm-AEJA".to_owned(),
+            ms_level: 2,
+            retention_time: None,
+            precursor: Some(Precursor {
+                mz: 469.2,
+                charge: Some(-2),
+                intensity: None,
+            }),
+            peaks: Vec::new(),
+        };
+        let mut writer = MgfWriter::new(Vec::new());
+        writer.write(&spectrum).unwrap();
+        let mgf = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(mgf.contains("\nCHARGE=2-\n"));
+
+        let round_tripped: Vec<_> = MgfReader::new(mgf.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(round_tripped, [spectrum]);
+    }
+
+    #[test]
+    fn write_many_spectra() {
+        let spectra: Vec<_> = MgfReader::new(SMALL_MGF).collect::<Result<_>>().unwrap();
+
+        let mut writer = MgfWriter::new(Vec::new());
+        writer.write_all(&spectra).unwrap();
+        let mgf = writer.into_inner();
+
+        let round_tripped: Vec<_> = MgfReader::new(mgf.as_slice())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(round_tripped.len(), 2);
+        assert_eq!(round_tripped[0], spectra[0]);
+        assert_eq!(round_tripped[1].peaks, spectra[1].peaks);
+    }
+
+    #[test]
+    fn write_fragments() {
+        let muropeptide = Muropeptide::new(&POLYMERIZER, "gm-AEJA").unwrap();
+        let precursor = Precursor {
+            mz: 942.4150,
+            charge: Some(1),
+            intensity: None,
+        };
+        let spectrum =
+            Spectrum::theoretical("gm-AEJA", Some(precursor), muropeptide.fragment(&RULES));
+
+        let mut writer = MgfWriter::new(Vec::new());
+        writer.write(&spectrum).unwrap();
+        let mgf = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(mgf.starts_with("BEGIN IONS\nTITLE=gm-AEJA\nPEPMASS=942.415\nCHARGE=1+\n"));
+        // NOTE: The g oxonium ion (168.0655) and the B1 ion (204.0866) are among the peaks written
+        for mz in ["168.0655", "204.0866"] {
+            assert!(mgf
+                .lines()
+                .any(|line| line.starts_with(mz) && line.ends_with(" 1")));
+        }
+
+        let round_tripped: Vec<_> = MgfReader::new(mgf.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(round_tripped, [spectrum]);
+    }
+}
@@ -0,0 +1,416 @@
+//! A streaming reader for (a subset of) the HUPO-PSI mzML format
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flate2::read::ZlibDecoder;
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+
+use crate::{Error, Peak, Precursor, Result, Spectrum};
+
+// NOTE: Controlled-vocabulary accessions from https://github.com/HUPO-PSI/psi-ms-CV
+const MS_LEVEL: &str = "MS:1000511";
+const SCAN_START_TIME: &str = "MS:1000016";
+const SELECTED_ION_MZ: &str = "MS:1000744";
+const CHARGE_STATE: &str = "MS:1000041";
+const PEAK_INTENSITY: &str = "MS:1000042";
+const MZ_ARRAY: &str = "MS:1000514";
+const INTENSITY_ARRAY: &str = "MS:1000515";
+const FLOAT_32: &str = "MS:1000521";
+const FLOAT_64: &str = "MS:1000523";
+const ZLIB_COMPRESSION: &str = "MS:1000574";
+const NO_COMPRESSION: &str = "MS:1000576";
+const SECOND: &str = "UO:0000010";
+
+pub struct MzMLReader<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+}
+
+impl MzMLReader<BufReader<File>> {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> MzMLReader<R> {
+    pub fn new(reader: R) -> Self {
+        let mut reader = Reader::from_reader(reader);
+        reader.config_mut().trim_text(true);
+        Self {
+            reader,
+            buf: Vec::new(),
+        }
+    }
+
+    fn next_spectrum(&mut self) -> Result<Option<Spectrum>> {
+        let mut spectrum: Option<SpectrumBuilder> = None;
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf)? {
+                Event::Start(e) if e.local_name().as_ref() == b"spectrum" => {
+                    let id = attribute(&e, "id")?.unwrap_or_default();
+                    spectrum = Some(SpectrumBuilder::new(id));
+                }
+                Event::End(e) if e.local_name().as_ref() == b"spectrum" => {
+                    if let Some(builder) = spectrum.take() {
+                        return builder.build().map(Some);
+                    }
+                }
+                Event::Start(e) | Event::Empty(e) => {
+                    if let Some(builder) = &mut spectrum {
+                        builder.start_element(&e)?;
+                    }
+                }
+                Event::Text(t) => {
+                    if let Some(BinaryArray { base64, .. }) =
+                        spectrum.as_mut().and_then(|b| b.array.as_mut())
+                    {
+                        base64.push_str(&t.unescape()?);
+                    }
+                }
+                Event::End(e) if e.local_name().as_ref() == b"binaryDataArray" => {
+                    if let Some(builder) = &mut spectrum {
+                        builder.end_array()?;
+                    }
+                }
+                Event::Eof => return Ok(None),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for MzMLReader<R> {
+    type Item = Result<Spectrum>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_spectrum().transpose()
+    }
+}
+
+// Spectrum Building ===================================================================================================
+
+#[derive(Default)]
+struct SpectrumBuilder {
+    id: String,
+    ms_level: Option<u8>,
+    retention_time: Option<f64>,
+    precursor: Option<Precursor>,
+    mzs: Option<Vec<f64>>,
+    intensities: Option<Vec<f64>>,
+    array: Option<BinaryArray>,
+}
+
+#[derive(Default)]
+struct BinaryArray {
+    kind: Option<ArrayKind>,
+    precision: Option<Precision>,
+    compressed: bool,
+    base64: String,
+}
+
+#[derive(Copy, Clone)]
+enum ArrayKind {
+    Mz,
+    Intensity,
+}
+
+#[derive(Copy, Clone)]
+enum Precision {
+    Float32,
+    Float64,
+}
+
+impl SpectrumBuilder {
+    fn new(id: String) -> Self {
+        Self {
+            id,
+            ..Self::default()
+        }
+    }
+
+    fn start_element(&mut self, e: &BytesStart) -> Result<()> {
+        match e.local_name().as_ref() {
+            b"binaryDataArray" => self.array = Some(BinaryArray::default()),
+            b"selectedIon" => {
+                self.precursor = Some(Precursor {
+                    mz: 0.0,
+                    charge: None,
+                    intensity: None,
+                });
+            }
+            b"cvParam" => self.cv_param(e)?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    // NOTE: Every accession used here is only valid in a single part of a `<spectrum>`, so there's no need to keep
+    // track of which element each `<cvParam>` was found in
+    fn cv_param(&mut self, e: &BytesStart) -> Result<()> {
+        let Some(accession) = attribute(e, "accession")? else {
+            return Ok(());
+        };
+        let value = || attribute(e, "value").map(Option::unwrap_or_default);
+
+        if let Some(array) = &mut self.array {
+            match accession.as_str() {
+                MZ_ARRAY => array.kind = Some(ArrayKind::Mz),
+                INTENSITY_ARRAY => array.kind = Some(ArrayKind::Intensity),
+                FLOAT_32 => array.precision = Some(Precision::Float32),
+                FLOAT_64 => array.precision = Some(Precision::Float64),
+                ZLIB_COMPRESSION => array.compressed = true,
+                NO_COMPRESSION => array.compressed = false,
+                _ => (),
+            }
+            return Ok(());
+        }
+
+        match accession.as_str() {
+            MS_LEVEL => self.ms_level = Some(parse_number("MS level", &value()?)?),
+            SCAN_START_TIME => {
+                let time: f64 = parse_number("scan start time", &value()?)?;
+                let unit = attribute(e, "unitAccession")?;
+                // NOTE: Minutes are assumed when no (or an unknown) unit is given
+                self.retention_time = Some(if unit.as_deref() == Some(SECOND) {
+                    time / 60.0
+                } else {
+                    time
+                });
+            }
+            SELECTED_ION_MZ => {
+                if let Some(precursor) = &mut self.precursor {
+                    precursor.mz = parse_number("selected ion m/z", &value()?)?;
+                }
+            }
+            CHARGE_STATE => {
+                if let Some(precursor) = &mut self.precursor {
+                    precursor.charge = Some(parse_number("charge state", &value()?)?);
+                }
+            }
+            PEAK_INTENSITY => {
+                if let Some(precursor) = &mut self.precursor {
+                    precursor.intensity = Some(parse_number("peak intensity", &value()?)?);
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn end_array(&mut self) -> Result<()> {
+        let Some(array) = self.array.take() else {
+            return Ok(());
+        };
+        // NOTE: Arrays other than m/z and intensity (like ion mobility) are skipped
+        let Some(kind) = array.kind else {
+            return Ok(());
+        };
+        let values = array.decode(&self.id)?;
+        match kind {
+            ArrayKind::Mz => self.mzs = Some(values),
+            ArrayKind::Intensity => self.intensities = Some(values),
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<Spectrum> {
+        let mzs = self.mzs.unwrap_or_default();
+        let intensities = self.intensities.unwrap_or_default();
+        if mzs.len() != intensities.len() {
+            return Err(Error::MismatchedArrays {
+                id: self.id,
+                mzs: mzs.len(),
+                intensities: intensities.len(),
+            });
+        }
+
+        let peaks = mzs
+            .into_iter()
+            .zip(intensities)
+            .map(|(mz, intensity)| Peak { mz, intensity })
+            .collect();
+
+        Ok(Spectrum {
+            id: self.id,
+            // NOTE: Spectra without an MS level are assumed to be MS1 spectra
+            ms_level: self.ms_level.unwrap_or(1),
+            retention_time: self.retention_time,
+            precursor: self.precursor,
+            peaks,
+        })
+    }
+}
+
+impl BinaryArray {
+    fn decode(self, id: &str) -> Result<Vec<f64>> {
+        let Some(precision) = self.precision else {
+            return Err(Error::UnsupportedEncoding {
+                id: id.to_owned(),
+                encoding: "precision",
+            });
+        };
+
+        let mut bytes = BASE64.decode(self.base64.trim())?;
+        if self.compressed {
+            let mut decompressed = Vec::new();
+            ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+            bytes = decompressed;
+        }
+
+        let width = match precision {
+            Precision::Float32 => 4,
+            Precision::Float64 => 8,
+        };
+        if bytes.len() % width != 0 {
+            return Err(Error::TruncatedArray {
+                id: id.to_owned(),
+                width,
+            });
+        }
+
+        // SAFETY: The `.chunks_exact()` here guarantees that each slice is of the right length for `.try_into()`
+        let values = bytes.chunks_exact(width).map(|chunk| match precision {
+            Precision::Float32 => f64::from(f32::from_le_bytes(chunk.try_into().unwrap())),
+            Precision::Float64 => f64::from_le_bytes(chunk.try_into().unwrap()),
+        });
+        Ok(values.collect())
+    }
+}
+
+// Attribute Parsing ===================================================================================================
+
+fn attribute(e: &BytesStart, name: &str) -> Result<Option<String>> {
+    e.try_get_attribute(name)?
+        .map(|a| Ok(a.unescape_value()?.into_owned()))
+        .transpose()
+}
+
+fn parse_number<T: std::str::FromStr>(name: &'static str, value: &str) -> Result<T> {
+    value.trim().parse().map_err(|_| Error::InvalidNumber {
+        name,
+        value: value.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL_MZML: &[u8] = include_bytes!("../tests/data/small.mzML");
+
+    fn assert_close(left: f64, right: f64) {
+        assert!((left - right).abs() < 1e-4, "{left} != {right}");
+    }
+
+    #[test]
+    fn read_ms1_spectrum() {
+        let spectrum = MzMLReader::new(SMALL_MZML).next().unwrap().unwrap();
+
+        assert_eq!(spectrum.id, "controllerType=0 controllerNumber=1 scan=1");
+        assert_eq!(spectrum.ms_level, 1);
+        assert_close(spectrum.retention_time.unwrap(), 1.0);
+        assert_eq!(spectrum.precursor, None);
+        assert_eq!(
+            spectrum.peaks,
+            [
+                Peak {
+                    mz: 300.125,
+                    intensity: 1500.0
+                },
+                Peak {
+                    mz: 499.2012,
+                    intensity: 82000.5
+                },
+                Peak {
+                    mz: 997.3951,
+                    intensity: 1200.25
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_compressed_ms2_spectrum() {
+        let spectrum = MzMLReader::new(SMALL_MZML).nth(1).unwrap().unwrap();
+
+        assert_eq!(spectrum.id, "controllerType=0 controllerNumber=1 scan=2");
+        assert_eq!(spectrum.ms_level, 2);
+        assert_close(spectrum.retention_time.unwrap(), 1.05);
+
+        let precursor = spectrum.precursor.unwrap();
+        assert_close(precursor.mz, 499.2012);
+        assert_eq!(precursor.charge, Some(2));
+        assert_close(precursor.intensity.unwrap(), 82000.5);
+
+        let mzs = [168.0655, 204.0867, 498.6938, 699.3142];
+        let intensities = [5000.0, 2500.0, 100.0, 3000.0];
+        assert_eq!(spectrum.peaks.len(), 4);
+        for (peak, (mz, intensity)) in spectrum.peaks.iter().zip(mzs.into_iter().zip(intensities)) {
+            assert_close(peak.mz, mz);
+            assert_close(peak.intensity, intensity);
+        }
+    }
+
+    #[test]
+    fn skip_chromatograms() {
+        let spectra: Result<Vec<_>> = MzMLReader::new(SMALL_MZML).collect();
+        assert_eq!(spectra.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn read_from_path() {
+        let reader = MzMLReader::from_path("tests/data/small.mzML").unwrap();
+        assert_eq!(reader.count(), 2);
+        assert!(matches!(
+            MzMLReader::from_path("tests/data/missing.mzML"),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn mismatched_arrays() {
+        let mzml = String::from_utf8_lossy(SMALL_MZML).replacen(
+            "AAAAAABwl0AAAAAACAX0QAAAAAAAwZJA",
+            "AAAAAABwl0AAAAAACAX0QA==",
+            1,
+        );
+        let error = MzMLReader::new(mzml.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::MismatchedArrays {
+                mzs: 3,
+                intensities: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn invalid_number() {
+        let mzml = String::from_utf8_lossy(SMALL_MZML).replacen(
+            r#"name="ms level" value="1""#,
+            r#"name="ms level" value="one""#,
+            1,
+        );
+        let error = MzMLReader::new(mzml.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidNumber { name: "MS level", value } if value == "one"
+        ));
+    }
+}
@@ -0,0 +1,214 @@
+//! Ranking candidate structures by how well their theoretical fragments explain an observed spectrum
+use std::cmp::Ordering;
+
+use ahash::{HashSet, HashSetExt};
+use polychem::{ChargedParticle, MassTolerance, MonoisotopicMz};
+use smithereens::{Dissociable, FragmentationRules};
+
+use crate::{diagnostic_ions, match_fragments, matching::closest_peak, Peak, PeakMatch};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Score {
+    pub matched_peaks: usize,
+    // NOTE: The summed intensity of every matched peak, divided by the total intensity of the spectrum
+    pub matched_intensity_fraction: f64,
+    // NOTE: The number of distinct m/z values among this candidate's diagnostic ions (see `diagnostic_ions()`)
+    pub diagnostic_ions: usize,
+    pub matched_diagnostic_ions: usize,
+    pub hyperscore: f64,
+}
+
+impl Score {
+    // NOTE: Returns `None` when a candidate has no diagnostic ions, since there's nothing to cover
+    #[must_use]
+    pub fn diagnostic_coverage(&self) -> Option<f64> {
+        #[allow(clippy::cast_precision_loss)]
+        let coverage = self.matched_diagnostic_ions as f64 / self.diagnostic_ions as f64;
+        (self.diagnostic_ions != 0).then_some(coverage)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScoredCandidate<'c, D> {
+    pub candidate: &'c D,
+    pub score: Score,
+    // NOTE: The evidence behind this candidate's score — every fragment that was matched to a peak
+    pub matches: Vec<PeakMatch<D>>,
+}
+
+// NOTE: Candidates are returned from best to worst — sorted by their hyperscores, then by the fraction of the
+// spectrum's intensity they explain, and finally by their coverage of diagnostic ions. Fragments are matched to peaks
+// within the `tolerance`, but are only diagnostic when they're `min_separation` away from the fragments of every other
+// candidate (see `diagnostic_ions()`)
+pub fn rank_candidates<'c, 'a: 'p + 'c, 'p: 'c, D>(
+    candidates: &'c [D],
+    rules: &'c FragmentationRules<'a>,
+    peaks: &[Peak],
+    tolerance: MassTolerance,
+    min_separation: MassTolerance,
+) -> Vec<ScoredCandidate<'c, D>>
+where
+    D: Dissociable<'c, 'a, 'p> + ChargedParticle,
+{
+    let mut peaks = peaks.to_vec();
+    peaks.sort_unstable_by(|a, b| a.mz.total_cmp(&b.mz));
+    let total_intensity: f64 = peaks.iter().map(|p| p.intensity).sum();
+
+    // PERF: Every candidate is fragmented twice — once here, and once more when matching
+    let diagnostic_mzs: Vec<_> = diagnostic_ions(candidates, rules, min_separation)
+        .into_iter()
+        .map(|ions| {
+            let mut mzs: Vec<_> = ions.into_iter().map(|ion| ion.mz).collect();
+            mzs.dedup();
+            mzs
+        })
+        .collect();
+
+    let mut scored: Vec<_> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let matches = match_fragments(candidate, rules, &peaks, tolerance);
+
+            let matched_peaks: HashSet<MonoisotopicMz> =
+                matches.iter().map(|m| m.observed_mz).collect();
+            let mut counted = HashSet::new();
+            let matched_intensity: f64 = matches
+                .iter()
+                .filter(|m| counted.insert(m.observed_mz))
+                .map(|m| m.intensity)
+                .sum();
+
+            let matched_diagnostic_ions = diagnostic_mzs[i]
+                .iter()
+                .filter(|&&mz| closest_peak(&peaks, mz, tolerance).is_some())
+                .count();
+
+            let score = Score {
+                matched_peaks: matched_peaks.len(),
+                matched_intensity_fraction: if total_intensity > 0.0 {
+                    matched_intensity / total_intensity
+                } else {
+                    0.0
+                },
+                diagnostic_ions: diagnostic_mzs[i].len(),
+                matched_diagnostic_ions,
+                hyperscore: hyperscore(matched_peaks.len(), matched_intensity),
+            };
+
+            ScoredCandidate {
+                candidate,
+                score,
+                matches,
+            }
+        })
+        .collect();
+
+    scored.sort_by(|a, b| compare_scores(&b.score, &a.score));
+    scored
+}
+
+// NOTE: Modelled on the X! Tandem hyperscore, but without splitting matches into separate ion series: ln(n! * ΣI)
+#[must_use]
+pub fn hyperscore(matched_peaks: usize, matched_intensity: f64) -> f64 {
+    if matched_peaks == 0 || matched_intensity <= 0.0 {
+        return 0.0;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let ln_factorial: f64 = (2..=matched_peaks).map(|k| (k as f64).ln()).sum();
+    ln_factorial + matched_intensity.ln()
+}
+
+fn compare_scores(a: &Score, b: &Score) -> Ordering {
+    let coverage = |s: &Score| s.diagnostic_coverage().unwrap_or_default();
+    a.hyperscore
+        .total_cmp(&b.hyperscore)
+        .then_with(|| {
+            a.matched_intensity_fraction
+                .total_cmp(&b.matched_intensity_fraction)
+        })
+        .then_with(|| coverage(a).total_cmp(&coverage(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use muropeptide::Muropeptide;
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, PolymerDatabase, Polymerizer};
+    use rust_decimal_macros::dec;
+
+    use crate::Spectrum;
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../../muropeptide/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+    static RULES: Lazy<FragmentationRules> = Lazy::new(|| {
+        FragmentationRules::new(
+            &ATOMIC_DB,
+            &POLYMER_DB,
+            "hcd_rules.kdl",
+            include_str!("../../muropeptide/data/hcd_rules.kdl"),
+        )
+        .unwrap()
+    });
+
+    #[test]
+    fn hyperscores() {
+        assert!(hyperscore(0, 100.0).abs() < f64::EPSILON);
+        assert!(hyperscore(3, 0.0).abs() < f64::EPSILON);
+        assert!((hyperscore(1, 100.0) - 100_f64.ln()).abs() < 1e-12);
+        assert!((hyperscore(3, 100.0) - (6_f64 * 100.0).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn diagnostic_coverage() {
+        let mut score = Score {
+            matched_peaks: 4,
+            matched_intensity_fraction: 0.5,
+            diagnostic_ions: 0,
+            matched_diagnostic_ions: 0,
+            hyperscore: 1.0,
+        };
+        assert_eq!(score.diagnostic_coverage(), None);
+        score.diagnostic_ions = 4;
+        score.matched_diagnostic_ions = 3;
+        assert_eq!(score.diagnostic_coverage(), Some(0.75));
+    }
+
+    #[test]
+    fn rank_isobaric_candidates() {
+        // NOTE: These two stem peptides are isobaric, differing only in the order of their residues
+        let candidates = ["gm-AEAJ", "gm-AEJA"]
+            .map(|structure| Muropeptide::new(&POLYMERIZER, structure).unwrap());
+        let correct = &candidates[1];
+        let spectrum = Spectrum::theoretical("gm-AEJA", None, correct.fragment(&RULES));
+
+        let peaks = &spectrum.peaks;
+        let tolerance = MassTolerance::Ppm(dec!(10));
+        let min_separation = MassTolerance::Da(dec!(0.02));
+        let ranked = rank_candidates(&candidates, &RULES, peaks, tolerance, min_separation);
+        assert_eq!(ranked.len(), 2);
+
+        let (best, worst) = (&ranked[0], &ranked[1]);
+        assert_eq!(best.candidate.to_string(), correct.to_string());
+        assert_eq!(best.score.matched_peaks, peaks.len());
+        assert!((best.score.matched_intensity_fraction - 1.0).abs() < 1e-12);
+        assert_eq!(best.score.diagnostic_coverage(), Some(1.0));
+        assert!(!best.matches.is_empty());
+
+        assert_eq!(worst.candidate.to_string(), candidates[0].to_string());
+        assert!(worst.score.matched_peaks < best.score.matched_peaks);
+        assert!(worst.score.matched_intensity_fraction < 1.0);
+        assert_eq!(worst.score.diagnostic_coverage(), Some(0.0));
+        assert!(worst.score.hyperscore < best.score.hyperscore);
+    }
+}
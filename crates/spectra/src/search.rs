@@ -0,0 +1,180 @@
+//! Searching deconvoluted MS1 features against a library of theoretical structures
+use polychem::{MassTolerance, Massive, MonoisotopicMass};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use crate::matching::ppm_error;
+
+// NOTE: Features have already been deconvoluted, so the charge states they were observed at have been folded into
+// their neutral `mass` — the search never needs to know them
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Feature {
+    // NOTE: The deconvoluted (neutral) monoisotopic mass of the feature, in Da
+    pub mass: f64,
+    // NOTE: Always stored in minutes, just like `Spectrum::retention_time`
+    pub retention_time: Option<f64>,
+    pub intensity: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchHit<'c, D> {
+    pub candidate: &'c D,
+    pub theoretical_mass: MonoisotopicMass,
+    pub ppm_error: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult<'f, 'c, D> {
+    pub feature: &'f Feature,
+    // NOTE: Sorted from the smallest to the largest absolute ppm error
+    pub hits: Vec<SearchHit<'c, D>>,
+}
+
+impl<D> SearchResult<'_, '_, D> {
+    // NOTE: A feature is ambiguous when it matches several library entries (often isobaric structures)
+    #[must_use]
+    pub const fn is_ambiguous(&self) -> bool {
+        self.hits.len() > 1
+    }
+}
+
+// NOTE: The library is expected to contain neutral structures, since their monoisotopic masses are compared directly
+// against the deconvoluted masses of each feature. One result is returned per feature (in the same order as
+// `features`), and features without any hits are kept, so unmatched features can still be reported
+pub fn search_features<'f, 'c, D: Massive>(
+    features: &'f [Feature],
+    library: &'c [D],
+    tolerance: MassTolerance,
+) -> Vec<SearchResult<'f, 'c, D>> {
+    let mut library: Vec<_> = library
+        .iter()
+        .filter_map(|candidate| {
+            let theoretical_mass = candidate.monoisotopic_mass();
+            let mass = Decimal::from(theoretical_mass);
+            Some((mass, mass.to_f64()?, candidate, theoretical_mass))
+        })
+        .collect();
+    library.sort_unstable_by_key(|&(mass, ..)| mass);
+
+    features
+        .iter()
+        .map(|feature| {
+            // NOTE: Features with masses that can't be written as a `Decimal` (like `NaN`) can't match anything
+            let Ok(observed) = Decimal::try_from(feature.mass) else {
+                return SearchResult {
+                    feature,
+                    hits: Vec::new(),
+                };
+            };
+            // NOTE: Tolerances are relative to each theoretical mass (not to the feature's mass), so a generously
+            // widened range of the library is searched first, then each candidate is checked against its own window
+            let window = tolerance.window(observed) * Decimal::TWO;
+            let start = library.partition_point(|&(mass, ..)| mass < observed - window);
+            let mut hits: Vec<_> = library[start..]
+                .iter()
+                .take_while(|&&(mass, ..)| mass <= observed + window)
+                .filter(|&&(mass, ..)| tolerance.contains(mass, observed))
+                .map(|&(_, mass, candidate, theoretical_mass)| SearchHit {
+                    candidate,
+                    theoretical_mass,
+                    ppm_error: ppm_error(mass, feature.mass),
+                })
+                .collect();
+            hits.sort_by(|a, b| a.ppm_error.abs().total_cmp(&b.ppm_error.abs()));
+            SearchResult { feature, hits }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use muropeptide::Muropeptide;
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, PolymerDatabase, Polymerizer};
+    use rust_decimal_macros::dec;
+
+    use super::*;
+
+    static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+    static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
+        PolymerDatabase::new(
+            &ATOMIC_DB,
+            "polymer_database.kdl",
+            include_str!("../../muropeptide/data/polymer_database.kdl"),
+        )
+        .unwrap()
+    });
+    static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
+
+    fn feature(mass: f64, intensity: f64) -> Feature {
+        Feature {
+            mass,
+            retention_time: Some(12.5),
+            intensity,
+        }
+    }
+
+    #[test]
+    fn search_library() {
+        let library = ["gm-AE", "gm-AEJ", "gm-AEJA", "gm-AEAJ", "gm-AEJAA"]
+            .map(|structure| Muropeptide::new(&POLYMERIZER, structure).unwrap());
+        let mass = |i: usize| {
+            Decimal::from(library[i].monoisotopic_mass())
+                .to_f64()
+                .unwrap()
+        };
+
+        let features = [
+            // NOTE: Shifted by +3 ppm from the mass of gm-AEJ
+            feature(mass(1) * 1.000_003, 1000.0),
+            // NOTE: Shifted by -2 ppm from the (shared) mass of gm-AEJA and gm-AEAJ
+            feature(mass(2) * 0.999_998, 500.0),
+            feature(mass(4) + 0.1, 250.0),
+        ];
+        let results = search_features(&features, &library, MassTolerance::Ppm(dec!(10)));
+        assert_eq!(results.len(), 3);
+
+        let aej = &results[0];
+        assert_eq!(aej.feature, &features[0]);
+        assert!(!aej.is_ambiguous());
+        assert_eq!(aej.hits[0].candidate.to_string(), library[1].to_string());
+        assert_eq!(aej.hits[0].theoretical_mass, library[1].monoisotopic_mass());
+        assert!((aej.hits[0].ppm_error - 3.0).abs() < 1e-6);
+
+        let aeja = &results[1];
+        assert!(aeja.is_ambiguous());
+        let mut structures: Vec<_> = aeja
+            .hits
+            .iter()
+            .map(|hit| hit.candidate.to_string())
+            .collect();
+        structures.sort_unstable();
+        let mut expected = [library[2].to_string(), library[3].to_string()];
+        expected.sort_unstable();
+        assert_eq!(structures, expected);
+        assert!(aeja
+            .hits
+            .iter()
+            .all(|hit| (hit.ppm_error + 2.0).abs() < 1e-6));
+
+        let unmatched = &results[2];
+        assert!(unmatched.hits.is_empty());
+        assert!(!unmatched.is_ambiguous());
+
+        // NOTE: Absolute tolerances (in Da) work just as well
+        let results = search_features(&features[2..], &library, MassTolerance::Da(dec!(0.2)));
+        assert_eq!(
+            results[0].hits[0].candidate.to_string(),
+            library[4].to_string()
+        );
+        assert!((results[0].hits[0].ppm_error - 0.1 / mass(4) * 1e6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn search_empty_library() {
+        let library: [Muropeptide; 0] = [];
+        let features = [feature(941.4077, 1.0)];
+        let results = search_features(&features, &library, MassTolerance::Ppm(dec!(10)));
+        assert_eq!(results.len(), 1);
+        assert!(results[0].hits.is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+//! Building `Spectrum`s from theoretical ions, like the fragments produced by `smithereens`
+use crate::{matching::mz_to_f64, Peak, Precursor, Spectrum};
+use polychem::ChargedParticle;
+
+impl Spectrum {
+    // NOTE: Every ion is given the same intensity (1.0), since there's no way to predict relative intensities yet.
+    // Uncharged ions (which have no m/z) are skipped, and ions sharing an m/z are only given a single peak
+    pub fn theoretical(
+        id: impl Into<String>,
+        precursor: Option<Precursor>,
+        ions: impl IntoIterator<Item = impl ChargedParticle>,
+    ) -> Self {
+        let mut peaks: Vec<_> = ions
+            .into_iter()
+            .filter_map(|ion| ion.monoisotopic_mz())
+            .filter_map(mz_to_f64)
+            .map(|mz| Peak { mz, intensity: 1.0 })
+            .collect();
+        peaks.sort_unstable_by(|a, b| a.mz.total_cmp(&b.mz));
+        peaks.dedup_by(|a, b| a.mz == b.mz);
+
+        Self {
+            id: id.into(),
+            ms_level: 2,
+            retention_time: None,
+            precursor,
+            peaks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use once_cell::sync::Lazy;
+    use polychem::{AtomicDatabase, ChemicalComposition};
+
+    use super::*;
+
+    static DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
+
+    #[test]
+    fn theoretical_spectrum() {
+        let ions = [
+            "C8H9NO3+p",
+            "C8H11NO4+p",
+            "C8H9NO3+p",
+            "C6H9NO3",
+            "C8H9NO3+2p",
+        ]
+        .map(|formula| ChemicalComposition::new(&DB, formula).unwrap());
+        let spectrum = Spectrum::theoretical("oxonium", None, ions);
+
+        assert_eq!(spectrum.id, "oxonium");
+        assert_eq!(spectrum.ms_level, 2);
+        assert_eq!(spectrum.precursor, None);
+        // NOTE: The uncharged ion is skipped, and the duplicate ion is only given a single peak
+        let mzs: Vec<_> = spectrum
+            .peaks
+            .iter()
+            .map(|p| format!("{:.4}", p.mz))
+            .collect();
+        assert_eq!(mzs, ["84.5364", "168.0655", "186.0761"]);
+        assert!(spectrum.peaks.iter().all(|p| p.intensity == 1.0));
+    }
+}
@@ -1,6 +1,8 @@
 use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme};
 use once_cell::sync::Lazy;
-use polychem::{AtomicDatabase, Charged, ChargedParticle, ChemicalComposition, Massive, Result};
+use polychem::{
+    AtomicDatabase, Charged, ChargedParticle, ChemicalComposition, Formulaic, Massive, Result,
+};
 use rustyline::DefaultEditor;
 use std::fmt::Write;
 
@@ -24,7 +26,9 @@ fn molecule_info(formula: &str) -> Result<String> {
     let mono_mass = molecule.monoisotopic_mass();
     let avg_mass = molecule.average_mass();
     let charge = molecule.charge();
+    let formula = molecule.formula();
 
+    writeln!(buf, "Formula: {formula}").unwrap();
     writeln!(buf, "Monoisotopic Mass: {mono_mass:.6}").unwrap();
     writeln!(buf, "Average Mass: {avg_mass:.4}").unwrap();
     writeln!(buf, "Charge: {charge}").unwrap();
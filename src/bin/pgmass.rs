@@ -1,9 +1,11 @@
 use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme};
 use muropeptide::{Muropeptide, Result};
 use once_cell::sync::Lazy;
-use polychem::{AtomicDatabase, Charged, ChargedParticle, Massive, PolymerDatabase, Polymerizer};
+use polychem::{
+    AtomicDatabase, Charged, ChargedParticle, Formulaic, Massive, PolymerDatabase, Polymerizer,
+};
 use rustyline::DefaultEditor;
-use smithereens::Dissociable;
+use smithereens::{Dissociable, FragmentationRules};
 use std::fmt::Write;
 
 static ATOMIC_DB: Lazy<AtomicDatabase> = Lazy::new(AtomicDatabase::default);
@@ -18,6 +20,16 @@ static POLYMER_DB: Lazy<PolymerDatabase> = Lazy::new(|| {
 
 static POLYMERIZER: Lazy<Polymerizer> = Lazy::new(|| Polymerizer::new(&ATOMIC_DB, &POLYMER_DB));
 
+static FRAGMENTATION_RULES: Lazy<FragmentationRules> = Lazy::new(|| {
+    FragmentationRules::new(
+        &ATOMIC_DB,
+        &POLYMER_DB,
+        "hcd_rules.kdl",
+        include_str!("../../crates/muropeptide/data/hcd_rules.kdl"),
+    )
+    .unwrap()
+});
+
 // FIXME: This entire binary is just copy-pasted, but needs to be rewritten for PG!
 fn main() {
     let mut rl = DefaultEditor::new().unwrap();
@@ -37,7 +49,9 @@ fn pg_info(formula: &str) -> Result<String> {
     let mono_mass = muropeptide.monoisotopic_mass();
     let avg_mass = muropeptide.average_mass();
     let charge = muropeptide.charge();
+    let formula = muropeptide.formula();
 
+    writeln!(buf, "Formula: {formula}").unwrap();
     writeln!(buf, "Monoisotopic Mass: {mono_mass:.6}").unwrap();
     writeln!(buf, "Average Mass: {avg_mass:.4}").unwrap();
     writeln!(buf, "Charge: {charge}").unwrap();
@@ -54,14 +68,23 @@ fn pg_info(formula: &str) -> Result<String> {
     // FIXME: Remove after debugging is finished!
     writeln!(buf, "Fragments:").unwrap();
     let mut fragments: Vec<_> = muropeptide
-        .fragment(None)
-        .map(|fragment| (fragment.to_string(), fragment.monoisotopic_mz().unwrap()))
+        .fragment(&FRAGMENTATION_RULES)
+        .filter_map(|fragment| {
+            let mz = fragment.monoisotopic_mz()?;
+            let label = fragment.label()?.to_string();
+            Some((fragment.to_string(), label, fragment.charge(), mz))
+        })
         .collect();
-    fragments
-        .sort_unstable_by(|(s1, mz1), (s2, mz2)| mz1.cmp(mz2).then_with(|| s1.cmp(s2)).reverse());
+    fragments.sort_unstable_by(|(s1, l1, z1, mz1), (s2, l2, z2, mz2)| {
+        mz1.cmp(mz2)
+            .then_with(|| s1.cmp(s2))
+            .then_with(|| l1.cmp(l2))
+            .then_with(|| z1.cmp(z2))
+            .reverse()
+    });
     fragments.dedup();
-    for (structure, mz) in fragments {
-        writeln!(buf, r#""{structure}",{mz:.6}"#).unwrap();
+    for (structure, label, charge, mz) in fragments {
+        writeln!(buf, r#""{structure}","{label}",{charge},{mz:.6}"#).unwrap();
     }
 
     Ok(buf)